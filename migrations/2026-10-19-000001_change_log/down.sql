@@ -0,0 +1,8 @@
+DROP TABLE changes CASCADE;
+
+ALTER TABLE decks DROP COLUMN updated_at;
+ALTER TABLE backs DROP COLUMN updated_at;
+ALTER TABLE cards DROP COLUMN updated_at;
+ALTER TABLE scores DROP COLUMN updated_at;
+ALTER TABLE sets DROP COLUMN updated_at;
+ALTER TABLE set_cards DROP COLUMN updated_at;
@@ -0,0 +1,24 @@
+ALTER TABLE decks ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE backs ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE cards ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE scores ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sets ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE set_cards ADD COLUMN updated_at BIGINT NOT NULL DEFAULT 0;
+
+UPDATE cards SET updated_at = created_at;
+UPDATE scores SET updated_at = created_at;
+UPDATE sets SET updated_at = created_at;
+UPDATE backs SET updated_at = cards.created_at FROM cards WHERE cards.back = backs.id;
+UPDATE set_cards SET updated_at = sets.created_at FROM sets WHERE sets.id = set_cards.set_id;
+UPDATE decks SET updated_at = COALESCE((SELECT MAX(created_at) FROM cards WHERE cards.deck = decks.id), 0);
+
+CREATE TABLE changes (
+  id BIGSERIAL PRIMARY KEY,
+  owner INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  entity SMALLINT NOT NULL,
+  entity_id INT NOT NULL,
+  kind SMALLINT NOT NULL,
+  created_at BIGINT NOT NULL
+);
+
+CREATE INDEX changes_owner_id_idx ON changes (owner, id);
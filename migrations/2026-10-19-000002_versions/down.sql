@@ -0,0 +1,3 @@
+ALTER TABLE decks DROP COLUMN version;
+ALTER TABLE cards DROP COLUMN version;
+ALTER TABLE sets DROP COLUMN version;
@@ -0,0 +1,3 @@
+ALTER TABLE decks ADD COLUMN version INT NOT NULL DEFAULT 1;
+ALTER TABLE cards ADD COLUMN version INT NOT NULL DEFAULT 1;
+ALTER TABLE sets ADD COLUMN version INT NOT NULL DEFAULT 1;
@@ -0,0 +1,3 @@
+ALTER TABLE decks DROP COLUMN deleted_at;
+ALTER TABLE cards DROP COLUMN deleted_at;
+ALTER TABLE sets DROP COLUMN deleted_at;
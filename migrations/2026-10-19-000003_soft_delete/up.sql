@@ -0,0 +1,3 @@
+ALTER TABLE decks ADD COLUMN deleted_at BIGINT;
+ALTER TABLE cards ADD COLUMN deleted_at BIGINT;
+ALTER TABLE sets ADD COLUMN deleted_at BIGINT;
@@ -0,0 +1,15 @@
+-- No foreign keys: the log has to outlive the users and rows it describes
+CREATE TABLE audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  actor INT,
+  owner INT NOT NULL,
+  entity SMALLINT NOT NULL,
+  entity_id INT NOT NULL,
+  operation SMALLINT NOT NULL,
+  before TEXT,
+  after TEXT,
+  created_at BIGINT NOT NULL
+);
+
+CREATE INDEX audit_log_owner_id_idx ON audit_log (owner, id);
+CREATE INDEX audit_log_entity_idx ON audit_log (entity, entity_id);
@@ -0,0 +1,2 @@
+DROP TABLE deck_members;
+ALTER TABLE scores DROP COLUMN owner;
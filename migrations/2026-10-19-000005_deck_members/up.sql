@@ -0,0 +1,15 @@
+ALTER TABLE scores ADD COLUMN owner INT REFERENCES users(id) ON DELETE CASCADE;
+UPDATE scores SET owner = decks.owner FROM cards, decks WHERE cards.id = scores.card AND decks.id = cards.deck;
+ALTER TABLE scores ALTER COLUMN owner SET NOT NULL;
+
+CREATE TABLE deck_members (
+  id SERIAL PRIMARY KEY,
+  deck INT NOT NULL REFERENCES decks(id) ON DELETE CASCADE,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  role SMALLINT NOT NULL,
+  accepted BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at BIGINT NOT NULL,
+  UNIQUE (deck, user_id)
+);
+
+CREATE INDEX deck_members_user_id_idx ON deck_members (user_id);
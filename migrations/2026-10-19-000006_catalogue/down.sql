@@ -0,0 +1,4 @@
+ALTER TABLE decks DROP COLUMN source;
+ALTER TABLE decks DROP COLUMN published;
+ALTER TABLE decks DROP COLUMN licence;
+ALTER TABLE decks DROP COLUMN description;
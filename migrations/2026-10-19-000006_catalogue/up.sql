@@ -0,0 +1,7 @@
+ALTER TABLE decks ADD COLUMN description TEXT;
+ALTER TABLE decks ADD COLUMN licence TEXT;
+ALTER TABLE decks ADD COLUMN published BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE decks ADD COLUMN source INT REFERENCES decks(id) ON DELETE SET NULL;
+
+CREATE INDEX decks_published_idx ON decks (published) WHERE published;
+CREATE INDEX decks_source_idx ON decks (source);
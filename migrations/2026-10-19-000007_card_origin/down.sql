@@ -0,0 +1,3 @@
+ALTER TABLE cards DROP COLUMN merged_version;
+ALTER TABLE cards DROP COLUMN origin_version;
+ALTER TABLE cards DROP COLUMN origin;
@@ -0,0 +1,6 @@
+-- No foreign key: a purged upstream card should still show up as removed
+ALTER TABLE cards ADD COLUMN origin INT;
+ALTER TABLE cards ADD COLUMN origin_version INT;
+ALTER TABLE cards ADD COLUMN merged_version INT;
+
+CREATE INDEX cards_origin_idx ON cards (origin);
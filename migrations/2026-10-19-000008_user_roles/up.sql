@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN role SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN password_reset BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+DROP INDEX backs_language_text;
+ALTER TABLE backs DROP COLUMN image_media;
+ALTER TABLE backs DROP COLUMN audio_media;
+DROP TABLE media;
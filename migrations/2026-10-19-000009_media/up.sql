@@ -0,0 +1,16 @@
+CREATE TABLE media (
+  id SERIAL PRIMARY KEY,
+  hash VARCHAR (64) UNIQUE NOT NULL,
+  mime VARCHAR (255) NOT NULL,
+  size BIGINT NOT NULL,
+  source TEXT,
+  licence TEXT,
+  created_at BIGINT NOT NULL
+);
+
+ALTER TABLE backs ADD COLUMN audio_media INT REFERENCES media(id) ON DELETE SET NULL;
+ALTER TABLE backs ADD COLUMN image_media INT REFERENCES media(id) ON DELETE SET NULL;
+
+CREATE INDEX backs_audio_media ON backs (audio_media);
+CREATE INDEX backs_image_media ON backs (image_media);
+CREATE INDEX backs_language_text ON backs (language, text);
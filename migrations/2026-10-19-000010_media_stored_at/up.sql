@@ -0,0 +1 @@
+ALTER TABLE media ADD COLUMN stored_at BIGINT NOT NULL DEFAULT 0;
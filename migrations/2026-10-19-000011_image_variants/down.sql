@@ -0,0 +1,3 @@
+ALTER TABLE backs DROP COLUMN image_card;
+ALTER TABLE backs DROP COLUMN image_thumbnail;
+DROP TABLE media_variants;
@@ -0,0 +1,11 @@
+CREATE TABLE media_variants (
+  media_id INT NOT NULL REFERENCES media(id) ON DELETE CASCADE,
+  variant SMALLINT NOT NULL,
+  variant_id INT NOT NULL REFERENCES media(id) ON DELETE CASCADE,
+  PRIMARY KEY (media_id, variant)
+);
+
+CREATE INDEX media_variants_variant_id ON media_variants (variant_id);
+
+ALTER TABLE backs ADD COLUMN image_thumbnail TEXT;
+ALTER TABLE backs ADD COLUMN image_card TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE cards DROP COLUMN front_audio_media;
+ALTER TABLE cards DROP COLUMN front_audio;
+ALTER TABLE cards DROP COLUMN front_language;
+ALTER TABLE decks DROP COLUMN front_language;
+ALTER TABLE users DROP COLUMN native_language;
@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN native_language INT REFERENCES languages(id);
+
+-- The language fronts are written in, for decks pairing two languages
+ALTER TABLE decks ADD COLUMN front_language INT REFERENCES languages(id);
+
+ALTER TABLE cards ADD COLUMN front_language INT REFERENCES languages(id);
+ALTER TABLE cards ADD COLUMN front_audio TEXT;
+ALTER TABLE cards ADD COLUMN front_audio_media INT REFERENCES media(id) ON DELETE SET NULL;
+
+CREATE INDEX cards_front_audio_media ON cards (front_audio_media);
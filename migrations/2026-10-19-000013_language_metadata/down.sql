@@ -0,0 +1,5 @@
+ALTER TABLE languages DROP COLUMN disabled;
+ALTER TABLE languages DROP COLUMN tts_voice;
+ALTER TABLE languages DROP COLUMN direction;
+ALTER TABLE languages DROP COLUMN script;
+ALTER TABLE languages DROP COLUMN tag;
@@ -0,0 +1,49 @@
+-- BCP-47 tag, e.g. for the lang attribute of text in the language
+ALTER TABLE languages ADD COLUMN tag VARCHAR (50);
+UPDATE languages SET tag = CASE
+  WHEN abbreviation = 'jw' THEN 'jv'
+  WHEN abbreviation = 'en-uk' THEN 'en-GB'
+  WHEN abbreviation LIKE '%-%' THEN split_part(abbreviation, '-', 1) || '-' || UPPER(split_part(abbreviation, '-', 2))
+  ELSE abbreviation
+END;
+ALTER TABLE languages ALTER COLUMN tag SET NOT NULL;
+
+-- ISO 15924 script code
+ALTER TABLE languages ADD COLUMN script VARCHAR (4);
+UPDATE languages SET script = CASE abbreviation
+  WHEN 'ar' THEN 'Arab'
+  WHEN 'ur' THEN 'Arab'
+  WHEN 'bn' THEN 'Beng'
+  WHEN 'el' THEN 'Grek'
+  WHEN 'gu' THEN 'Gujr'
+  WHEN 'hi' THEN 'Deva'
+  WHEN 'mr' THEN 'Deva'
+  WHEN 'ne' THEN 'Deva'
+  WHEN 'hy' THEN 'Armn'
+  WHEN 'ja' THEN 'Jpan'
+  WHEN 'km' THEN 'Khmr'
+  WHEN 'kn' THEN 'Knda'
+  WHEN 'ko' THEN 'Kore'
+  WHEN 'mk' THEN 'Cyrl'
+  WHEN 'ru' THEN 'Cyrl'
+  WHEN 'sr' THEN 'Cyrl'
+  WHEN 'uk' THEN 'Cyrl'
+  WHEN 'ml' THEN 'Mlym'
+  WHEN 'my' THEN 'Mymr'
+  WHEN 'si' THEN 'Sinh'
+  WHEN 'ta' THEN 'Taml'
+  WHEN 'te' THEN 'Telu'
+  WHEN 'th' THEN 'Thai'
+  WHEN 'zh-cn' THEN 'Hans'
+  WHEN 'zh-tw' THEN 'Hant'
+  ELSE 'Latn'
+END;
+
+-- 0 is left to right, 1 right to left
+ALTER TABLE languages ADD COLUMN direction SMALLINT NOT NULL DEFAULT 0;
+UPDATE languages SET direction = 1 WHERE abbreviation IN ('ar', 'ur');
+
+ALTER TABLE languages ADD COLUMN tts_voice VARCHAR (255);
+
+-- Disabled languages can't be picked for new decks, existing decks keep them
+ALTER TABLE languages ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
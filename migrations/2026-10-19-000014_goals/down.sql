@@ -0,0 +1,4 @@
+DROP TABLE achievements;
+ALTER TABLE users DROP COLUMN daily_new_card_goal;
+ALTER TABLE users DROP COLUMN daily_review_goal;
+ALTER TABLE users DROP COLUMN timezone;
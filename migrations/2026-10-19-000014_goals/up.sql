@@ -0,0 +1,12 @@
+-- IANA time zone days are counted in, for streaks and daily goals
+ALTER TABLE users ADD COLUMN timezone VARCHAR (64) NOT NULL DEFAULT 'UTC';
+ALTER TABLE users ADD COLUMN daily_review_goal INT;
+ALTER TABLE users ADD COLUMN daily_new_card_goal INT;
+
+CREATE TABLE achievements (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  kind SMALLINT NOT NULL,
+  unlocked_at BIGINT NOT NULL,
+  UNIQUE (user_id, kind)
+);
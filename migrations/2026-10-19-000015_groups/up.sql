@@ -0,0 +1,31 @@
+-- Classes, run by teachers and studied by students
+CREATE TABLE groups (
+  id SERIAL PRIMARY KEY,
+  name VARCHAR (255) NOT NULL,
+  owner INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  created_at BIGINT NOT NULL
+);
+
+CREATE TABLE group_members (
+  id SERIAL PRIMARY KEY,
+  group_id INT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  user_id INT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  -- 0 is a student, 1 a teacher
+  role SMALLINT NOT NULL,
+  accepted BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at BIGINT NOT NULL,
+  UNIQUE (group_id, user_id)
+);
+
+CREATE INDEX group_members_user_id_idx ON group_members (user_id);
+
+-- Decks, or sets of them, the students of a group study
+CREATE TABLE group_assignments (
+  id SERIAL PRIMARY KEY,
+  group_id INT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+  deck INT NOT NULL REFERENCES decks(id) ON DELETE CASCADE,
+  set_id INT REFERENCES sets(id) ON DELETE CASCADE,
+  assigned_at BIGINT NOT NULL
+);
+
+CREATE INDEX group_assignments_deck_idx ON group_assignments (deck);
@@ -0,0 +1,3 @@
+-- Set when applying updates trashed a copy because its upstream card was
+-- removed, so the copy is offered back if the card returns upstream
+ALTER TABLE cards ADD COLUMN removed_upstream BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,351 @@
+use diesel::prelude::*;
+use std::{
+  fs,
+  path::Path,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  config::{safe_join, MediaConfig},
+  db::{
+    audit::Audit,
+    changes::{self, ChangeKind},
+    models::{CardRow, DeckRow, SetRow},
+    schema::{backs, cards, decks, languages, set_cards, sets, users},
+    DBConnection,
+  },
+  graphql::mutations::utilities,
+  media::{self, ImageUrls, NewMedia},
+  TRCError,
+};
+
+/// A deck with its cards and the owner's sets, as written by `deck export`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeckExport {
+  pub name: String,
+  pub description: Option<String>,
+  pub licence: Option<String>,
+  /// Language abbreviation, so the file doesn't depend on row ids
+  pub language: String,
+  #[serde(default)]
+  pub front_language: Option<String>,
+  pub cards: Vec<CardExport>,
+  pub sets: Vec<SetExport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CardExport {
+  pub front: String,
+  pub back: String,
+  pub link: Option<String>,
+  /// Media paths relative to the media root, or URLs of media kept
+  /// elsewhere
+  pub audio: Option<String>,
+  pub image: Option<String>,
+  #[serde(default)]
+  pub front_audio: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetExport {
+  pub name: String,
+  /// Positions in `DeckExport::cards`
+  pub cards: Vec<usize>,
+}
+
+/// Exports a deck, leaving out anything in the trash
+pub fn export(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  deck: i32,
+) -> Result<DeckExport, TRCError> {
+  let (row, language) = decks::table
+    .inner_join(languages::table)
+    .select((DeckRow::COLUMNS, languages::abbreviation))
+    .filter(decks::id.eq(deck))
+    .filter(decks::deleted_at.is_null())
+    .get_result::<(DeckRow, String)>(conn)?;
+  let front_language = languages::table
+    .select(languages::abbreviation)
+    .filter(languages::id.nullable().eq(row.front_language))
+    .get_result::<String>(conn)
+    .optional()?;
+
+  let card_rows = cards::table
+    .inner_join(backs::table)
+    .select((
+      cards::id,
+      cards::front,
+      backs::text,
+      cards::link,
+      backs::audio,
+      backs::image,
+      cards::front_audio,
+    ))
+    .filter(cards::deck.eq(deck))
+    .filter(cards::deleted_at.is_null())
+    .order(cards::id.asc())
+    .load::<(
+      i32,
+      String,
+      String,
+      Option<String>,
+      Option<String>,
+      Option<String>,
+      Option<String>,
+    )>(conn)?;
+  let card_ids = card_rows.iter().map(|card| card.0).collect::<Vec<_>>();
+
+  let mut exported_sets = vec![];
+  for (set_id, name) in sets::table
+    .select((sets::id, sets::name))
+    .filter(sets::deck.eq(deck))
+    .filter(sets::owner.eq(row.owner))
+    .filter(sets::deleted_at.is_null())
+    .order(sets::id.asc())
+    .load::<(i32, String)>(conn)?
+  {
+    let members = set_cards::table
+      .select(set_cards::card_id)
+      .filter(set_cards::set_id.eq(set_id))
+      .load::<i32>(conn)?;
+    exported_sets.push(SetExport {
+      name,
+      cards: members
+        .iter()
+        .filter_map(|card| card_ids.iter().position(|id| id == card))
+        .collect(),
+    });
+  }
+
+  Ok(DeckExport {
+    name: row.name,
+    description: row.description,
+    licence: row.licence,
+    language,
+    front_language,
+    cards: card_rows
+      .into_iter()
+      .map(
+        |(_, front, back, link, audio, image, front_audio)| CardExport {
+          front,
+          back,
+          link,
+          audio: audio.map(|url| relative_path(config, url)),
+          image: image.map(|url| relative_path(config, url)),
+          front_audio: front_audio.map(|url| relative_path(config, url)),
+        },
+      )
+      .collect(),
+    sets: exported_sets,
+  })
+}
+
+/// Where the file behind a media URL lives, relative to the media root, so
+/// that the export doesn't depend on the server's base URL
+fn relative_path(config: &MediaConfig, url: String) -> String {
+  config
+    .path(&url)
+    .and_then(|path| {
+      path
+        .strip_prefix(&config.root)
+        .ok()
+        .and_then(|path| path.to_str())
+        .map(String::from)
+    })
+    .unwrap_or(url)
+}
+
+/// Reads a file named in an export from `dir`. URLs of media kept elsewhere
+/// give `None`.
+fn exported_file(dir: &Path, path: &str) -> Result<Option<NewMedia>, TRCError> {
+  if path.contains("://") {
+    return Ok(None);
+  }
+  let file = safe_join(dir, path)
+    .ok_or_else(|| TRCError::Unknown(format!("Invalid media path {}", path)))?;
+  let mime = file
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .and_then(media::mime_for_extension)
+    .ok_or_else(|| TRCError::Unknown(format!("Unknown type of media {}", path)))?;
+  let bytes = fs::read(&file)
+    .map_err(|err| TRCError::Unknown(format!("Failed to read {}: {}", file.display(), err)))?;
+  Ok(Some(NewMedia {
+    bytes,
+    mime: mime.to_owned(),
+    source: None,
+    licence: None,
+  }))
+}
+
+/// Stores an exported audio file, returning its URL and media id
+fn import_audio(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  dir: &Path,
+  path: Option<String>,
+  time: i64,
+) -> Result<(Option<String>, Option<i32>), TRCError> {
+  let path = match path {
+    Some(path) => path,
+    None => return Ok((None, None)),
+  };
+  match exported_file(dir, &path)? {
+    Some(new) => {
+      let stored = media::store(conn, config, new, time)?;
+      Ok((Some(config.url(&stored.path())), Some(stored.id)))
+    }
+    None => Ok((Some(path), None)),
+  }
+}
+
+/// Stores an exported image along with its variants
+fn import_image(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  dir: &Path,
+  path: Option<String>,
+  time: i64,
+) -> Result<ImageUrls, TRCError> {
+  let path = match path {
+    Some(path) => path,
+    None => return Ok(ImageUrls::default()),
+  };
+  match exported_file(dir, &path)? {
+    Some(new) => Ok(media::store_image(conn, config, new, time)?.urls(config)),
+    None => Ok(ImageUrls {
+      image: Some(path),
+      ..ImageUrls::default()
+    }),
+  }
+}
+
+/// Imports an exported deck into a user's account and returns its id. Media
+/// files are read from `media_dir`, the media root of the exporting server or
+/// a copy of it, and stored like uploads.
+pub fn import(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  media_dir: &Path,
+  username: &str,
+  deck: DeckExport,
+) -> Result<i32, TRCError> {
+  conn.transaction(|| {
+    let owner = users::table
+      .select(users::id)
+      .filter(users::username.eq(username))
+      .get_result::<i32>(conn)
+      .optional()?
+      .ok_or_else(|| TRCError::Unknown(format!("No user named {}", username)))?;
+    let language_id = |abbreviation: &str| -> Result<i32, TRCError> {
+      languages::table
+        .select(languages::id)
+        .filter(languages::abbreviation.eq(abbreviation))
+        .get_result::<i32>(conn)
+        .optional()?
+        .ok_or_else(|| TRCError::Unknown(format!("Unknown language {}", abbreviation)))
+    };
+    let language = language_id(&deck.language)?;
+    let front_language = match &deck.front_language {
+      Some(abbreviation) => Some(language_id(abbreviation)?),
+      None => None,
+    };
+    utilities::check_language(conn, language)?;
+    if let Some(language) = front_language {
+      utilities::check_language(conn, language)?;
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let imported = diesel::insert_into(decks::table)
+      .values((
+        decks::name.eq(deck.name),
+        decks::owner.eq(owner),
+        decks::language.eq(language),
+        decks::description.eq(deck.description),
+        decks::licence.eq(deck.licence),
+        decks::front_language.eq(front_language),
+        decks::updated_at.eq(time),
+      ))
+      .returning(decks::id)
+      .get_result::<i32>(conn)?;
+
+    let card_language = utilities::front_language(conn, imported)?;
+    let mut card_ids = vec![];
+    for card in deck.cards {
+      let (audio, audio_media) = import_audio(conn, config, media_dir, card.audio, time)?;
+      let image = import_image(conn, config, media_dir, card.image, time)?;
+      let (front_audio, front_audio_media) =
+        import_audio(conn, config, media_dir, card.front_audio, time)?;
+      let back = diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq(card.back),
+          backs::language.eq(language),
+          backs::audio.eq(audio),
+          backs::audio_media.eq(audio_media),
+          backs::image.eq(image.image),
+          backs::image_media.eq(image.image_media),
+          backs::image_thumbnail.eq(image.thumbnail),
+          backs::image_card.eq(image.card),
+          backs::updated_at.eq(time),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(conn)?;
+      card_ids.push(
+        diesel::insert_into(cards::table)
+          .values((
+            cards::front.eq(card.front),
+            cards::back.eq(back),
+            cards::deck.eq(imported),
+            cards::link.eq(card.link),
+            cards::front_language.eq(card_language),
+            cards::front_audio.eq(front_audio),
+            cards::front_audio_media.eq(front_audio_media),
+            cards::created_at.eq(time),
+            cards::updated_at.eq(time),
+          ))
+          .returning(cards::id)
+          .get_result::<i32>(conn)?,
+      );
+    }
+
+    let mut set_ids = vec![];
+    for set in deck.sets {
+      let set_id = diesel::insert_into(sets::table)
+        .values((
+          sets::name.eq(set.name),
+          sets::deck.eq(imported),
+          sets::owner.eq(owner),
+          sets::created_at.eq(time),
+          sets::updated_at.eq(time),
+        ))
+        .returning(sets::id)
+        .get_result::<i32>(conn)?;
+      diesel::insert_into(set_cards::table)
+        .values(
+          set
+            .cards
+            .iter()
+            .filter_map(|position| card_ids.get(*position))
+            .map(|card| {
+              (
+                set_cards::card_id.eq(card),
+                set_cards::set_id.eq(set_id),
+                set_cards::updated_at.eq(time),
+              )
+            })
+            .collect::<Vec<_>>(),
+        )
+        .execute(conn)?;
+      set_ids.push(set_id);
+    }
+
+    changes::record_deck(conn, owner, imported, ChangeKind::Upsert, time)?;
+    let audit = Audit::new(None, owner, time);
+    audit.inserted::<DeckRow>(conn, &[imported])?;
+    audit.inserted::<CardRow>(conn, &card_ids)?;
+    audit.inserted::<SetRow>(conn, &set_ids)?;
+
+    Ok(imported)
+  })
+}
@@ -0,0 +1,159 @@
+use diesel::prelude::*;
+use std::{
+  fs,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  config::MediaConfig,
+  db::{
+    changes::{self, ChangeKind, Entity},
+    schema::{backs, cards, decks},
+    DBConnection,
+  },
+  media::{self, ImageUrls, MediaRow, NewMedia},
+  TRCError,
+};
+
+/// Reads a file kept under its old name. Files that are gone or of an
+/// unknown type are left alone.
+fn legacy_file(config: &MediaConfig, url: &str) -> Result<Option<NewMedia>, TRCError> {
+  let path = match config.path(url) {
+    Some(path) if path.is_file() => path,
+    _ => return Ok(None),
+  };
+  let mime = match path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .and_then(media::mime_for_extension)
+  {
+    Some(mime) => mime.to_owned(),
+    None => return Ok(None),
+  };
+  Ok(Some(NewMedia {
+    bytes: fs::read(&path)?,
+    mime,
+    source: None,
+    licence: None,
+  }))
+}
+
+/// Runs an image through `media::store_image`. Images that can't be decoded
+/// are left as they are.
+fn adopt_image(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  new: NewMedia,
+  time: i64,
+) -> Result<Option<ImageUrls>, TRCError> {
+  match media::store_image(conn, config, new, time) {
+    Ok(stored) => Ok(Some(stored.urls(config))),
+    Err(TRCError::Media(_)) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+/// Moves media stored by file name, before media was content addressed, into
+/// the media table and points backs at the new files. The old files are left
+/// for `gc` to remove. Images stored before variants were made are converted
+/// as well. Returns how many backs were updated.
+pub fn rehash(conn: &DBConnection, config: &MediaConfig) -> Result<usize, TRCError> {
+  let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+  let legacy = backs::table
+    .select((
+      backs::id,
+      backs::audio,
+      backs::image,
+      backs::audio_media,
+      backs::image_media,
+      backs::image_thumbnail,
+    ))
+    .filter(
+      backs::audio_media
+        .is_null()
+        .or(backs::image_media.is_null())
+        .or(backs::image_thumbnail.is_null()),
+    )
+    .load::<(
+      i32,
+      Option<String>,
+      Option<String>,
+      Option<i32>,
+      Option<i32>,
+      Option<String>,
+    )>(conn)?;
+
+  let mut updated = 0;
+  for (id, audio, image, audio_media, image_media, thumbnail) in legacy {
+    conn.transaction::<_, TRCError, _>(|| {
+      let audio = match (audio, audio_media) {
+        (Some(url), None) => match legacy_file(config, &url)? {
+          Some(new) => {
+            let stored = media::store(conn, config, new, time)?;
+            Some((stored.id, config.url(&stored.path())))
+          }
+          None => None,
+        },
+        _ => None,
+      };
+      let image = match (image, image_media, thumbnail) {
+        (Some(url), None, _) => match legacy_file(config, &url)? {
+          Some(new) => adopt_image(conn, config, new, time)?,
+          None => None,
+        },
+        (_, Some(media), None) => {
+          let row = MediaRow::find(conn, media)?;
+          match fs::read(config.root.join(row.path())) {
+            Ok(bytes) => {
+              let new = NewMedia {
+                bytes,
+                mime: row.mime,
+                source: row.source,
+                licence: row.licence,
+              };
+              adopt_image(conn, config, new, time)?
+            }
+            Err(_) => None,
+          }
+        }
+        _ => None,
+      };
+      if audio.is_none() && image.is_none() {
+        return Ok(());
+      }
+
+      if let Some((media, url)) = audio {
+        diesel::update(backs::table.filter(backs::id.eq(id)))
+          .set((backs::audio.eq(url), backs::audio_media.eq(media)))
+          .execute(conn)?;
+      }
+      if let Some(urls) = image {
+        diesel::update(backs::table.filter(backs::id.eq(id)))
+          .set((
+            backs::image.eq(urls.image),
+            backs::image_media.eq(urls.image_media),
+            backs::image_thumbnail.eq(urls.thumbnail),
+            backs::image_card.eq(urls.card),
+          ))
+          .execute(conn)?;
+      }
+      diesel::update(backs::table.filter(backs::id.eq(id)))
+        .set(backs::updated_at.eq(time))
+        .execute(conn)?;
+
+      // Let synced clients pick up the new URLs
+      let owners = cards::table
+        .inner_join(decks::table)
+        .select(decks::owner)
+        .filter(cards::back.eq(id))
+        .distinct()
+        .load::<i32>(conn)?;
+      for owner in owners {
+        changes::record(conn, owner, Entity::Back, &[id], ChangeKind::Upsert, time)?;
+      }
+      updated += 1;
+      Ok(())
+    })?;
+  }
+  Ok(updated)
+}
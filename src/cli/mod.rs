@@ -0,0 +1,6 @@
+//! Operations behind the admin subcommands of the `total_recall` binary
+
+pub mod deck;
+pub mod media;
+pub mod stats;
+pub mod user;
@@ -0,0 +1,90 @@
+use diesel::{dsl::count_star, prelude::*};
+use std::{fmt, path::Path};
+
+use crate::{
+  db::{
+    schema::{cards, decks, scores, sets, users},
+    DBConnection,
+  },
+  graphql::authorization::UserRole,
+  media, TRCError,
+};
+
+/// Row counts and media usage for the `stats` subcommand
+#[derive(Debug)]
+pub struct Stats {
+  pub users: i64,
+  pub admins: i64,
+  pub decks: i64,
+  pub published_decks: i64,
+  pub cards: i64,
+  pub sets: i64,
+  pub scores: i64,
+  /// Decks, cards and sets waiting in the trash
+  pub trashed: i64,
+  pub media_files: usize,
+  pub media_bytes: u64,
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "users:     {} ({} admins)", self.users, self.admins)?;
+    writeln!(
+      f,
+      "decks:     {} ({} published)",
+      self.decks, self.published_decks
+    )?;
+    writeln!(f, "cards:     {}", self.cards)?;
+    writeln!(f, "sets:      {}", self.sets)?;
+    writeln!(f, "scores:    {}", self.scores)?;
+    writeln!(f, "trashed:   {}", self.trashed)?;
+    write!(
+      f,
+      "media:     {} files, {} bytes",
+      self.media_files, self.media_bytes
+    )
+  }
+}
+
+pub fn collect(conn: &DBConnection, media_root: &Path) -> Result<Stats, TRCError> {
+  let media = media::files(media_root)?;
+  Ok(Stats {
+    users: users::table.select(count_star()).get_result(conn)?,
+    admins: users::table
+      .select(count_star())
+      .filter(users::role.eq(UserRole::ADMIN))
+      .get_result(conn)?,
+    decks: decks::table
+      .select(count_star())
+      .filter(decks::deleted_at.is_null())
+      .get_result(conn)?,
+    published_decks: decks::table
+      .select(count_star())
+      .filter(decks::deleted_at.is_null())
+      .filter(decks::published)
+      .get_result(conn)?,
+    cards: cards::table
+      .select(count_star())
+      .filter(cards::deleted_at.is_null())
+      .get_result(conn)?,
+    sets: sets::table
+      .select(count_star())
+      .filter(sets::deleted_at.is_null())
+      .get_result(conn)?,
+    scores: scores::table.select(count_star()).get_result(conn)?,
+    trashed: decks::table
+      .select(count_star())
+      .filter(decks::deleted_at.is_not_null())
+      .get_result::<i64>(conn)?
+      + cards::table
+        .select(count_star())
+        .filter(cards::deleted_at.is_not_null())
+        .get_result::<i64>(conn)?
+      + sets::table
+        .select(count_star())
+        .filter(sets::deleted_at.is_not_null())
+        .get_result::<i64>(conn)?,
+    media_files: media.len(),
+    media_bytes: media.iter().map(|(_, size)| size).sum(),
+  })
+}
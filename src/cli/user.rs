@@ -0,0 +1,111 @@
+use bcrypt::hash;
+use diesel::prelude::*;
+use std::{
+  io::{self, BufRead, IsTerminal},
+  process::Command,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  db::{
+    audit::{Audit, Audited},
+    models::UserRow,
+    schema::users,
+    DBConnection,
+  },
+  graphql::authorization::UserRole,
+  TRCError,
+};
+
+fn hash_password(password: &str) -> Result<String, TRCError> {
+  hash(password, 10).map_err(|err| TRCError::Unknown(err.to_string()))
+}
+
+/// Reads a password from the terminal without echoing it, or the first line
+/// of stdin when it isn't a terminal, so it stays out of the shell history
+pub fn read_password(prompt: &str) -> Result<String, TRCError> {
+  let stdin = io::stdin();
+  let terminal = stdin.is_terminal();
+  if terminal {
+    eprint!("{}", prompt);
+    // Without stty the password shows, which is still better than failing
+    let _ = Command::new("stty").arg("-echo").status();
+  }
+  let mut line = String::new();
+  let read = stdin.lock().read_line(&mut line);
+  if terminal {
+    let _ = Command::new("stty").arg("echo").status();
+    eprintln!();
+  }
+  read?;
+  let password = line.trim_end_matches(&['\r', '\n'][..]);
+  if password.is_empty() {
+    return Err(TRCError::Unknown("The password can't be empty".to_owned()));
+  }
+  Ok(password.to_owned())
+}
+
+fn find(conn: &DBConnection, username: &str) -> Result<i32, TRCError> {
+  users::table
+    .select(users::id)
+    .filter(users::username.eq(username))
+    .get_result::<i32>(conn)
+    .optional()?
+    .ok_or_else(|| TRCError::Unknown(format!("No user named {}", username)))
+}
+
+/// Creates an account and returns its id
+pub fn create(
+  conn: &DBConnection,
+  username: &str,
+  password: &str,
+  role: UserRole,
+) -> Result<i32, TRCError> {
+  let hashed = hash_password(password)?;
+  conn.transaction(|| {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let id = diesel::insert_into(users::table)
+      .values((
+        users::username.eq(username),
+        users::password.eq(hashed),
+        users::role.eq(role),
+        users::created_at.eq(time),
+        users::updated_at.eq(time),
+      ))
+      .returning(users::id)
+      .get_result::<i32>(conn)?;
+    Audit::new(None, id, time).inserted::<UserRow>(conn, &[id])?;
+    Ok(id)
+  })
+}
+
+/// Sets a temporary password, which the user is asked to change
+pub fn reset_password(conn: &DBConnection, username: &str, password: &str) -> Result<(), TRCError> {
+  let hashed = hash_password(password)?;
+  conn.transaction(|| {
+    let id = find(conn, username)?;
+    let before = UserRow::load(conn, &[id])?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    diesel::update(users::table.filter(users::id.eq(id)))
+      .set((
+        users::password.eq(hashed),
+        users::password_reset.eq(true),
+        users::updated_at.eq(time),
+      ))
+      .execute(conn)?;
+    Audit::new(None, id, time).updated(conn, before)?;
+    Ok(())
+  })
+}
+
+/// Removes an account along with everything it owns
+pub fn delete(conn: &DBConnection, username: &str) -> Result<(), TRCError> {
+  conn.transaction(|| {
+    let id = find(conn, username)?;
+    let before = UserRow::load(conn, &[id])?;
+    diesel::delete(users::table.filter(users::id.eq(id))).execute(conn)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    Audit::new(None, id, time).deleted(conn, before)?;
+    Ok(())
+  })
+}
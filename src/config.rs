@@ -0,0 +1,444 @@
+use std::{
+  collections::HashMap,
+  env, fs,
+  path::{Component, Path, PathBuf},
+  str::FromStr,
+};
+
+use crate::TRCError;
+
+/// Where the configuration is read from when no path is given
+pub const DEFAULT_PATH: &str = "total_recall.toml";
+
+/// Prefix of the environment variables that override the configuration file,
+/// e.g. `TOTAL_RECALL_DATABASE_POOL_SIZE` for `database.pool_size`.
+const ENV_PREFIX: &str = "TOTAL_RECALL_";
+
+/// Token secrets from old defaults and the example configuration
+const PLACEHOLDER_SECRETS: [&str; 2] = ["secret", "change me"];
+
+/// Server settings, read from a TOML file with environment overrides
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub database: DatabaseConfig,
+  pub server: ServerConfig,
+  pub auth: AuthConfig,
+  pub web: WebConfig,
+  pub media: MediaConfig,
+  pub cors: CorsConfig,
+  pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+  /// Also taken from `DATABASE_URL`
+  pub url: String,
+  pub pool_size: u32,
+}
+
+impl Default for DatabaseConfig {
+  fn default() -> Self {
+    DatabaseConfig {
+      url: String::new(),
+      pool_size: 10,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+  pub socket: String,
+  pub trash_retention_days: u64,
+}
+
+impl Default for ServerConfig {
+  fn default() -> Self {
+    ServerConfig {
+      socket: "127.0.0.1:8000".to_owned(),
+      trash_retention_days: 30,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+  /// Key used to sign login tokens
+  pub jwt_secret: String,
+  /// How long a login token stays valid
+  pub token_days: i32,
+}
+
+impl Default for AuthConfig {
+  fn default() -> Self {
+    AuthConfig {
+      jwt_secret: String::new(),
+      token_days: 30,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+  /// Directory holding the web client bundle
+  pub root: PathBuf,
+}
+
+impl Default for WebConfig {
+  fn default() -> Self {
+    WebConfig {
+      root: PathBuf::from("./static"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+  /// Directory generated audio and images are stored in. It is kept apart
+  /// from the web root, as garbage collection removes files from it.
+  pub root: PathBuf,
+  /// Prefix of the media URLs stored on cards, `/media` by default, which is
+  /// where the server serves the media root. Point it at another host, e.g.
+  /// `https://media.example.com/`, to serve media from there. Left empty,
+  /// URLs are relative paths like `audio/ab/ab…mp3`, which the server only
+  /// resolves for URLs stored before `/media` existed.
+  pub base_url: String,
+  /// Only serve media to users who can see a deck using it, or when the deck
+  /// is published
+  pub private: bool,
+  /// How often the server removes media no card uses, 0 to leave it to
+  /// `media gc`
+  pub gc_interval_hours: u64,
+  /// Largest file users may upload, in bytes
+  pub max_upload_bytes: u64,
+  /// Where the pictures of new cards come from
+  pub images: ImageSource,
+  pub tts: TtsConfig,
+}
+
+impl Default for MediaConfig {
+  fn default() -> Self {
+    MediaConfig {
+      root: PathBuf::from("./media"),
+      base_url: "/media".to_owned(),
+      private: false,
+      gc_interval_hours: 0,
+      max_upload_bytes: 5 * 1024 * 1024,
+      images: ImageSource::Google,
+      tts: TtsConfig::default(),
+    }
+  }
+}
+
+/// Where the pictures of new cards come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSource {
+  /// The first Google Images result, over the network
+  Google,
+  /// No picture, for servers without internet access. Users can still
+  /// upload one.
+  None,
+}
+
+impl FromStr for ImageSource {
+  type Err = ();
+
+  fn from_str(value: &str) -> Result<Self, ()> {
+    match value {
+      "google" => Ok(ImageSource::Google),
+      "none" => Ok(ImageSource::None),
+      _ => Err(()),
+    }
+  }
+}
+
+/// Where the pronunciation audio of new cards comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtsProvider {
+  /// Google Translate, over the network
+  Google,
+  /// A text-to-speech engine installed on the server, like espeak-ng or piper
+  Local,
+}
+
+impl FromStr for TtsProvider {
+  type Err = ();
+
+  fn from_str(value: &str) -> Result<Self, ()> {
+    match value {
+      "google" => Ok(TtsProvider::Google),
+      "local" => Ok(TtsProvider::Local),
+      _ => Err(()),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtsConfig {
+  pub provider: TtsProvider,
+  /// Program and arguments run by the local provider, with `{voice}` and
+  /// `{output}` replaced by the voice and the file to write. The text to
+  /// speak is written to its standard input.
+  pub command: Vec<String>,
+  /// Voice to use for a language, by abbreviation. Languages without one
+  /// use their abbreviation.
+  pub voices: HashMap<String, String>,
+  /// How long the command may run before it is killed
+  pub timeout_secs: u64,
+}
+
+impl Default for TtsConfig {
+  fn default() -> Self {
+    TtsConfig {
+      provider: TtsProvider::Google,
+      command: ["espeak-ng", "--stdin", "-v", "{voice}", "-w", "{output}"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect(),
+      voices: HashMap::new(),
+      timeout_secs: 30,
+    }
+  }
+}
+
+impl TtsConfig {
+  pub fn voice<'a>(&'a self, language_abbr: &'a str) -> &'a str {
+    self
+      .voices
+      .get(language_abbr)
+      .map(String::as_str)
+      .unwrap_or(language_abbr)
+  }
+}
+
+impl MediaConfig {
+  /// The public URL of a file stored at `path` under the media root
+  pub fn url(&self, path: &str) -> String {
+    if self.base_url.is_empty() {
+      path.to_owned()
+    } else {
+      format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+  }
+
+  /// Where the file behind a media URL lives. URLs stored before the base
+  /// URL was set, or changed, are taken to be relative to the media root.
+  /// Returns `None` for URLs pointing elsewhere.
+  pub fn path(&self, url: &str) -> Option<PathBuf> {
+    let base = self.base_url.trim_end_matches('/');
+    let relative = match url.strip_prefix(base) {
+      Some(rest) if !base.is_empty() => rest.trim_start_matches('/'),
+      _ if url.contains("://") || url.starts_with('/') => return None,
+      _ => url,
+    };
+    safe_join(&self.root, relative)
+  }
+}
+
+/// Joins a relative path from a URL onto `root`, refusing paths that could
+/// lead outside of it
+pub fn safe_join(root: &Path, relative: &str) -> Option<PathBuf> {
+  let relative = Path::new(relative);
+  if relative
+    .components()
+    .any(|component| !matches!(component, Component::Normal(_)))
+  {
+    return None;
+  }
+  Some(root.join(relative))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+  /// Origins allowed to call the API from a browser, `*` for any
+  pub allowed_origins: Vec<String>,
+  /// How long browsers may cache a preflight response, in seconds
+  pub max_age: u32,
+}
+
+impl Default for CorsConfig {
+  fn default() -> Self {
+    CorsConfig {
+      allowed_origins: vec![],
+      max_age: 60 * 60,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+  /// Filter in `RUST_LOG` syntax, which takes precedence when set
+  pub level: String,
+}
+
+impl Default for LogConfig {
+  fn default() -> Self {
+    LogConfig {
+      level: "info".to_owned(),
+    }
+  }
+}
+
+fn invalid(message: String) -> TRCError {
+  TRCError::Configuration(message)
+}
+
+fn override_with<T: FromStr>(field: &mut T, name: &str) -> Result<(), TRCError> {
+  if let Ok(value) = env::var(format!("{}{}", ENV_PREFIX, name)) {
+    *field = value
+      .parse()
+      .map_err(|_| invalid(format!("{}{} has an invalid value", ENV_PREFIX, name)))?;
+  }
+  Ok(())
+}
+
+impl Config {
+  /// Reads the configuration file at `path`, or `total_recall.toml` if it
+  /// exists, and applies environment overrides. Callers validate once they
+  /// have applied their own overrides.
+  pub fn load(path: Option<&Path>) -> Result<Self, TRCError> {
+    let path = path.map(Path::to_path_buf).or_else(|| {
+      env::var(format!("{}CONFIG", ENV_PREFIX))
+        .ok()
+        .map(PathBuf::from)
+    });
+    let mut config = match path {
+      Some(path) => Config::parse(
+        &fs::read_to_string(&path)
+          .map_err(|err| invalid(format!("Failed to read {}: {}", path.display(), err)))?,
+      )?,
+      None if Path::new(DEFAULT_PATH).exists() => {
+        Config::parse(&fs::read_to_string(DEFAULT_PATH)?)?
+      }
+      None => Config::default(),
+    };
+    config.apply_env()?;
+    Ok(config)
+  }
+
+  pub fn parse(toml: &str) -> Result<Self, TRCError> {
+    toml::from_str(toml).map_err(|err| invalid(err.to_string()))
+  }
+
+  pub fn apply_env(&mut self) -> Result<(), TRCError> {
+    override_with(&mut self.database.url, "DATABASE_URL")?;
+    if self.database.url.is_empty() {
+      if let Ok(url) = env::var("DATABASE_URL") {
+        self.database.url = url;
+      }
+    }
+    override_with(&mut self.database.pool_size, "DATABASE_POOL_SIZE")?;
+    override_with(&mut self.server.socket, "SERVER_SOCKET")?;
+    override_with(
+      &mut self.server.trash_retention_days,
+      "SERVER_TRASH_RETENTION_DAYS",
+    )?;
+    override_with(&mut self.auth.jwt_secret, "AUTH_JWT_SECRET")?;
+    override_with(&mut self.auth.token_days, "AUTH_TOKEN_DAYS")?;
+    override_with(&mut self.web.root, "WEB_ROOT")?;
+    override_with(&mut self.media.root, "MEDIA_ROOT")?;
+    override_with(&mut self.media.base_url, "MEDIA_BASE_URL")?;
+    override_with(&mut self.media.private, "MEDIA_PRIVATE")?;
+    override_with(&mut self.media.gc_interval_hours, "MEDIA_GC_INTERVAL_HOURS")?;
+    override_with(&mut self.media.images, "MEDIA_IMAGES")?;
+    override_with(&mut self.media.tts.provider, "MEDIA_TTS_PROVIDER")?;
+    if let Ok(command) = env::var(format!("{}MEDIA_TTS_COMMAND", ENV_PREFIX)) {
+      self.media.tts.command = command.split_whitespace().map(String::from).collect();
+    }
+    if let Ok(origins) = env::var(format!("{}CORS_ALLOWED_ORIGINS", ENV_PREFIX)) {
+      self.cors.allowed_origins = origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(String::from)
+        .collect();
+    }
+    override_with(&mut self.cors.max_age, "CORS_MAX_AGE")?;
+    override_with(&mut self.log.level, "LOG_LEVEL")?;
+    Ok(())
+  }
+
+  pub fn validate(&self) -> Result<(), TRCError> {
+    if self.database.url.is_empty() {
+      return Err(invalid(
+        "database.url is required, set it in the configuration file or DATABASE_URL".to_owned(),
+      ));
+    }
+    if self.database.pool_size == 0 {
+      return Err(invalid("database.pool_size must be at least 1".to_owned()));
+    }
+    if self.server.socket.parse::<std::net::SocketAddr>().is_err() {
+      return Err(invalid(format!(
+        "server.socket {} is not an address and port",
+        self.server.socket
+      )));
+    }
+    if self.auth.jwt_secret.is_empty() {
+      return Err(invalid(
+        "auth.jwt_secret is required, set it in the configuration file or TOTAL_RECALL_AUTH_JWT_SECRET"
+          .to_owned(),
+      ));
+    }
+    if PLACEHOLDER_SECRETS.contains(&self.auth.jwt_secret.as_str()) {
+      return Err(invalid(
+        "auth.jwt_secret is still a placeholder, anyone could sign tokens with it".to_owned(),
+      ));
+    }
+    if self.auth.token_days <= 0 {
+      return Err(invalid("auth.token_days must be positive".to_owned()));
+    }
+    let base_url = &self.media.base_url;
+    if !base_url.is_empty()
+      && !base_url.starts_with('/')
+      && !base_url.starts_with("http://")
+      && !base_url.starts_with("https://")
+    {
+      return Err(invalid(format!(
+        "media.base_url {} must be an http(s) URL or start with /",
+        base_url
+      )));
+    }
+    if self.media.max_upload_bytes == 0 {
+      return Err(invalid(
+        "media.max_upload_bytes must be at least 1".to_owned(),
+      ));
+    }
+    let tts = &self.media.tts;
+    if tts.provider == TtsProvider::Local {
+      if tts.command.is_empty() {
+        return Err(invalid(
+          "media.tts.command is required for the local provider".to_owned(),
+        ));
+      }
+      if !tts.command.iter().any(|arg| arg.contains("{output}")) {
+        return Err(invalid(
+          "media.tts.command must write to {output}".to_owned(),
+        ));
+      }
+    }
+    if tts.timeout_secs == 0 {
+      return Err(invalid(
+        "media.tts.timeout_secs must be at least 1".to_owned(),
+      ));
+    }
+    for origin in &self.cors.allowed_origins {
+      if origin != "*" && !origin.starts_with("http://") && !origin.starts_with("https://") {
+        return Err(invalid(format!(
+          "cors.allowed_origins: {} is not an http(s) origin",
+          origin
+        )));
+      }
+    }
+    Ok(())
+  }
+}
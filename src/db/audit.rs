@@ -0,0 +1,197 @@
+use diesel::{
+  backend::Backend,
+  deserialize::{self, FromSql},
+  prelude::*,
+  serialize::{self, ToSql},
+  sql_types::SmallInt,
+};
+use serde::Serialize;
+use std::io::Write;
+
+use super::{
+  changes::Entity,
+  models::{
+    CardRow, DeckMemberRow, DeckRow, GroupAssignmentRow, GroupMemberRow, GroupRow, LanguageRow,
+    ScoreRow, SetRow, UserRow,
+  },
+  schema::{
+    audit_log, backs, cards, deck_members, decks, group_assignments, group_members, groups,
+    languages, scores, sets, users,
+  },
+  DBConnection,
+};
+
+/// What a mutation did to the audited row
+#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, Eq, PartialEq)]
+#[sql_type = "SmallInt"]
+pub enum Operation {
+  Insert = 0,
+  Update = 1,
+  Delete = 2,
+  Restore = 3,
+}
+
+impl<DB> ToSql<SmallInt, DB> for Operation
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for Operation
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => Operation::Insert,
+      1 => Operation::Update,
+      2 => Operation::Delete,
+      3 => Operation::Restore,
+      _ => return Err(format!("Unknown audit operation {}", value).into()),
+    })
+  }
+}
+
+/// A row whose state is captured in the audit log
+pub trait Audited: Serialize + Sized {
+  const ENTITY: Entity;
+
+  fn id(&self) -> i32;
+
+  fn load(conn: &DBConnection, ids: &[i32]) -> QueryResult<Vec<Self>>;
+}
+
+macro_rules! audited {
+  ($row:ident, $table:ident, $entity:ident) => {
+    impl Audited for $row {
+      const ENTITY: Entity = Entity::$entity;
+
+      fn id(&self) -> i32 {
+        self.id
+      }
+
+      fn load(conn: &DBConnection, ids: &[i32]) -> QueryResult<Vec<Self>> {
+        $table::table
+          .select($row::COLUMNS)
+          .filter($table::id.eq_any(ids))
+          .load(conn)
+      }
+    }
+  };
+}
+
+audited!(DeckRow, decks, Deck);
+audited!(SetRow, sets, Set);
+audited!(ScoreRow, scores, Score);
+audited!(UserRow, users, User);
+audited!(DeckMemberRow, deck_members, DeckMember);
+audited!(GroupRow, groups, Group);
+audited!(GroupMemberRow, group_members, GroupMember);
+audited!(GroupAssignmentRow, group_assignments, GroupAssignment);
+audited!(LanguageRow, languages, Language);
+
+// Cards carry the text of their back, which lives in its own table
+impl Audited for CardRow {
+  const ENTITY: Entity = Entity::Card;
+
+  fn id(&self) -> i32 {
+    self.id
+  }
+
+  fn load(conn: &DBConnection, ids: &[i32]) -> QueryResult<Vec<Self>> {
+    cards::table
+      .inner_join(backs::table)
+      .select(CardRow::COLUMNS)
+      .filter(cards::id.eq_any(ids))
+      .load(conn)
+  }
+}
+
+/// Who made a change, on whose behalf, and when
+#[derive(Debug, Copy, Clone)]
+pub struct Audit {
+  pub actor: Option<i32>,
+  pub owner: i32,
+  pub time: i64,
+}
+
+impl Audit {
+  pub fn new(actor: Option<i32>, owner: i32, time: i64) -> Self {
+    Audit { actor, owner, time }
+  }
+
+  fn record<T: Audited>(
+    &self,
+    conn: &DBConnection,
+    operation: Operation,
+    entity_id: i32,
+    before: Option<&T>,
+    after: Option<&T>,
+  ) -> QueryResult<()> {
+    diesel::insert_into(audit_log::table)
+      .values((
+        audit_log::actor.eq(self.actor),
+        audit_log::owner.eq(self.owner),
+        audit_log::entity.eq(T::ENTITY),
+        audit_log::entity_id.eq(entity_id),
+        audit_log::operation.eq(operation),
+        audit_log::before.eq(before.and_then(|row| serde_json::to_string(row).ok())),
+        audit_log::after.eq(after.and_then(|row| serde_json::to_string(row).ok())),
+        audit_log::created_at.eq(self.time),
+      ))
+      .execute(conn)?;
+    Ok(())
+  }
+
+  /// Records newly written rows, reading back their stored state
+  pub fn inserted<T: Audited>(&self, conn: &DBConnection, ids: &[i32]) -> QueryResult<()> {
+    self.written::<T>(conn, Operation::Insert, ids)
+  }
+
+  pub fn restored<T: Audited>(&self, conn: &DBConnection, ids: &[i32]) -> QueryResult<()> {
+    self.written::<T>(conn, Operation::Restore, ids)
+  }
+
+  fn written<T: Audited>(
+    &self,
+    conn: &DBConnection,
+    operation: Operation,
+    ids: &[i32],
+  ) -> QueryResult<()> {
+    for row in T::load(conn, ids)? {
+      self.record(conn, operation, row.id(), None, Some(&row))?;
+    }
+    Ok(())
+  }
+
+  /// Records updates given the state of the rows before they were changed
+  pub fn updated<T: Audited>(&self, conn: &DBConnection, before: Vec<T>) -> QueryResult<()> {
+    let ids = before.iter().map(Audited::id).collect::<Vec<_>>();
+    let mut after = T::load(conn, &ids)?;
+    for row in before {
+      let position = after.iter().position(|updated| updated.id() == row.id());
+      let updated = position.map(|position| after.swap_remove(position));
+      self.record(
+        conn,
+        Operation::Update,
+        row.id(),
+        Some(&row),
+        updated.as_ref(),
+      )?;
+    }
+    Ok(())
+  }
+
+  pub fn deleted<T: Audited>(&self, conn: &DBConnection, before: Vec<T>) -> QueryResult<()> {
+    for row in before {
+      self.record::<T>(conn, Operation::Delete, row.id(), Some(&row), None)?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,351 @@
+use diesel::{
+  backend::Backend,
+  deserialize::{self, FromSql},
+  prelude::*,
+  serialize::{self, ToSql},
+  sql_types::{BigInt, SmallInt},
+};
+use std::{cell::RefCell, collections::HashMap, io::Write};
+
+use super::{
+  schema::{cards, changes, scores, set_cards, sets},
+  DBConnection,
+};
+
+/// Key of the advisory lock that keeps change log writers in commit order
+const CHANGES_LOCK: i64 = 0x0063_6861_6e67_6573;
+
+/// The kind of row a change log entry refers to
+#[derive(
+  Debug,
+  Copy,
+  Clone,
+  AsExpression,
+  FromSqlRow,
+  GraphQLEnum,
+  Eq,
+  PartialEq,
+  Hash,
+  Serialize,
+  Deserialize,
+)]
+#[sql_type = "SmallInt"]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+  Deck = 0,
+  Card = 1,
+  Back = 2,
+  Set = 3,
+  SetCard = 4,
+  Score = 5,
+  User = 6,
+  DeckMember = 7,
+  Group = 8,
+  GroupMember = 9,
+  GroupAssignment = 10,
+  Language = 11,
+}
+
+impl<DB> ToSql<SmallInt, DB> for Entity
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for Entity
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => Entity::Deck,
+      1 => Entity::Card,
+      2 => Entity::Back,
+      3 => Entity::Set,
+      4 => Entity::SetCard,
+      5 => Entity::Score,
+      6 => Entity::User,
+      7 => Entity::DeckMember,
+      8 => Entity::Group,
+      9 => Entity::GroupMember,
+      10 => Entity::GroupAssignment,
+      11 => Entity::Language,
+      _ => return Err(format!("Unknown entity {}", value).into()),
+    })
+  }
+}
+
+/// Whether a row was written or removed
+#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, Eq, PartialEq, Hash)]
+#[sql_type = "SmallInt"]
+pub enum ChangeKind {
+  Upsert = 0,
+  Delete = 1,
+}
+
+impl<DB> ToSql<SmallInt, DB> for ChangeKind
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for ChangeKind
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => ChangeKind::Upsert,
+      1 => ChangeKind::Delete,
+      _ => return Err(format!("Unknown change kind {}", value).into()),
+    })
+  }
+}
+
+/// A change log entry waiting to be written: owner, entity, id, kind, time
+type Entry = (i32, Entity, i32, ChangeKind, i64);
+
+thread_local! {
+  /// Entries held back by `deferred` until its transaction is done
+  static DEFERRED: RefCell<Option<Vec<Entry>>> = const { RefCell::new(None) };
+}
+
+/// Drops entries held back by a transaction that failed or panicked, so they
+/// don't end up in the next one on this thread
+struct DeferredGuard;
+
+impl Drop for DeferredGuard {
+  fn drop(&mut self) {
+    DEFERRED.with(|deferred| deferred.borrow_mut().take());
+  }
+}
+
+fn write(conn: &DBConnection, entries: &[Entry]) -> QueryResult<()> {
+  if entries.is_empty() {
+    return Ok(());
+  }
+
+  diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+    .bind::<BigInt, _>(CHANGES_LOCK)
+    .execute(conn)?;
+  diesel::insert_into(changes::table)
+    .values(
+      entries
+        .iter()
+        .map(|(owner, entity, id, kind, time)| {
+          (
+            changes::owner.eq(owner),
+            changes::entity.eq(entity),
+            changes::entity_id.eq(id),
+            changes::kind.eq(kind),
+            changes::created_at.eq(time),
+          )
+        })
+        .collect::<Vec<_>>(),
+    )
+    .execute(conn)?;
+  Ok(())
+}
+
+/// Appends one change log entry per id. The generated `changes.id` values
+/// serve as the sync cursor handed out to clients.
+///
+/// Ids are handed out on insert, not on commit, so a transaction holds an
+/// advisory lock from its first entry until it commits. Otherwise a client
+/// could pull a later id while an earlier one is still uncommitted, move its
+/// cursor past it and never see that change.
+pub fn record(
+  conn: &DBConnection,
+  owner: i32,
+  entity: Entity,
+  ids: &[i32],
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let entries = ids
+    .iter()
+    .map(|id| (owner, entity, *id, kind, time))
+    .collect::<Vec<_>>();
+  let entries = DEFERRED.with(|deferred| match deferred.borrow_mut().as_mut() {
+    Some(held) => {
+      held.extend(entries);
+      vec![]
+    }
+    None => entries,
+  });
+  write(conn, &entries)
+}
+
+/// Runs `f` inside a transaction and holds back the change log entries it
+/// records until it is done, then writes them all at once. Transactions that
+/// download media use this, so the lock `record` takes isn't held while they
+/// wait on the network.
+pub fn deferred<T, E, F>(conn: &DBConnection, f: F) -> Result<T, E>
+where
+  F: FnOnce() -> Result<T, E>,
+  E: From<diesel::result::Error>,
+{
+  if DEFERRED.with(|deferred| deferred.borrow().is_some()) {
+    return f();
+  }
+
+  DEFERRED.with(|deferred| *deferred.borrow_mut() = Some(vec![]));
+  let guard = DeferredGuard;
+  let result = f();
+  let entries = DEFERRED.with(|deferred| deferred.borrow_mut().take());
+  drop(guard);
+  let value = result?;
+  write(conn, &entries.unwrap_or_default())?;
+  Ok(value)
+}
+
+/// Records changes to rows that may belong to different users, each under
+/// its own owner, so that nobody syncs rows of others.
+fn record_owned(
+  conn: &DBConnection,
+  entity: Entity,
+  rows: &[(i32, i32)],
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let mut by_owner: HashMap<i32, Vec<i32>> = HashMap::new();
+  for (id, owner) in rows {
+    by_owner.entry(*owner).or_default().push(*id);
+  }
+  for (owner, ids) in by_owner {
+    record(conn, owner, entity, &ids, kind, time)?;
+  }
+  Ok(())
+}
+
+/// Records a change for sets along with their card memberships, used when
+/// sets are moved to or restored from the trash.
+pub fn record_sets(
+  conn: &DBConnection,
+  set_ids: &[i32],
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let sets = sets::table
+    .select((sets::id, sets::owner))
+    .filter(sets::id.eq_any(set_ids))
+    .get_results::<(i32, i32)>(conn)?;
+  let set_cards = set_cards::table
+    .inner_join(sets::table)
+    .select((set_cards::id, sets::owner))
+    .filter(set_cards::set_id.eq_any(set_ids))
+    .get_results::<(i32, i32)>(conn)?;
+
+  record_owned(conn, Entity::SetCard, &set_cards, kind, time)?;
+  record_owned(conn, Entity::Set, &sets, kind, time)
+}
+
+/// Records a change for cards and everything hanging off them. The cards and
+/// backs go to the deck's `owner`, scores and set memberships to the users
+/// they belong to.
+pub fn record_cards(
+  conn: &DBConnection,
+  owner: i32,
+  card_ids: &[i32],
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let back_ids = cards::table
+    .select(cards::back)
+    .filter(cards::id.eq_any(card_ids))
+    .get_results::<i32>(conn)?;
+  let scores = scores::table
+    .select((scores::id, scores::owner))
+    .filter(scores::card.eq_any(card_ids))
+    .get_results::<(i32, i32)>(conn)?;
+  let set_cards = set_cards::table
+    .inner_join(sets::table)
+    .select((set_cards::id, sets::owner))
+    .filter(set_cards::card_id.eq_any(card_ids))
+    .get_results::<(i32, i32)>(conn)?;
+
+  record_owned(conn, Entity::Score, &scores, kind, time)?;
+  record_owned(conn, Entity::SetCard, &set_cards, kind, time)?;
+  record(conn, owner, Entity::Card, card_ids, kind, time)?;
+  record(conn, owner, Entity::Back, &back_ids, kind, time)
+}
+
+/// Records a change for a deck and everything in it. Cards and sets that
+/// are in the trash on their own are left out.
+pub fn record_deck(
+  conn: &DBConnection,
+  owner: i32,
+  deck_id: i32,
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let card_ids = cards::table
+    .select(cards::id)
+    .filter(cards::deck.eq(deck_id))
+    .filter(cards::deleted_at.is_null())
+    .get_results::<i32>(conn)?;
+  let set_ids = sets::table
+    .select(sets::id)
+    .filter(sets::deck.eq(deck_id))
+    .filter(sets::deleted_at.is_null())
+    .get_results::<i32>(conn)?;
+
+  record_sets(conn, &set_ids, kind, time)?;
+  record_cards(conn, owner, &card_ids, kind, time)?;
+  record(conn, owner, Entity::Deck, &[deck_id], kind, time)
+}
+
+/// Records a change for a deck someone else owns under `user`, who gained
+/// or lost access to it, along with its cards and the user's own sets and
+/// scores on it. Earlier changes to the deck are in its owner's log, which
+/// only reaches the user from now on.
+pub fn record_access(
+  conn: &DBConnection,
+  user: i32,
+  deck_id: i32,
+  kind: ChangeKind,
+  time: i64,
+) -> QueryResult<()> {
+  let cards = cards::table
+    .select((cards::id, cards::back))
+    .filter(cards::deck.eq(deck_id))
+    .filter(cards::deleted_at.is_null())
+    .get_results::<(i32, i32)>(conn)?;
+  let card_ids = cards.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+  let back_ids = cards.iter().map(|(_, back)| *back).collect::<Vec<_>>();
+  let set_ids = sets::table
+    .select(sets::id)
+    .filter(sets::deck.eq(deck_id))
+    .filter(sets::owner.eq(user))
+    .filter(sets::deleted_at.is_null())
+    .get_results::<i32>(conn)?;
+  let set_card_ids = set_cards::table
+    .select(set_cards::id)
+    .filter(set_cards::set_id.eq_any(&set_ids))
+    .filter(set_cards::card_id.eq_any(&card_ids))
+    .get_results::<i32>(conn)?;
+  let score_ids = scores::table
+    .select(scores::id)
+    .filter(scores::card.eq_any(&card_ids))
+    .filter(scores::owner.eq(user))
+    .get_results::<i32>(conn)?;
+
+  record(conn, user, Entity::Score, &score_ids, kind, time)?;
+  record(conn, user, Entity::SetCard, &set_card_ids, kind, time)?;
+  record(conn, user, Entity::Set, &set_ids, kind, time)?;
+  record(conn, user, Entity::Card, &card_ids, kind, time)?;
+  record(conn, user, Entity::Back, &back_ids, kind, time)?;
+  record(conn, user, Entity::Deck, &[deck_id], kind, time)
+}
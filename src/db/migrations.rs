@@ -0,0 +1,76 @@
+use diesel_migrations::{run_migrations, setup_database, Migration, MigrationConnection};
+use std::io::Write;
+
+use super::DBConnection;
+use crate::TRCError;
+
+// What `embed_migrations!` expands to, written out so the list of migrations
+// can be inspected and not only run.
+#[allow(dead_code)]
+mod embedded {
+  use diesel_migrations::{EmbedMigrations, Migration};
+
+  #[derive(EmbedMigrations)]
+  #[embed_migrations_options(migrations_path = "migrations")]
+  struct _Dummy;
+
+  pub fn all() -> &'static [&'static dyn Migration] {
+    ALL_MIGRATIONS
+  }
+}
+
+/// Versions of the migrations built into the binary, oldest first
+pub fn embedded() -> Vec<&'static str> {
+  let mut versions = embedded::all()
+    .iter()
+    .map(|migration| migration.version())
+    .collect::<Vec<_>>();
+  versions.sort();
+  versions
+}
+
+/// Embedded migrations the database hasn't run yet, oldest first
+pub fn pending(conn: &DBConnection) -> Result<Vec<&'static str>, TRCError> {
+  setup_database(conn)?;
+  let applied = conn.previously_run_migration_versions()?;
+  Ok(
+    embedded()
+      .into_iter()
+      .filter(|version| !applied.contains(*version))
+      .collect(),
+  )
+}
+
+/// Migrations the database has run that are newer than any this binary
+/// knows about, meaning it was migrated by a newer release.
+pub fn unknown(conn: &DBConnection) -> Result<Vec<String>, TRCError> {
+  setup_database(conn)?;
+  let latest = embedded().last().cloned().unwrap_or_default();
+  let mut newer = conn
+    .previously_run_migration_versions()?
+    .into_iter()
+    .filter(|version| version.as_str() > latest)
+    .collect::<Vec<_>>();
+  newer.sort();
+  Ok(newer)
+}
+
+/// Fails if the database schema is newer than the binary
+pub fn check(conn: &DBConnection) -> Result<(), TRCError> {
+  let newer = unknown(conn)?;
+  if newer.is_empty() {
+    Ok(())
+  } else {
+    Err(TRCError::Migration(format!(
+      "the database has migrations this binary doesn't know about: {}",
+      newer.join(", ")
+    )))
+  }
+}
+
+/// Runs the pending embedded migrations, writing their names to `out`.
+/// Refuses to touch a database whose schema is newer than the binary.
+pub fn run(conn: &DBConnection, out: &mut dyn Write) -> Result<(), TRCError> {
+  check(conn)?;
+  Ok(run_migrations(conn, embedded::all().iter().cloned(), out)?)
+}
@@ -1,6 +1,28 @@
-use diesel::{Connection, PgConnection};
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  Connection, PgConnection,
+};
 
+use crate::TRCError;
+
+pub mod audit;
+pub mod changes;
+pub mod migrations;
+pub mod models;
 pub mod schema;
+pub mod trash;
 
 pub type DBConnection = PgConnection;
 pub type DbBackend = <DBConnection as Connection>::Backend;
+pub type DBPool = Pool<ConnectionManager<DBConnection>>;
+
+/// Sets up the connection pool for the PostgreSQL database at `url`
+pub fn connect(url: &str, size: Option<u32>) -> Result<DBPool, TRCError> {
+  let mut builder = Pool::builder();
+  if let Some(size) = size {
+    builder = builder.max_size(size);
+  }
+  builder
+    .build(ConnectionManager::new(url))
+    .map_err(|err| TRCError::Unknown(err.to_string()))
+}
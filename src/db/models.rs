@@ -0,0 +1,380 @@
+use super::schema::{
+  backs, cards, deck_members, decks, group_assignments, group_members, groups, languages, scores,
+  set_cards, sets, users,
+};
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct DeckRow {
+  pub id: i32,
+  pub name: String,
+  pub owner: i32,
+  pub language: i32,
+  pub updated_at: i64,
+  pub version: i32,
+  pub description: Option<String>,
+  pub licence: Option<String>,
+  pub published: bool,
+  pub source: Option<i32>,
+  pub front_language: Option<i32>,
+}
+
+impl DeckRow {
+  pub const COLUMNS: (
+    decks::id,
+    decks::name,
+    decks::owner,
+    decks::language,
+    decks::updated_at,
+    decks::version,
+    decks::description,
+    decks::licence,
+    decks::published,
+    decks::source,
+    decks::front_language,
+  ) = (
+    decks::id,
+    decks::name,
+    decks::owner,
+    decks::language,
+    decks::updated_at,
+    decks::version,
+    decks::description,
+    decks::licence,
+    decks::published,
+    decks::source,
+    decks::front_language,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct CardRow {
+  pub id: i32,
+  pub created_at: i64,
+  pub front: String,
+  pub back: i32,
+  /// Text of the back, so audit entries show edits to it
+  pub back_text: String,
+  pub deck: i32,
+  pub link: Option<String>,
+  pub updated_at: i64,
+  pub version: i32,
+  pub front_language: Option<i32>,
+  pub front_audio: Option<String>,
+}
+
+impl CardRow {
+  pub const COLUMNS: (
+    cards::id,
+    cards::created_at,
+    cards::front,
+    cards::back,
+    backs::text,
+    cards::deck,
+    cards::link,
+    cards::updated_at,
+    cards::version,
+    cards::front_language,
+    cards::front_audio,
+  ) = (
+    cards::id,
+    cards::created_at,
+    cards::front,
+    cards::back,
+    backs::text,
+    cards::deck,
+    cards::link,
+    cards::updated_at,
+    cards::version,
+    cards::front_language,
+    cards::front_audio,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct BackRow {
+  pub id: i32,
+  pub text: String,
+  pub language: i32,
+  pub audio: Option<String>,
+  pub image: Option<String>,
+  pub updated_at: i64,
+  pub audio_media: Option<i32>,
+  pub image_media: Option<i32>,
+  pub image_thumbnail: Option<String>,
+  pub image_card: Option<String>,
+}
+
+impl BackRow {
+  pub const COLUMNS: (
+    backs::id,
+    backs::text,
+    backs::language,
+    backs::audio,
+    backs::image,
+    backs::updated_at,
+    backs::audio_media,
+    backs::image_media,
+    backs::image_thumbnail,
+    backs::image_card,
+  ) = (
+    backs::id,
+    backs::text,
+    backs::language,
+    backs::audio,
+    backs::image,
+    backs::updated_at,
+    backs::audio_media,
+    backs::image_media,
+    backs::image_thumbnail,
+    backs::image_card,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct SetRow {
+  pub id: i32,
+  pub created_at: i64,
+  pub name: String,
+  pub deck: i32,
+  pub owner: i32,
+  pub updated_at: i64,
+  pub version: i32,
+}
+
+impl SetRow {
+  pub const COLUMNS: (
+    sets::id,
+    sets::created_at,
+    sets::name,
+    sets::deck,
+    sets::owner,
+    sets::updated_at,
+    sets::version,
+  ) = (
+    sets::id,
+    sets::created_at,
+    sets::name,
+    sets::deck,
+    sets::owner,
+    sets::updated_at,
+    sets::version,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct SetCardRow {
+  pub id: i32,
+  pub card_id: i32,
+  pub set_id: i32,
+  pub updated_at: i64,
+}
+
+impl SetCardRow {
+  pub const COLUMNS: (
+    set_cards::id,
+    set_cards::card_id,
+    set_cards::set_id,
+    set_cards::updated_at,
+  ) = (
+    set_cards::id,
+    set_cards::card_id,
+    set_cards::set_id,
+    set_cards::updated_at,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct ScoreRow {
+  pub id: i32,
+  pub created_at: i64,
+  pub card: i32,
+  pub value: i16,
+  pub updated_at: i64,
+  pub owner: i32,
+}
+
+impl ScoreRow {
+  pub const COLUMNS: (
+    scores::id,
+    scores::created_at,
+    scores::card,
+    scores::value,
+    scores::updated_at,
+    scores::owner,
+  ) = (
+    scores::id,
+    scores::created_at,
+    scores::card,
+    scores::value,
+    scores::updated_at,
+    scores::owner,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct UserRow {
+  pub id: i32,
+  pub username: String,
+  pub created_at: i64,
+  pub updated_at: i64,
+  pub role: i16,
+  pub disabled: bool,
+  pub native_language: Option<i32>,
+  pub timezone: String,
+  pub daily_review_goal: Option<i32>,
+  pub daily_new_card_goal: Option<i32>,
+}
+
+impl UserRow {
+  pub const COLUMNS: (
+    users::id,
+    users::username,
+    users::created_at,
+    users::updated_at,
+    users::role,
+    users::disabled,
+    users::native_language,
+    users::timezone,
+    users::daily_review_goal,
+    users::daily_new_card_goal,
+  ) = (
+    users::id,
+    users::username,
+    users::created_at,
+    users::updated_at,
+    users::role,
+    users::disabled,
+    users::native_language,
+    users::timezone,
+    users::daily_review_goal,
+    users::daily_new_card_goal,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct DeckMemberRow {
+  pub id: i32,
+  pub deck: i32,
+  pub user_id: i32,
+  pub role: i16,
+  pub accepted: bool,
+  pub created_at: i64,
+}
+
+impl DeckMemberRow {
+  pub const COLUMNS: (
+    deck_members::id,
+    deck_members::deck,
+    deck_members::user_id,
+    deck_members::role,
+    deck_members::accepted,
+    deck_members::created_at,
+  ) = (
+    deck_members::id,
+    deck_members::deck,
+    deck_members::user_id,
+    deck_members::role,
+    deck_members::accepted,
+    deck_members::created_at,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct GroupRow {
+  pub id: i32,
+  pub name: String,
+  pub owner: i32,
+  pub created_at: i64,
+}
+
+impl GroupRow {
+  pub const COLUMNS: (groups::id, groups::name, groups::owner, groups::created_at) =
+    (groups::id, groups::name, groups::owner, groups::created_at);
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct GroupMemberRow {
+  pub id: i32,
+  pub group_id: i32,
+  pub user_id: i32,
+  pub role: i16,
+  pub accepted: bool,
+  pub created_at: i64,
+}
+
+impl GroupMemberRow {
+  pub const COLUMNS: (
+    group_members::id,
+    group_members::group_id,
+    group_members::user_id,
+    group_members::role,
+    group_members::accepted,
+    group_members::created_at,
+  ) = (
+    group_members::id,
+    group_members::group_id,
+    group_members::user_id,
+    group_members::role,
+    group_members::accepted,
+    group_members::created_at,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct GroupAssignmentRow {
+  pub id: i32,
+  pub group_id: i32,
+  pub deck: i32,
+  pub set_id: Option<i32>,
+  pub assigned_at: i64,
+}
+
+impl GroupAssignmentRow {
+  pub const COLUMNS: (
+    group_assignments::id,
+    group_assignments::group_id,
+    group_assignments::deck,
+    group_assignments::set_id,
+    group_assignments::assigned_at,
+  ) = (
+    group_assignments::id,
+    group_assignments::group_id,
+    group_assignments::deck,
+    group_assignments::set_id,
+    group_assignments::assigned_at,
+  );
+}
+
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct LanguageRow {
+  pub id: i32,
+  pub name: String,
+  pub abbreviation: String,
+  pub tag: String,
+  pub script: Option<String>,
+  pub direction: i16,
+  pub tts_voice: Option<String>,
+  pub disabled: bool,
+}
+
+impl LanguageRow {
+  pub const COLUMNS: (
+    languages::id,
+    languages::name,
+    languages::abbreviation,
+    languages::tag,
+    languages::script,
+    languages::direction,
+    languages::tts_voice,
+    languages::disabled,
+  ) = (
+    languages::id,
+    languages::name,
+    languages::abbreviation,
+    languages::tag,
+    languages::script,
+    languages::direction,
+    languages::tts_voice,
+    languages::disabled,
+  );
+}
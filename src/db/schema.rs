@@ -1,3 +1,30 @@
+table! {
+    use diesel::sql_types::*;
+
+    achievements (id) {
+        id -> Int4,
+        user_id -> Int4,
+        kind -> Int2,
+        unlocked_at -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    audit_log (id) {
+        id -> Int8,
+        actor -> Nullable<Int4>,
+        owner -> Int4,
+        entity -> Int2,
+        entity_id -> Int4,
+        operation -> Int2,
+        before -> Nullable<Text>,
+        after -> Nullable<Text>,
+        created_at -> Int8,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
 
@@ -7,6 +34,11 @@ table! {
         language -> Int4,
         audio -> Nullable<Text>,
         image -> Nullable<Text>,
+        updated_at -> Int8,
+        audio_media -> Nullable<Int4>,
+        image_media -> Nullable<Int4>,
+        image_thumbnail -> Nullable<Text>,
+        image_card -> Nullable<Text>,
     }
 }
 
@@ -20,6 +52,42 @@ table! {
         back -> Int4,
         deck -> Int4,
         link -> Nullable<Text>,
+        updated_at -> Int8,
+        version -> Int4,
+        deleted_at -> Nullable<Int8>,
+        origin -> Nullable<Int4>,
+        origin_version -> Nullable<Int4>,
+        merged_version -> Nullable<Int4>,
+        front_language -> Nullable<Int4>,
+        front_audio -> Nullable<Text>,
+        front_audio_media -> Nullable<Int4>,
+        removed_upstream -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    changes (id) {
+        id -> Int8,
+        owner -> Int4,
+        entity -> Int2,
+        entity_id -> Int4,
+        kind -> Int2,
+        created_at -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    deck_members (id) {
+        id -> Int4,
+        deck -> Int4,
+        user_id -> Int4,
+        role -> Int2,
+        accepted -> Bool,
+        created_at -> Int8,
     }
 }
 
@@ -31,6 +99,50 @@ table! {
         name -> Varchar,
         owner -> Int4,
         language -> Int4,
+        updated_at -> Int8,
+        version -> Int4,
+        deleted_at -> Nullable<Int8>,
+        description -> Nullable<Text>,
+        licence -> Nullable<Text>,
+        published -> Bool,
+        source -> Nullable<Int4>,
+        front_language -> Nullable<Int4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    group_assignments (id) {
+        id -> Int4,
+        group_id -> Int4,
+        deck -> Int4,
+        set_id -> Nullable<Int4>,
+        assigned_at -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    group_members (id) {
+        id -> Int4,
+        group_id -> Int4,
+        user_id -> Int4,
+        role -> Int2,
+        accepted -> Bool,
+        created_at -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    groups (id) {
+        id -> Int4,
+        name -> Varchar,
+        owner -> Int4,
+        created_at -> Int8,
     }
 }
 
@@ -41,6 +153,36 @@ table! {
         id -> Int4,
         name -> Varchar,
         abbreviation -> Varchar,
+        tag -> Varchar,
+        script -> Nullable<Varchar>,
+        direction -> Int2,
+        tts_voice -> Nullable<Varchar>,
+        disabled -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    media (id) {
+        id -> Int4,
+        hash -> Varchar,
+        mime -> Varchar,
+        size -> Int8,
+        source -> Nullable<Text>,
+        licence -> Nullable<Text>,
+        created_at -> Int8,
+        stored_at -> Int8,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+
+    media_variants (media_id, variant) {
+        media_id -> Int4,
+        variant -> Int2,
+        variant_id -> Int4,
     }
 }
 
@@ -52,6 +194,8 @@ table! {
         created_at -> Int8,
         card -> Int4,
         value -> Int2,
+        updated_at -> Int8,
+        owner -> Int4,
     }
 }
 
@@ -62,6 +206,7 @@ table! {
         id -> Int4,
         card_id -> Int4,
         set_id -> Int4,
+        updated_at -> Int8,
     }
 }
 
@@ -74,6 +219,9 @@ table! {
         name -> Text,
         deck -> Int4,
         owner -> Int4,
+        updated_at -> Int8,
+        version -> Int4,
+        deleted_at -> Nullable<Int8>,
     }
 }
 
@@ -86,25 +234,52 @@ table! {
         password -> Varchar,
         created_at -> Int8,
         updated_at -> Int8,
+        role -> Int2,
+        disabled -> Bool,
+        password_reset -> Bool,
+        native_language -> Nullable<Int4>,
+        timezone -> Varchar,
+        daily_review_goal -> Nullable<Int4>,
+        daily_new_card_goal -> Nullable<Int4>,
     }
 }
 
+joinable!(achievements -> users (user_id));
 joinable!(backs -> languages (language));
 joinable!(cards -> backs (back));
 joinable!(cards -> decks (deck));
+joinable!(changes -> users (owner));
+joinable!(deck_members -> decks (deck));
+joinable!(deck_members -> users (user_id));
 joinable!(decks -> languages (language));
 joinable!(decks -> users (owner));
+joinable!(group_assignments -> decks (deck));
+joinable!(group_assignments -> groups (group_id));
+joinable!(group_assignments -> sets (set_id));
+joinable!(group_members -> groups (group_id));
+joinable!(group_members -> users (user_id));
+joinable!(groups -> users (owner));
 joinable!(scores -> cards (card));
+joinable!(scores -> users (owner));
 joinable!(set_cards -> cards (card_id));
 joinable!(set_cards -> sets (set_id));
 joinable!(sets -> decks (deck));
 joinable!(sets -> users (owner));
 
 allow_tables_to_appear_in_same_query!(
+    achievements,
+    audit_log,
     backs,
     cards,
+    changes,
+    deck_members,
     decks,
+    group_assignments,
+    group_members,
+    groups,
     languages,
+    media,
+    media_variants,
     scores,
     set_cards,
     sets,
@@ -0,0 +1,203 @@
+use diesel::{pg::Pg, prelude::*, sql_types::Integer};
+use std::{
+  cmp::Reverse,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+  changes::{self, ChangeKind, Entity},
+  schema::{backs, cards, decks, sets},
+  DBConnection,
+};
+use crate::TRCError;
+
+/// A deck, card or set waiting in the trash
+#[derive(Clone, Debug)]
+pub struct TrashEntry {
+  pub entity: Entity,
+  pub id: i32,
+  pub name: String,
+  pub deleted_at: i64,
+}
+
+/// Decks that aren't in the trash
+fn live_decks() -> decks::BoxedQuery<'static, Pg, Integer> {
+  decks::table
+    .select(decks::id)
+    .filter(decks::deleted_at.is_null())
+    .into_boxed()
+}
+
+/// Moves a deck to the trash, hiding its cards and sets along with it.
+pub fn trash_deck(conn: &DBConnection, owner: i32, id: i32, time: i64) -> QueryResult<usize> {
+  changes::record_deck(conn, owner, id, ChangeKind::Delete, time)?;
+  diesel::update(
+    decks::table
+      .filter(decks::id.eq(id))
+      .filter(decks::deleted_at.is_null()),
+  )
+  .set((decks::deleted_at.eq(time), decks::updated_at.eq(time)))
+  .execute(conn)
+}
+
+pub fn trash_card(conn: &DBConnection, owner: i32, id: i32, time: i64) -> QueryResult<usize> {
+  changes::record_cards(conn, owner, &[id], ChangeKind::Delete, time)?;
+  diesel::update(
+    cards::table
+      .filter(cards::id.eq(id))
+      .filter(cards::deleted_at.is_null()),
+  )
+  .set((cards::deleted_at.eq(time), cards::updated_at.eq(time)))
+  .execute(conn)
+}
+
+pub fn trash_set(conn: &DBConnection, id: i32, time: i64) -> QueryResult<usize> {
+  changes::record_sets(conn, &[id], ChangeKind::Delete, time)?;
+  diesel::update(
+    sets::table
+      .filter(sets::id.eq(id))
+      .filter(sets::deleted_at.is_null()),
+  )
+  .set((sets::deleted_at.eq(time), sets::updated_at.eq(time)))
+  .execute(conn)
+}
+
+pub fn restore_deck(conn: &DBConnection, owner: i32, id: i32, time: i64) -> QueryResult<usize> {
+  let restored = diesel::update(
+    decks::table
+      .filter(decks::id.eq(id))
+      .filter(decks::deleted_at.is_not_null()),
+  )
+  .set((
+    decks::deleted_at.eq(None::<i64>),
+    decks::updated_at.eq(time),
+  ))
+  .execute(conn)?;
+  if restored > 0 {
+    changes::record_deck(conn, owner, id, ChangeKind::Upsert, time)?;
+  }
+  Ok(restored)
+}
+
+/// Takes a card out of the trash. Cards of a trashed deck stay trashed until
+/// the deck is restored.
+pub fn restore_card(conn: &DBConnection, owner: i32, id: i32, time: i64) -> QueryResult<usize> {
+  let restored = diesel::update(
+    cards::table
+      .filter(cards::id.eq(id))
+      .filter(cards::deleted_at.is_not_null())
+      .filter(cards::deck.eq_any(live_decks())),
+  )
+  .set((
+    cards::deleted_at.eq(None::<i64>),
+    cards::updated_at.eq(time),
+    cards::removed_upstream.eq(false),
+  ))
+  .execute(conn)?;
+  if restored > 0 {
+    changes::record_cards(conn, owner, &[id], ChangeKind::Upsert, time)?;
+  }
+  Ok(restored)
+}
+
+/// Takes a set out of the trash. Sets of a trashed deck stay trashed until
+/// the deck is restored.
+pub fn restore_set(conn: &DBConnection, id: i32, time: i64) -> QueryResult<usize> {
+  let restored = diesel::update(
+    sets::table
+      .filter(sets::id.eq(id))
+      .filter(sets::deleted_at.is_not_null())
+      .filter(sets::deck.eq_any(live_decks())),
+  )
+  .set((sets::deleted_at.eq(None::<i64>), sets::updated_at.eq(time)))
+  .execute(conn)?;
+  if restored > 0 {
+    changes::record_sets(conn, &[id], ChangeKind::Upsert, time)?;
+  }
+  Ok(restored)
+}
+
+/// Lists what the user has in the trash, most recently deleted first. Cards
+/// and sets of a trashed deck are not listed, they come back with the deck.
+pub fn list(conn: &DBConnection, owner: i32) -> QueryResult<Vec<TrashEntry>> {
+  let mut entries = vec![];
+
+  for (id, name, deleted_at) in decks::table
+    .select((decks::id, decks::name, decks::deleted_at))
+    .filter(decks::owner.eq(owner))
+    .filter(decks::deleted_at.is_not_null())
+    .load::<(i32, String, Option<i64>)>(conn)?
+  {
+    entries.push(TrashEntry {
+      entity: Entity::Deck,
+      id,
+      name,
+      deleted_at: deleted_at.unwrap_or_default(),
+    });
+  }
+
+  for (id, name, deleted_at) in cards::table
+    .inner_join(decks::table)
+    .select((cards::id, cards::front, cards::deleted_at))
+    .filter(decks::owner.eq(owner))
+    .filter(decks::deleted_at.is_null())
+    .filter(cards::deleted_at.is_not_null())
+    .load::<(i32, String, Option<i64>)>(conn)?
+  {
+    entries.push(TrashEntry {
+      entity: Entity::Card,
+      id,
+      name,
+      deleted_at: deleted_at.unwrap_or_default(),
+    });
+  }
+
+  for (id, name, deleted_at) in sets::table
+    .inner_join(decks::table)
+    .select((sets::id, sets::name, sets::deleted_at))
+    .filter(sets::owner.eq(owner))
+    .filter(decks::deleted_at.is_null())
+    .filter(sets::deleted_at.is_not_null())
+    .load::<(i32, String, Option<i64>)>(conn)?
+  {
+    entries.push(TrashEntry {
+      entity: Entity::Set,
+      id,
+      name,
+      deleted_at: deleted_at.unwrap_or_default(),
+    });
+  }
+
+  entries.sort_by_key(|entry| Reverse(entry.deleted_at));
+  Ok(entries)
+}
+
+/// Permanently removes everything that has been in the trash for longer than
+/// `retention`. Returns the number of decks, cards and sets removed.
+pub fn purge(conn: &DBConnection, retention: Duration) -> Result<usize, TRCError> {
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+  let before = now.checked_sub(retention).unwrap_or_default().as_millis() as i64;
+
+  Ok(conn.transaction::<_, diesel::result::Error, _>(|| {
+    let expired_decks = decks::table
+      .select(decks::id)
+      .filter(decks::deleted_at.lt(before));
+    // Every card gets its own back, which would otherwise be left behind.
+    let mut back_ids = cards::table
+      .select(cards::back)
+      .filter(cards::deleted_at.lt(before))
+      .get_results::<i32>(conn)?;
+    back_ids.extend(
+      cards::table
+        .select(cards::back)
+        .filter(cards::deck.eq_any(expired_decks))
+        .get_results::<i32>(conn)?,
+    );
+
+    let removed = diesel::delete(sets::table.filter(sets::deleted_at.lt(before))).execute(conn)?
+      + diesel::delete(cards::table.filter(cards::deleted_at.lt(before))).execute(conn)?
+      + diesel::delete(decks::table.filter(decks::deleted_at.lt(before))).execute(conn)?;
+    diesel::delete(backs::table.filter(backs::id.eq_any(back_ids))).execute(conn)?;
+    Ok(removed)
+  })?)
+}
@@ -0,0 +1,304 @@
+use bcrypt::hash;
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{authorization::UserRole, query::TextDirection, GQLContext};
+use crate::{
+  db::{
+    audit::{Audit, Audited},
+    models::{LanguageRow, UserRow},
+    schema::{languages, users},
+    DBConnection,
+  },
+  TRCError,
+};
+
+/// A user account as administrators see it
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct Account {
+  id: i32,
+  username: String,
+  role: UserRole,
+  /// Disabled accounts can't log in, and their tokens stop working
+  disabled: bool,
+  /// The user has to pick a new password
+  password_reset: bool,
+  created_at: i64,
+}
+
+const DEFAULT_LIMIT: i32 = 50;
+
+/// Fields for administrators, guarded by `AdminOnly`
+pub struct AdminQuery;
+
+#[juniper::object(Context = GQLContext<DBConnection>, Scalar = WundergraphScalarValue)]
+impl AdminQuery {
+  /// All user accounts, optionally those whose username contains `search`
+  fn accounts(
+    context: &GQLContext<DBConnection>,
+    search: Option<String>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+  ) -> FieldResult<Vec<Account>, WundergraphScalarValue> {
+    accounts(context, search, limit, offset)
+  }
+}
+
+pub struct AdminMutation;
+
+#[juniper::object(Context = GQLContext<DBConnection>, Scalar = WundergraphScalarValue)]
+impl AdminMutation {
+  fn set_user_role(
+    context: &GQLContext<DBConnection>,
+    user: i32,
+    role: UserRole,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    update_account(context, user, |conn| {
+      diesel::update(users::table.filter(users::id.eq(user)))
+        .set(users::role.eq(role))
+        .execute(conn)
+    })
+  }
+
+  /// Disables an account, or enables it again
+  fn disable_account(
+    context: &GQLContext<DBConnection>,
+    user: i32,
+    disabled: bool,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    update_account(context, user, |conn| {
+      diesel::update(users::table.filter(users::id.eq(user)))
+        .set(users::disabled.eq(disabled))
+        .execute(conn)
+    })
+  }
+
+  /// Sets a temporary password the user has to change after logging in
+  fn force_password_reset(
+    context: &GQLContext<DBConnection>,
+    user: i32,
+    password: String,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    let hashed = hash(&password, 10)?;
+    update_account(context, user, |conn| {
+      diesel::update(users::table.filter(users::id.eq(user)))
+        .set((users::password.eq(&hashed), users::password_reset.eq(true)))
+        .execute(conn)
+    })
+  }
+
+  /// Adds a language and returns its id. The tag defaults to the
+  /// abbreviation.
+  fn add_language(
+    context: &GQLContext<DBConnection>,
+    name: String,
+    abbreviation: String,
+    tag: Option<String>,
+    script: Option<String>,
+    direction: Option<TextDirection>,
+    tts_voice: Option<String>,
+  ) -> FieldResult<i32, WundergraphScalarValue> {
+    let admin = context.user_id.ok_or(TRCError::Unauthorized)?;
+    let tag = check_tag(tag.unwrap_or_else(|| abbreviation.clone()))?;
+    let script = script.map(check_script).transpose()?;
+    let conn = context.get_connection();
+    conn.transaction(|| {
+      let id = diesel::insert_into(languages::table)
+        .values((
+          languages::name.eq(name),
+          languages::abbreviation.eq(abbreviation),
+          languages::tag.eq(tag),
+          languages::script.eq(script),
+          languages::direction.eq(direction.unwrap_or(TextDirection::LTR)),
+          languages::tts_voice.eq(tts_voice.filter(|voice| !voice.is_empty())),
+        ))
+        .returning(languages::id)
+        .get_result::<i32>(conn)?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      Audit::new(Some(admin), admin, time).inserted::<LanguageRow>(conn, &[id])?;
+      Ok(id)
+    })
+  }
+
+  fn rename_language(
+    context: &GQLContext<DBConnection>,
+    id: i32,
+    name: String,
+    abbreviation: Option<String>,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    update_language(context, id, |conn| {
+      let current = languages::table
+        .select(languages::abbreviation)
+        .filter(languages::id.eq(id))
+        .get_result::<String>(conn)?;
+      diesel::update(languages::table.filter(languages::id.eq(id)))
+        .set((
+          languages::name.eq(name),
+          languages::abbreviation.eq(abbreviation.unwrap_or(current)),
+        ))
+        .execute(conn)
+    })
+  }
+
+  /// Changes the locale details of a language. An empty script or voice
+  /// removes it.
+  fn set_language_details(
+    context: &GQLContext<DBConnection>,
+    id: i32,
+    tag: Option<String>,
+    script: Option<String>,
+    direction: Option<TextDirection>,
+    tts_voice: Option<String>,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    // An empty changeset isn't valid SQL, and there is nothing to audit
+    if tag.is_none() && script.is_none() && direction.is_none() && tts_voice.is_none() {
+      return Ok(false);
+    }
+    let tag = tag.map(check_tag).transpose()?;
+    let script = script
+      .map(|script| match script.as_str() {
+        "" => Ok(None),
+        _ => check_script(script).map(Some),
+      })
+      .transpose()?;
+    update_language(context, id, |conn| {
+      diesel::update(languages::table.filter(languages::id.eq(id)))
+        .set((
+          tag.map(|tag| languages::tag.eq(tag)),
+          script.map(|script| languages::script.eq(script)),
+          direction.map(|direction| languages::direction.eq(direction)),
+          tts_voice
+            .map(|voice| languages::tts_voice.eq(Some(voice).filter(|voice| !voice.is_empty()))),
+        ))
+        .execute(conn)
+    })
+  }
+
+  /// Disables a language, or enables it again. Decks already in a disabled
+  /// language keep it, but new decks can't pick it.
+  fn disable_language(
+    context: &GQLContext<DBConnection>,
+    id: i32,
+    disabled: bool,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    update_language(context, id, |conn| {
+      diesel::update(languages::table.filter(languages::id.eq(id)))
+        .set(languages::disabled.eq(disabled))
+        .execute(conn)
+    })
+  }
+}
+
+/// Accepts tags shaped like BCP-47 ones: a two or three letter language
+/// followed by subtags of up to eight letters or digits
+fn check_tag(tag: String) -> Result<String, TRCError> {
+  let mut subtags = tag.split('-');
+  let language = subtags.next().unwrap_or_default();
+  let valid = (2..=3).contains(&language.len())
+    && language.chars().all(|c| c.is_ascii_alphabetic())
+    && subtags.all(|subtag| {
+      (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+  if valid {
+    Ok(tag)
+  } else {
+    Err(TRCError::Unknown(format!("{} isn't a BCP-47 tag", tag)))
+  }
+}
+
+/// Accepts ISO 15924 script codes, in their usual title case
+fn check_script(script: String) -> Result<String, TRCError> {
+  if script.len() != 4 || !script.chars().all(|c| c.is_ascii_alphabetic()) {
+    return Err(TRCError::Unknown(format!(
+      "{} isn't an ISO 15924 script code",
+      script
+    )));
+  }
+  Ok(script[..1].to_ascii_uppercase() + &script[1..].to_ascii_lowercase())
+}
+
+fn accounts(
+  ctx: &GQLContext<DBConnection>,
+  search: Option<String>,
+  limit: Option<i32>,
+  offset: Option<i32>,
+) -> FieldResult<Vec<Account>, WundergraphScalarValue> {
+  let mut query = users::table
+    .select((
+      users::id,
+      users::username,
+      users::role,
+      users::disabled,
+      users::password_reset,
+      users::created_at,
+    ))
+    .into_boxed();
+  if let Some(search) = search {
+    // Wildcards in the search are matched literally
+    let search = search
+      .replace('\\', "\\\\")
+      .replace('%', "\\%")
+      .replace('_', "\\_");
+    query = query.filter(users::username.like(format!("%{}%", search)));
+  }
+
+  Ok(
+    query
+      .order(users::id.asc())
+      .limit(limit.unwrap_or(DEFAULT_LIMIT).max(0).into())
+      .offset(offset.unwrap_or(0).max(0).into())
+      .load::<Account>(ctx.get_connection())?,
+  )
+}
+
+/// Applies `update` to another user's account and audits it. Administrators
+/// can't change their own account this way, so there's always one left.
+fn update_account<F>(
+  ctx: &GQLContext<DBConnection>,
+  user: i32,
+  update: F,
+) -> FieldResult<bool, WundergraphScalarValue>
+where
+  F: FnOnce(&DBConnection) -> QueryResult<usize>,
+{
+  let admin = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  if admin == user {
+    return Err(TRCError::Unauthorized.into());
+  }
+
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let before = UserRow::load(conn, &[user])?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let updated = update(conn)?;
+    diesel::update(users::table.filter(users::id.eq(user)))
+      .set(users::updated_at.eq(time))
+      .execute(conn)?;
+    Audit::new(Some(admin), user, time).updated(conn, before)?;
+    Ok(updated > 0)
+  })
+}
+
+/// Applies `update` to a language and audits it. Languages belong to nobody,
+/// so the entry goes to the administrator who made the change.
+fn update_language<F>(
+  ctx: &GQLContext<DBConnection>,
+  id: i32,
+  update: F,
+) -> FieldResult<bool, WundergraphScalarValue>
+where
+  F: FnOnce(&DBConnection) -> QueryResult<usize>,
+{
+  let admin = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let before = LanguageRow::load(conn, &[id])?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let updated = update(conn)?;
+    Audit::new(Some(admin), admin, time).updated(conn, before)?;
+    Ok(updated > 0)
+  })
+}
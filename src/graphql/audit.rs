@@ -0,0 +1,56 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{authorization::UserRole, GQLContext};
+use crate::{
+  db::{audit::Operation, changes::Entity, schema::audit_log, DBConnection},
+  TRCError,
+};
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct AuditEntry {
+  id: i64,
+  /// The user who made the change, if they were logged in
+  actor: Option<i32>,
+  owner: i32,
+  entity: Entity,
+  entity_id: i32,
+  operation: Operation,
+  /// JSON of the row before the change
+  before: Option<String>,
+  /// JSON of the row after the change
+  after: Option<String>,
+  created_at: i64,
+}
+
+const DEFAULT_LIMIT: i32 = 100;
+
+/// The caller's audit trail, newest first. Administrators see everyone's.
+pub fn list(
+  ctx: &GQLContext<DBConnection>,
+  entity: Option<Entity>,
+  entity_id: Option<i32>,
+  limit: Option<i32>,
+) -> FieldResult<Vec<AuditEntry>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+
+  let mut query = audit_log::table.into_boxed();
+  if ctx.role != UserRole::ADMIN {
+    query = query.filter(audit_log::owner.eq(user_id));
+  }
+  if let Some(entity) = entity {
+    query = query.filter(audit_log::entity.eq(entity));
+  }
+  if let Some(entity_id) = entity_id {
+    query = query.filter(audit_log::entity_id.eq(entity_id));
+  }
+
+  Ok(
+    query
+      .order(audit_log::id.desc())
+      .limit(limit.unwrap_or(DEFAULT_LIMIT).max(0).into())
+      .load::<AuditEntry>(ctx.get_connection())?,
+  )
+}
@@ -0,0 +1,295 @@
+use diesel::{
+  backend::Backend,
+  deserialize::{self, FromSql},
+  prelude::*,
+  serialize::{self, ToSql},
+  sql_types::SmallInt,
+};
+use std::io::Write;
+use wundergraph::query_builder::types::WundergraphValue;
+
+use crate::{
+  db::{
+    changes::{self, ChangeKind},
+    schema::{cards, deck_members, decks, group_assignments, group_members},
+    DBConnection,
+  },
+  TRCError,
+};
+
+/// What a user may do across the whole service
+#[derive(
+  Debug,
+  Copy,
+  Clone,
+  AsExpression,
+  FromSqlRow,
+  GraphQLEnum,
+  WundergraphValue,
+  Eq,
+  PartialEq,
+  Hash,
+  Serialize,
+  Deserialize,
+  Default,
+)]
+#[sql_type = "SmallInt"]
+pub enum UserRole {
+  #[default]
+  USER = 0,
+  /// Manages accounts and languages
+  ADMIN = 1,
+}
+
+impl<DB> ToSql<SmallInt, DB> for UserRole
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for UserRole
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => UserRole::USER,
+      1 => UserRole::ADMIN,
+      _ => return Err(format!("Unknown user role {}", value).into()),
+    })
+  }
+}
+
+/// What a deck member who isn't the owner may do
+#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, Eq, PartialEq)]
+#[sql_type = "SmallInt"]
+pub enum DeckRole {
+  /// Study the deck and keep their own scores
+  VIEWER = 0,
+  /// Also add, change and remove cards
+  EDITOR = 1,
+}
+
+impl<DB> ToSql<SmallInt, DB> for DeckRole
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for DeckRole
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => DeckRole::VIEWER,
+      1 => DeckRole::EDITOR,
+      _ => return Err(format!("Unknown deck role {}", value).into()),
+    })
+  }
+}
+
+/// What a member of a group does in it
+#[derive(
+  Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, Eq, PartialEq, Ord, PartialOrd,
+)]
+#[sql_type = "SmallInt"]
+pub enum GroupRole {
+  /// Studies the decks assigned to the group
+  STUDENT = 0,
+  /// Also manages members and assignments, and follows the students' progress
+  TEACHER = 1,
+}
+
+impl<DB> ToSql<SmallInt, DB> for GroupRole
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for GroupRole
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => GroupRole::STUDENT,
+      1 => GroupRole::TEACHER,
+      _ => return Err(format!("Unknown group role {}", value).into()),
+    })
+  }
+}
+
+/// Levels of access to a deck, each including the ones before it
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Access {
+  View,
+  Edit,
+  Own,
+}
+
+impl From<DeckRole> for Access {
+  fn from(role: DeckRole) -> Self {
+    match role {
+      DeckRole::VIEWER => Access::View,
+      DeckRole::EDITOR => Access::Edit,
+    }
+  }
+}
+
+/// The user's access to a deck along with the deck's owner, or `None` if the
+/// user is neither the owner nor a member who accepted an invitation. Members
+/// of groups the deck is assigned to may view it.
+pub fn deck_access(
+  conn: &DBConnection,
+  user_id: i32,
+  deck_id: i32,
+) -> QueryResult<Option<(Access, i32)>> {
+  let owner = decks::table
+    .select(decks::owner)
+    .filter(decks::id.eq(deck_id))
+    .filter(decks::deleted_at.is_null())
+    .get_result::<i32>(conn)?;
+  if owner == user_id {
+    return Ok(Some((Access::Own, owner)));
+  }
+
+  let role = deck_members::table
+    .select(deck_members::role)
+    .filter(deck_members::deck.eq(deck_id))
+    .filter(deck_members::user_id.eq(user_id))
+    .filter(deck_members::accepted.eq(true))
+    .get_result::<DeckRole>(conn)
+    .optional()?;
+  if let Some(role) = role {
+    return Ok(Some((role.into(), owner)));
+  }
+
+  let assigned = group_assignments::table
+    .inner_join(group_members::table.on(group_members::group_id.eq(group_assignments::group_id)))
+    .select(group_assignments::id)
+    .filter(group_assignments::deck.eq(deck_id))
+    .filter(group_members::user_id.eq(user_id))
+    .filter(group_members::accepted.eq(true))
+    .first::<i32>(conn)
+    .optional()?;
+  Ok(assigned.map(|_| (Access::View, owner)))
+}
+
+/// Decks the user owns, or shares as a member or through a group, that is
+/// every deck not in the trash `deck_access` grants access to
+pub fn accessible_decks(conn: &DBConnection, user_id: i32) -> QueryResult<Vec<i32>> {
+  let mut deck_ids = decks::table
+    .select(decks::id)
+    .filter(decks::owner.eq(user_id))
+    .filter(decks::deleted_at.is_null())
+    .load::<i32>(conn)?;
+  deck_ids.extend(
+    deck_members::table
+      .inner_join(decks::table)
+      .select(decks::id)
+      .filter(deck_members::user_id.eq(user_id))
+      .filter(deck_members::accepted.eq(true))
+      .filter(decks::deleted_at.is_null())
+      .load::<i32>(conn)?,
+  );
+  deck_ids.extend(
+    group_assignments::table
+      .inner_join(decks::table)
+      .inner_join(group_members::table.on(group_members::group_id.eq(group_assignments::group_id)))
+      .select(decks::id)
+      .filter(group_members::user_id.eq(user_id))
+      .filter(group_members::accepted.eq(true))
+      .filter(decks::deleted_at.is_null())
+      .load::<i32>(conn)?,
+  );
+  deck_ids.sort_unstable();
+  deck_ids.dedup();
+  Ok(deck_ids)
+}
+
+/// Records the decks in `deck_ids` under `user` after their access to them
+/// changed, as upserts if they can still see a deck and deletions otherwise
+pub fn record_access(
+  conn: &DBConnection,
+  user: i32,
+  deck_ids: &[i32],
+  time: i64,
+) -> QueryResult<()> {
+  for deck_id in deck_ids {
+    let kind = match deck_access(conn, user, *deck_id).optional()? {
+      Some(Some((Access::Own, _))) => continue,
+      Some(Some(_)) => ChangeKind::Upsert,
+      Some(None) | None => ChangeKind::Delete,
+    };
+    changes::record_access(conn, user, *deck_id, kind, time)?;
+  }
+  Ok(())
+}
+
+/// Checks the user has at least `needed` access to the deck and returns the
+/// deck owner, whose change log and audit trail the change belongs to.
+pub fn authorize_deck(
+  conn: &DBConnection,
+  user_id: i32,
+  deck_id: i32,
+  needed: Access,
+) -> Result<i32, TRCError> {
+  match deck_access(conn, user_id, deck_id)? {
+    Some((access, owner)) if access >= needed => Ok(owner),
+    _ => Err(TRCError::Unauthorized),
+  }
+}
+
+/// Checks the user is a member of the group who accepted the invitation,
+/// with at least the `needed` role
+pub fn authorize_group(
+  conn: &DBConnection,
+  user_id: i32,
+  group_id: i32,
+  needed: GroupRole,
+) -> Result<(), TRCError> {
+  let role = group_members::table
+    .select(group_members::role)
+    .filter(group_members::group_id.eq(group_id))
+    .filter(group_members::user_id.eq(user_id))
+    .filter(group_members::accepted.eq(true))
+    .get_result::<GroupRole>(conn)
+    .optional()?;
+  match role {
+    Some(role) if role >= needed => Ok(()),
+    _ => Err(TRCError::Unauthorized),
+  }
+}
+
+pub fn authorize_card(
+  conn: &DBConnection,
+  user_id: i32,
+  card_id: i32,
+  needed: Access,
+) -> Result<i32, TRCError> {
+  let deck = cards::table
+    .select(cards::deck)
+    .filter(cards::id.eq(card_id))
+    .filter(cards::deleted_at.is_null())
+    .get_result::<i32>(conn)?;
+  authorize_deck(conn, user_id, deck, needed)
+}
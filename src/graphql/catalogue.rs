@@ -0,0 +1,284 @@
+use diesel::{
+  prelude::*,
+  sql_query,
+  sql_types::{BigInt, Integer, Nullable, Text},
+};
+use juniper::FieldResult;
+use std::{
+  collections::HashMap,
+  time::{SystemTime, UNIX_EPOCH},
+};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{authorization::deck_access, mutations::utilities::check_language, GQLContext};
+use crate::{
+  db::{
+    audit::Audit,
+    changes::{self, ChangeKind},
+    models::{CardRow, DeckRow, SetRow},
+    schema::{backs, cards, decks, set_cards, sets},
+    DBConnection,
+  },
+  media, TRCError,
+};
+
+/// A published deck as listed in the catalogue
+#[derive(Debug, Clone, QueryableByName, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct PublicDeck {
+  #[sql_type = "Integer"]
+  id: i32,
+  #[sql_type = "Text"]
+  name: String,
+  #[sql_type = "Nullable<Text>"]
+  description: Option<String>,
+  #[sql_type = "Nullable<Text>"]
+  licence: Option<String>,
+  #[sql_type = "Integer"]
+  language: i32,
+  #[sql_type = "Text"]
+  language_name: String,
+  /// Username of the author
+  #[sql_type = "Text"]
+  owner: String,
+  #[sql_type = "BigInt"]
+  card_count: i64,
+  /// How many times the deck has been cloned
+  #[sql_type = "BigInt"]
+  clones: i64,
+}
+
+const DEFAULT_LIMIT: i32 = 50;
+
+/// Published decks, most cloned first. `language` matches a language's name
+/// or abbreviation, `search` the deck's name and description.
+pub fn public_decks(
+  ctx: &GQLContext<DBConnection>,
+  language: Option<String>,
+  search: Option<String>,
+  limit: Option<i32>,
+  offset: Option<i32>,
+) -> FieldResult<Vec<PublicDeck>, WundergraphScalarValue> {
+  let search = search.map(|search| {
+    format!(
+      "%{}%",
+      search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+    )
+  });
+
+  Ok(
+    sql_query(
+      "SELECT decks.id, decks.name, decks.description, decks.licence, decks.language, \
+       languages.name AS language_name, users.username AS owner, \
+       (SELECT COUNT(*) FROM cards WHERE cards.deck = decks.id AND cards.deleted_at IS NULL) \
+       AS card_count, \
+       (SELECT COUNT(*) FROM decks AS clones WHERE clones.source = decks.id) AS clones \
+       FROM decks \
+       INNER JOIN languages ON languages.id = decks.language \
+       INNER JOIN users ON users.id = decks.owner \
+       WHERE decks.published AND decks.deleted_at IS NULL \
+       AND ($1 IS NULL OR LOWER(languages.abbreviation) = LOWER($1) \
+       OR LOWER(languages.name) = LOWER($1)) \
+       AND ($2 IS NULL OR decks.name ILIKE $2 OR decks.description ILIKE $2) \
+       ORDER BY clones DESC, card_count DESC, decks.id \
+       LIMIT $3 OFFSET $4",
+    )
+    .bind::<Nullable<Text>, _>(language)
+    .bind::<Nullable<Text>, _>(search)
+    .bind::<Integer, _>(limit.unwrap_or(DEFAULT_LIMIT).max(0))
+    .bind::<Integer, _>(offset.unwrap_or(0).max(0))
+    .load::<PublicDeck>(ctx.get_connection())?,
+  )
+}
+
+#[derive(Queryable)]
+pub(super) struct SourceCard {
+  pub id: i32,
+  pub front: String,
+  pub link: Option<String>,
+  pub version: i32,
+  pub front_language: Option<i32>,
+  pub front_audio: Option<String>,
+  pub front_audio_media: Option<i32>,
+  pub text: String,
+  pub language: i32,
+  pub audio: Option<String>,
+  pub image: Option<String>,
+  pub audio_media: Option<i32>,
+  pub image_media: Option<i32>,
+  pub image_thumbnail: Option<String>,
+  pub image_card: Option<String>,
+}
+
+/// The cards of a deck that aren't in the trash, along with their backs
+pub(super) fn source_cards(conn: &DBConnection, deck: i32) -> QueryResult<Vec<SourceCard>> {
+  cards::table
+    .inner_join(backs::table)
+    .select((
+      cards::id,
+      cards::front,
+      cards::link,
+      cards::version,
+      cards::front_language,
+      cards::front_audio,
+      cards::front_audio_media,
+      backs::text,
+      backs::language,
+      backs::audio,
+      backs::image,
+      backs::audio_media,
+      backs::image_media,
+      backs::image_thumbnail,
+      backs::image_card,
+    ))
+    .filter(cards::deck.eq(deck))
+    .filter(cards::deleted_at.is_null())
+    .order(cards::id.asc())
+    .load::<SourceCard>(conn)
+}
+
+/// Copies a card and its back into `deck`, remembering which card it came from.
+pub(super) fn copy_card(
+  conn: &DBConnection,
+  card: &SourceCard,
+  deck: i32,
+  time: i64,
+) -> QueryResult<i32> {
+  media::lock_shared(conn)?;
+  let back = diesel::insert_into(backs::table)
+    .values((
+      backs::text.eq(&card.text),
+      backs::language.eq(card.language),
+      backs::audio.eq(&card.audio),
+      backs::image.eq(&card.image),
+      backs::audio_media.eq(card.audio_media),
+      backs::image_media.eq(card.image_media),
+      backs::image_thumbnail.eq(&card.image_thumbnail),
+      backs::image_card.eq(&card.image_card),
+      backs::updated_at.eq(time),
+    ))
+    .returning(backs::id)
+    .get_result::<i32>(conn)?;
+  diesel::insert_into(cards::table)
+    .values((
+      cards::front.eq(&card.front),
+      cards::back.eq(back),
+      cards::deck.eq(deck),
+      cards::link.eq(&card.link),
+      cards::created_at.eq(time),
+      cards::updated_at.eq(time),
+      cards::origin.eq(card.id),
+      cards::origin_version.eq(card.version),
+      cards::merged_version.eq(1),
+      cards::front_language.eq(card.front_language),
+      cards::front_audio.eq(&card.front_audio),
+      cards::front_audio_media.eq(card.front_audio_media),
+    ))
+    .returning(cards::id)
+    .get_result::<i32>(conn)
+}
+
+/// Copies a published deck, or one shared with the caller, into the caller's
+/// account. Backs keep pointing at the same media files. Scores are not
+/// copied, so the clone starts out unstudied. Each copy remembers the card it
+/// came from, see `updates`. Returns the new deck's id.
+pub fn clone_deck(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+  name: Option<String>,
+  include_sets: bool,
+) -> FieldResult<i32, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let source = decks::table
+      .select(DeckRow::COLUMNS)
+      .filter(decks::id.eq(deck))
+      .filter(decks::deleted_at.is_null())
+      .get_result::<DeckRow>(conn)?;
+    if !source.published && deck_access(conn, user_id, deck)?.is_none() {
+      return Err(TRCError::Unauthorized.into());
+    }
+
+    check_language(conn, source.language)?;
+    if let Some(language) = source.front_language {
+      check_language(conn, language)?;
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let cloned = diesel::insert_into(decks::table)
+      .values((
+        decks::name.eq(name.unwrap_or(source.name)),
+        decks::owner.eq(user_id),
+        decks::language.eq(source.language),
+        decks::description.eq(source.description),
+        decks::licence.eq(source.licence),
+        decks::front_language.eq(source.front_language),
+        decks::source.eq(deck),
+        decks::updated_at.eq(time),
+      ))
+      .returning(decks::id)
+      .get_result::<i32>(conn)?;
+
+    let mut copies = HashMap::new();
+    for card in source_cards(conn, deck)? {
+      copies.insert(card.id, copy_card(conn, &card, cloned, time)?);
+    }
+
+    let mut set_ids = vec![];
+    if include_sets {
+      // Only the author's sets, the ones members made for themselves stay private
+      let source_sets = sets::table
+        .select((sets::id, sets::name))
+        .filter(sets::deck.eq(deck))
+        .filter(sets::owner.eq(source.owner))
+        .filter(sets::deleted_at.is_null())
+        .order(sets::id.asc())
+        .load::<(i32, String)>(conn)?;
+
+      for (set_id, set_name) in source_sets {
+        let copy = diesel::insert_into(sets::table)
+          .values((
+            sets::name.eq(set_name),
+            sets::deck.eq(cloned),
+            sets::owner.eq(user_id),
+            sets::created_at.eq(time),
+            sets::updated_at.eq(time),
+          ))
+          .returning(sets::id)
+          .get_result::<i32>(conn)?;
+        let members = set_cards::table
+          .select(set_cards::card_id)
+          .filter(set_cards::set_id.eq(set_id))
+          .load::<i32>(conn)?;
+        diesel::insert_into(set_cards::table)
+          .values(
+            members
+              .iter()
+              .filter_map(|card| copies.get(card))
+              .map(|card| {
+                (
+                  set_cards::card_id.eq(card),
+                  set_cards::set_id.eq(copy),
+                  set_cards::updated_at.eq(time),
+                )
+              })
+              .collect::<Vec<_>>(),
+          )
+          .execute(conn)?;
+        set_ids.push(copy);
+      }
+    }
+
+    changes::record_deck(conn, user_id, cloned, ChangeKind::Upsert, time)?;
+    let audit = Audit::new(Some(user_id), user_id, time);
+    audit.inserted::<DeckRow>(conn, &[cloned])?;
+    audit.inserted::<CardRow>(conn, &copies.values().cloned().collect::<Vec<_>>())?;
+    audit.inserted::<SetRow>(conn, &set_ids)?;
+
+    Ok(cloned)
+  })
+}
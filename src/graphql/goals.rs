@@ -0,0 +1,302 @@
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::{
+  backend::Backend,
+  deserialize::{self, FromSql},
+  prelude::*,
+  serialize::{self, ToSql},
+  sql_query,
+  sql_types::{BigInt, Integer, SmallInt, Text},
+};
+use juniper::FieldResult;
+use std::{
+  io::Write,
+  time::{SystemTime, UNIX_EPOCH},
+};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::GQLContext;
+use crate::{
+  db::{
+    schema::{achievements, users},
+    DBConnection,
+  },
+  TRCError,
+};
+
+/// Milestones a user can reach, each only once
+#[derive(Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, Eq, PartialEq, Hash)]
+#[sql_type = "SmallInt"]
+pub enum AchievementKind {
+  FirstReview = 0,
+  HundredReviews = 1,
+  ThousandReviews = 2,
+  WeekStreak = 3,
+  MonthStreak = 4,
+  HundredDayStreak = 5,
+  /// Reached every daily goal set for a day
+  DailyGoal = 6,
+}
+
+impl<DB> ToSql<SmallInt, DB> for AchievementKind
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for AchievementKind
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => AchievementKind::FirstReview,
+      1 => AchievementKind::HundredReviews,
+      2 => AchievementKind::ThousandReviews,
+      3 => AchievementKind::WeekStreak,
+      4 => AchievementKind::MonthStreak,
+      5 => AchievementKind::HundredDayStreak,
+      6 => AchievementKind::DailyGoal,
+      _ => return Err(format!("Unknown achievement {}", value).into()),
+    })
+  }
+}
+
+const REVIEW_ACHIEVEMENTS: [(i64, AchievementKind); 3] = [
+  (1, AchievementKind::FirstReview),
+  (100, AchievementKind::HundredReviews),
+  (1000, AchievementKind::ThousandReviews),
+];
+
+const STREAK_ACHIEVEMENTS: [(i32, AchievementKind); 3] = [
+  (7, AchievementKind::WeekStreak),
+  (30, AchievementKind::MonthStreak),
+  (100, AchievementKind::HundredDayStreak),
+];
+
+/// The day of a score in the user's time zone, which is bound to `$2`
+const SCORE_DAY: &str = "(to_timestamp(scores.created_at / 1000.0) AT TIME ZONE $2)::date";
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct Achievement {
+  kind: AchievementKind,
+  unlocked_at: i64,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct Progress {
+  /// IANA time zone days are counted in
+  timezone: String,
+  /// The current day in the user's time zone, as YYYY-MM-DD
+  today: String,
+  reviews_today: i32,
+  /// Cards scored today for the first time
+  new_cards_today: i32,
+  daily_review_goal: Option<i32>,
+  daily_new_card_goal: Option<i32>,
+  /// A goal is set and every goal set has been reached today
+  goal_met: bool,
+  /// Days in a row with at least one review, up to today. A streak that
+  /// ended yesterday still counts until today is over.
+  current_streak: i32,
+  longest_streak: i32,
+  achievements: Vec<Achievement>,
+}
+
+#[derive(QueryableByName)]
+struct Totals {
+  #[sql_type = "BigInt"]
+  reviews: i64,
+  #[sql_type = "Integer"]
+  reviews_today: i32,
+  #[sql_type = "Integer"]
+  new_cards_today: i32,
+}
+
+/// Days in a row with reviews
+#[derive(QueryableByName)]
+struct Streak {
+  /// The last day of the streak, as YYYY-MM-DD
+  #[sql_type = "Text"]
+  last_day: String,
+  #[sql_type = "Integer"]
+  length: i32,
+}
+
+/// A user's reviews summed up, with days counted in their time zone
+struct Activity {
+  timezone: String,
+  today: NaiveDate,
+  daily_review_goal: Option<i32>,
+  daily_new_card_goal: Option<i32>,
+  reviews: i64,
+  reviews_today: i32,
+  new_cards_today: i32,
+  current_streak: i32,
+  longest_streak: i32,
+}
+
+impl Activity {
+  fn load(conn: &DBConnection, user_id: i32, now: i64) -> QueryResult<Self> {
+    let (timezone, daily_review_goal, daily_new_card_goal) = users::table
+      .select((
+        users::timezone,
+        users::daily_review_goal,
+        users::daily_new_card_goal,
+      ))
+      .filter(users::id.eq(user_id))
+      .get_result::<(String, Option<i32>, Option<i32>)>(conn)?;
+    // Unknown zones count days in UTC, both here and in the database
+    let (tz, zone) = match timezone.parse::<Tz>() {
+      Ok(tz) => (tz, timezone.as_str()),
+      Err(_) => (Tz::UTC, "UTC"),
+    };
+    let today = Utc
+      .timestamp_millis(now)
+      .with_timezone(&tz)
+      .naive_local()
+      .date();
+    let today_text = today.format("%Y-%m-%d").to_string();
+
+    let totals = sql_query(format!(
+      "SELECT COUNT(*) AS reviews, \
+       (COUNT(*) FILTER (WHERE {day} = $3::date))::int AS reviews_today, \
+       (SELECT COUNT(*) FROM (SELECT scores.card FROM scores WHERE scores.owner = $1 \
+       GROUP BY scores.card HAVING MIN({day}) = $3::date) AS first_reviews)::int \
+       AS new_cards_today \
+       FROM scores WHERE scores.owner = $1",
+      day = SCORE_DAY
+    ))
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(zone)
+    .bind::<Text, _>(&today_text)
+    .get_result::<Totals>(conn)?;
+
+    // Days in a row share the difference between the day and its rank
+    let streaks = sql_query(format!(
+      "SELECT to_char(MAX(day), 'YYYY-MM-DD') AS last_day, COUNT(*)::int AS length \
+       FROM (SELECT day, day - (ROW_NUMBER() OVER (ORDER BY day))::int AS streak \
+       FROM (SELECT DISTINCT {day} AS day FROM scores WHERE scores.owner = $1) AS days) \
+       AS ranked \
+       GROUP BY streak ORDER BY MAX(day) DESC",
+      day = SCORE_DAY
+    ))
+    .bind::<Integer, _>(user_id)
+    .bind::<Text, _>(zone)
+    .load::<Streak>(conn)?;
+    let current_streak = streaks
+      .first()
+      .filter(|streak| {
+        matches!(
+          NaiveDate::parse_from_str(&streak.last_day, "%Y-%m-%d"),
+          Ok(last) if last == today || last + Duration::days(1) == today
+        )
+      })
+      .map_or(0, |streak| streak.length);
+    let longest_streak = streaks
+      .iter()
+      .map(|streak| streak.length)
+      .max()
+      .unwrap_or(0);
+
+    Ok(Activity {
+      timezone,
+      today,
+      daily_review_goal,
+      daily_new_card_goal,
+      reviews: totals.reviews,
+      reviews_today: totals.reviews_today,
+      new_cards_today: totals.new_cards_today,
+      current_streak,
+      longest_streak,
+    })
+  }
+
+  /// A goal is set and every goal set has been reached today
+  fn goal_met(&self) -> bool {
+    let goals = [
+      (self.daily_review_goal, self.reviews_today),
+      (self.daily_new_card_goal, self.new_cards_today),
+    ];
+    goals.iter().any(|(goal, _)| goal.is_some())
+      && goals
+        .iter()
+        .all(|(goal, done)| !matches!(goal, Some(goal) if done < goal))
+  }
+}
+
+/// Unlocks the achievements the user's reviews have reached, called when
+/// scores are added. Achievements already unlocked keep their time.
+pub fn unlock_achievements(conn: &DBConnection, user_id: i32, time: i64) -> QueryResult<()> {
+  let activity = Activity::load(conn, user_id, time)?;
+  let mut unlocked = REVIEW_ACHIEVEMENTS
+    .iter()
+    .filter(|(count, _)| activity.reviews >= *count)
+    .map(|(_, kind)| *kind)
+    .chain(
+      STREAK_ACHIEVEMENTS
+        .iter()
+        .filter(|(length, _)| activity.longest_streak >= *length)
+        .map(|(_, kind)| *kind),
+    )
+    .collect::<Vec<_>>();
+  if activity.goal_met() {
+    unlocked.push(AchievementKind::DailyGoal);
+  }
+  if unlocked.is_empty() {
+    return Ok(());
+  }
+
+  diesel::insert_into(achievements::table)
+    .values(
+      unlocked
+        .into_iter()
+        .map(|kind| {
+          (
+            achievements::user_id.eq(user_id),
+            achievements::kind.eq(kind),
+            achievements::unlocked_at.eq(time),
+          )
+        })
+        .collect::<Vec<_>>(),
+    )
+    .on_conflict((achievements::user_id, achievements::kind))
+    .do_nothing()
+    .execute(conn)?;
+  Ok(())
+}
+
+/// The caller's progress towards their daily goals and their streaks, along
+/// with the achievements they unlocked
+pub fn progress(ctx: &GQLContext<DBConnection>) -> FieldResult<Progress, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+  let activity = Activity::load(conn, user_id, now)?;
+  let achievements = achievements::table
+    .select((achievements::kind, achievements::unlocked_at))
+    .filter(achievements::user_id.eq(user_id))
+    .order((achievements::unlocked_at.asc(), achievements::kind.asc()))
+    .load::<Achievement>(conn)?;
+
+  Ok(Progress {
+    goal_met: activity.goal_met(),
+    today: activity.today.format("%Y-%m-%d").to_string(),
+    timezone: activity.timezone,
+    reviews_today: activity.reviews_today,
+    new_cards_today: activity.new_cards_today,
+    daily_review_goal: activity.daily_review_goal,
+    daily_new_card_goal: activity.daily_new_card_goal,
+    current_streak: activity.current_streak,
+    longest_streak: activity.longest_streak,
+    achievements,
+  })
+}
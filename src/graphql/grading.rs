@@ -0,0 +1,204 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_card, Access},
+  query::ScoreValue,
+  GQLContext,
+};
+use crate::{
+  db::{
+    schema::{backs, cards},
+    DBConnection,
+  },
+  TRCError,
+};
+
+/// Longer answers aren't worth comparing character by character
+const MAX_ANSWER_CHARS: usize = 1000;
+
+/// How part of a typed answer compares to the expected one
+#[derive(Debug, Copy, Clone, GraphQLEnum, Eq, PartialEq)]
+pub enum DiffKind {
+  /// In both answers
+  SAME,
+  /// Only in the expected answer
+  MISSING,
+  /// Only in the typed answer
+  EXTRA,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct DiffPart {
+  kind: DiffKind,
+  text: String,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct AnswerCheck {
+  expected: String,
+  /// The answers match when case, accents, punctuation and small typos are
+  /// ignored
+  correct: bool,
+  /// Edits between the answers once case, accents and punctuation are ignored
+  distance: i32,
+  /// The typed answer, character by character, against the expected one
+  diff: Vec<DiffPart>,
+  /// A score for the answer that can be recorded as it is
+  score: ScoreValue,
+}
+
+/// Grades an answer typed for the back of a card the user may study
+pub fn check_answer(
+  ctx: &GQLContext<DBConnection>,
+  card: i32,
+  typed: String,
+) -> FieldResult<AnswerCheck, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_card(conn, user_id, card, Access::View)?;
+  if typed.chars().count() > MAX_ANSWER_CHARS {
+    return Err(
+      TRCError::Unknown(format!(
+        "Answers can't be longer than {} characters",
+        MAX_ANSWER_CHARS
+      ))
+      .into(),
+    );
+  }
+
+  let expected = cards::table
+    .inner_join(backs::table)
+    .select(backs::text)
+    .filter(cards::id.eq(card))
+    .get_result::<String>(conn)?;
+  Ok(check(&expected, &typed))
+}
+
+fn check(expected: &str, typed: &str) -> AnswerCheck {
+  let expected = normalise(expected);
+  let typed = normalise(typed);
+  let expected_folded = fold(&expected);
+  let typed_folded = fold(&typed);
+  let distance = distance(&typed_folded, &expected_folded);
+  // One typo for every five characters
+  let tolerance = expected_folded.len() / 5;
+
+  let score = if typed == expected {
+    ScoreValue::FIVE
+  } else if distance == 0 {
+    ScoreValue::FOUR
+  } else if distance <= tolerance {
+    ScoreValue::THREE
+  } else if typed_folded.is_empty() {
+    ScoreValue::ZERO
+  } else if distance * 2 <= expected_folded.len().max(typed_folded.len()) {
+    ScoreValue::TWO
+  } else {
+    ScoreValue::ONE
+  };
+
+  let diff = diff(
+    &typed.chars().collect::<Vec<_>>(),
+    &expected.chars().collect::<Vec<_>>(),
+  );
+  AnswerCheck {
+    expected,
+    correct: distance <= tolerance,
+    distance: distance as i32,
+    diff,
+    score,
+  }
+}
+
+/// Composes the text and collapses its whitespace
+fn normalise(text: &str) -> String {
+  text
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .nfc()
+    .collect()
+}
+
+/// Lower case letters, digits and spaces, without accents
+fn fold(text: &str) -> Vec<char> {
+  let folded = text
+    .nfd()
+    .filter(|c| !is_combining_mark(*c))
+    .flat_map(char::to_lowercase)
+    .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+    .collect::<String>();
+  folded
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .chars()
+    .collect()
+}
+
+/// Insertions, deletions, substitutions and swaps of neighbours needed to
+/// turn `a` into `b`
+fn distance(a: &[char], b: &[char]) -> usize {
+  let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+  for (i, row) in rows.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, cell) in rows[0].iter_mut().enumerate() {
+    *cell = j;
+  }
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let substitution = rows[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+      let mut best = substitution.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        best = best.min(rows[i - 2][j - 2] + 1);
+      }
+      rows[i][j] = best;
+    }
+  }
+  rows[a.len()][b.len()]
+}
+
+/// What to keep, drop and add to turn the typed answer into the expected one
+fn diff(typed: &[char], expected: &[char]) -> Vec<DiffPart> {
+  // Lengths of the longest common subsequences of every pair of suffixes
+  let mut common = vec![vec![0; expected.len() + 1]; typed.len() + 1];
+  for i in (0..typed.len()).rev() {
+    for j in (0..expected.len()).rev() {
+      common[i][j] = if typed[i] == expected[j] {
+        common[i + 1][j + 1] + 1
+      } else {
+        common[i + 1][j].max(common[i][j + 1])
+      };
+    }
+  }
+
+  let mut parts: Vec<DiffPart> = vec![];
+  let mut push = |kind, c: char| match parts.last_mut() {
+    Some(last) if last.kind == kind => last.text.push(c),
+    _ => parts.push(DiffPart {
+      kind,
+      text: c.to_string(),
+    }),
+  };
+  let (mut i, mut j) = (0, 0);
+  while i < typed.len() || j < expected.len() {
+    if i < typed.len() && j < expected.len() && typed[i] == expected[j] {
+      push(DiffKind::SAME, typed[i]);
+      i += 1;
+      j += 1;
+    } else if i < typed.len() && (j == expected.len() || common[i + 1][j] >= common[i][j + 1]) {
+      push(DiffKind::EXTRA, typed[i]);
+      i += 1;
+    } else {
+      push(DiffKind::MISSING, expected[j]);
+      j += 1;
+    }
+  }
+  parts
+}
@@ -0,0 +1,595 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::{
+  collections::{HashMap, HashSet},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_deck, authorize_group, record_access, Access, GroupRole},
+  query::ScoreValue,
+  GQLContext,
+};
+use crate::{
+  db::{
+    audit::{Audit, Audited},
+    models::{GroupAssignmentRow, GroupMemberRow, GroupRow},
+    schema::{
+      cards, decks, group_assignments, group_members, groups, scores, set_cards, sets, users,
+    },
+    DBConnection,
+  },
+  TRCError,
+};
+
+/// Reviews this recent count towards retention
+const RETENTION_WINDOW: i64 = 30 * 24 * 60 * 60 * 1000;
+const DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct Group {
+  id: i32,
+  name: String,
+  owner: i32,
+  created_at: i64,
+  /// The caller's role in the group
+  role: GroupRole,
+}
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct GroupMember {
+  group: i32,
+  group_name: String,
+  user_id: i32,
+  username: String,
+  role: GroupRole,
+  /// Whether the user has accepted the invitation yet
+  accepted: bool,
+}
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct GroupAssignment {
+  id: i32,
+  group: i32,
+  deck: i32,
+  deck_name: String,
+  /// Only the cards of this set are assigned, when there is one
+  set_id: Option<i32>,
+  assigned_at: i64,
+}
+
+/// How a student is doing on the cards assigned to a group
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct StudentProgress {
+  user_id: i32,
+  username: String,
+  assigned_cards: i32,
+  /// Cards the student scored at least once
+  studied_cards: i32,
+  /// Cards whose latest score is three or more
+  learned_cards: i32,
+  /// Share of the reviews of the last 30 days scored three or more
+  retention: Option<f64>,
+  /// Studied cards that should have been reviewed again by now
+  overdue_cards: i32,
+  last_review: Option<i64>,
+}
+
+type MemberColumns = (
+  group_members::group_id,
+  groups::name,
+  group_members::user_id,
+  users::username,
+  group_members::role,
+  group_members::accepted,
+);
+
+const MEMBER_COLUMNS: MemberColumns = (
+  group_members::group_id,
+  groups::name,
+  group_members::user_id,
+  users::username,
+  group_members::role,
+  group_members::accepted,
+);
+
+type AssignmentColumns = (
+  group_assignments::id,
+  group_assignments::group_id,
+  group_assignments::deck,
+  decks::name,
+  group_assignments::set_id,
+  group_assignments::assigned_at,
+);
+
+const ASSIGNMENT_COLUMNS: AssignmentColumns = (
+  group_assignments::id,
+  group_assignments::group_id,
+  group_assignments::deck,
+  decks::name,
+  group_assignments::set_id,
+  group_assignments::assigned_at,
+);
+
+fn group_owner(conn: &DBConnection, group: i32) -> QueryResult<i32> {
+  groups::table
+    .select(groups::owner)
+    .filter(groups::id.eq(group))
+    .get_result::<i32>(conn)
+}
+
+/// Decks assigned to the group, directly or through one of their sets
+fn assigned_decks(conn: &DBConnection, group: i32) -> QueryResult<Vec<i32>> {
+  group_assignments::table
+    .select(group_assignments::deck)
+    .filter(group_assignments::group_id.eq(group))
+    .distinct()
+    .load::<i32>(conn)
+}
+
+/// Members of the group who accepted their invitation
+fn accepted_members(conn: &DBConnection, group: i32) -> QueryResult<Vec<i32>> {
+  group_members::table
+    .select(group_members::user_id)
+    .filter(group_members::group_id.eq(group))
+    .filter(group_members::accepted.eq(true))
+    .load::<i32>(conn)
+}
+
+/// A user's membership of a group, along with the owner of the group
+fn membership(
+  conn: &DBConnection,
+  group: i32,
+  user: i32,
+) -> QueryResult<Option<(GroupMemberRow, i32)>> {
+  group_members::table
+    .inner_join(groups::table)
+    .select((GroupMemberRow::COLUMNS, groups::owner))
+    .filter(group_members::group_id.eq(group))
+    .filter(group_members::user_id.eq(user))
+    .get_result(conn)
+    .optional()
+}
+
+/// Creates a group with the caller as its teacher
+pub fn create(
+  ctx: &GQLContext<DBConnection>,
+  name: String,
+) -> FieldResult<Group, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let id = diesel::insert_into(groups::table)
+      .values((
+        groups::name.eq(&name),
+        groups::owner.eq(user_id),
+        groups::created_at.eq(time),
+      ))
+      .returning(groups::id)
+      .get_result::<i32>(conn)?;
+    let member = diesel::insert_into(group_members::table)
+      .values((
+        group_members::group_id.eq(id),
+        group_members::user_id.eq(user_id),
+        group_members::role.eq(GroupRole::TEACHER),
+        group_members::accepted.eq(true),
+        group_members::created_at.eq(time),
+      ))
+      .returning(group_members::id)
+      .get_result::<i32>(conn)?;
+    let audit = Audit::new(Some(user_id), user_id, time);
+    audit.inserted::<GroupRow>(conn, &[id])?;
+    audit.inserted::<GroupMemberRow>(conn, &[member])?;
+    Ok(Group {
+      id,
+      name,
+      owner: user_id,
+      created_at: time,
+      role: GroupRole::TEACHER,
+    })
+  })
+}
+
+/// Groups the caller teaches or studies in
+pub fn list(ctx: &GQLContext<DBConnection>) -> FieldResult<Vec<Group>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  Ok(
+    group_members::table
+      .inner_join(groups::table)
+      .select((
+        groups::id,
+        groups::name,
+        groups::owner,
+        groups::created_at,
+        group_members::role,
+      ))
+      .filter(group_members::user_id.eq(user_id))
+      .filter(group_members::accepted.eq(true))
+      .order(groups::name.asc())
+      .load::<Group>(ctx.get_connection())?,
+  )
+}
+
+/// Members of a group, visible to everyone in it
+pub fn members(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+) -> FieldResult<Vec<GroupMember>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_group(conn, user_id, group, GroupRole::STUDENT)?;
+
+  Ok(
+    group_members::table
+      .inner_join(groups::table)
+      .inner_join(users::table)
+      .select(MEMBER_COLUMNS)
+      .filter(group_members::group_id.eq(group))
+      .order((group_members::role.desc(), users::username.asc()))
+      .load::<GroupMember>(conn)?,
+  )
+}
+
+/// Group invitations the caller hasn't answered yet
+pub fn invitations(
+  ctx: &GQLContext<DBConnection>,
+) -> FieldResult<Vec<GroupMember>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+
+  Ok(
+    group_members::table
+      .inner_join(groups::table)
+      .inner_join(users::table)
+      .select(MEMBER_COLUMNS)
+      .filter(group_members::user_id.eq(user_id))
+      .filter(group_members::accepted.eq(false))
+      .load::<GroupMember>(ctx.get_connection())?,
+  )
+}
+
+/// Invites a user to a group, or changes the role of an existing member.
+/// Only teachers may do this, and nobody can change the role of the owner.
+pub fn invite(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+  username: String,
+  role: GroupRole,
+) -> FieldResult<GroupMember, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    authorize_group(conn, user_id, group, GroupRole::TEACHER)?;
+    let member = users::table
+      .select(users::id)
+      .filter(users::username.eq(&username))
+      .get_result::<i32>(conn)?;
+    let owner = group_owner(conn, group)?;
+    if member == owner {
+      return Err(TRCError::Unknown("The owner of a group stays its teacher".into()).into());
+    }
+
+    let before = membership(conn, group, member)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let id = diesel::insert_into(group_members::table)
+      .values((
+        group_members::group_id.eq(group),
+        group_members::user_id.eq(member),
+        group_members::role.eq(role),
+        group_members::created_at.eq(time),
+      ))
+      .on_conflict((group_members::group_id, group_members::user_id))
+      .do_update()
+      .set(group_members::role.eq(role))
+      .returning(group_members::id)
+      .get_result::<i32>(conn)?;
+    let audit = Audit::new(Some(user_id), owner, time);
+    match before {
+      Some((before, _)) => audit.updated(conn, vec![before])?,
+      None => audit.inserted::<GroupMemberRow>(conn, &[id])?,
+    }
+
+    Ok(
+      group_members::table
+        .inner_join(groups::table)
+        .inner_join(users::table)
+        .select(MEMBER_COLUMNS)
+        .filter(group_members::group_id.eq(group))
+        .filter(group_members::user_id.eq(member))
+        .get_result::<GroupMember>(conn)?,
+    )
+  })
+}
+
+pub fn accept(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let (before, owner) = match membership(conn, group, user_id)? {
+      Some(membership) => membership,
+      None => return Ok(false),
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    diesel::update(group_members::table.filter(group_members::id.eq(before.id)))
+      .set(group_members::accepted.eq(true))
+      .execute(conn)?;
+    Audit::new(Some(user_id), owner, time).updated(conn, vec![before])?;
+    record_access(conn, user_id, &assigned_decks(conn, group)?, time)?;
+    Ok(true)
+  })
+}
+
+/// Removes a member from a group. Teachers may remove students, only the
+/// owner may remove other teachers. Members may remove themselves, which also
+/// declines an invitation.
+pub fn remove(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+  user: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  if user != user_id {
+    authorize_group(conn, user_id, group, GroupRole::TEACHER)?;
+  }
+  conn.transaction(|| {
+    let (before, owner) = match membership(conn, group, user)? {
+      Some(membership) => membership,
+      None => return Ok(false),
+    };
+    if user == owner {
+      return Err(TRCError::Unknown("The owner of a group can't leave it".into()).into());
+    }
+    if user != user_id && user_id != owner && before.role == GroupRole::TEACHER as i16 {
+      return Err(TRCError::Unauthorized.into());
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    diesel::delete(group_members::table.filter(group_members::id.eq(before.id))).execute(conn)?;
+    Audit::new(Some(user_id), owner, time).deleted(conn, vec![before])?;
+    record_access(conn, user, &assigned_decks(conn, group)?, time)?;
+    Ok(true)
+  })
+}
+
+/// Decks and sets assigned to a group, visible to everyone in it
+pub fn assignments(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+) -> FieldResult<Vec<GroupAssignment>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_group(conn, user_id, group, GroupRole::STUDENT)?;
+
+  Ok(
+    group_assignments::table
+      .inner_join(decks::table)
+      .select(ASSIGNMENT_COLUMNS)
+      .filter(group_assignments::group_id.eq(group))
+      .filter(decks::deleted_at.is_null())
+      .order(group_assignments::assigned_at.asc())
+      .load::<GroupAssignment>(conn)?,
+  )
+}
+
+/// Assigns a deck, or one of its sets, to a group, which lets its members
+/// study the deck. Teachers may assign their own decks and published ones.
+pub fn assign(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+  deck: Option<i32>,
+  set: Option<i32>,
+) -> FieldResult<GroupAssignment, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_group(conn, user_id, group, GroupRole::TEACHER)?;
+  let deck = match (deck, set) {
+    (Some(deck), None) => deck,
+    (None, Some(set)) => sets::table
+      .select(sets::deck)
+      .filter(sets::id.eq(set))
+      .filter(sets::deleted_at.is_null())
+      .get_result::<i32>(conn)?,
+    _ => {
+      let message = "Assign either a deck or a set".to_owned();
+      return Err(TRCError::Unknown(message).into());
+    }
+  };
+  let published = decks::table
+    .select(decks::published)
+    .filter(decks::id.eq(deck))
+    .filter(decks::deleted_at.is_null())
+    .get_result::<bool>(conn)?;
+  if !published {
+    authorize_deck(conn, user_id, deck, Access::Own)?;
+  }
+
+  conn.transaction(|| {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let id = diesel::insert_into(group_assignments::table)
+      .values((
+        group_assignments::group_id.eq(group),
+        group_assignments::deck.eq(deck),
+        group_assignments::set_id.eq(set),
+        group_assignments::assigned_at.eq(time),
+      ))
+      .returning(group_assignments::id)
+      .get_result::<i32>(conn)?;
+    Audit::new(Some(user_id), group_owner(conn, group)?, time)
+      .inserted::<GroupAssignmentRow>(conn, &[id])?;
+    for member in accepted_members(conn, group)? {
+      record_access(conn, member, &[deck], time)?;
+    }
+    Ok(
+      group_assignments::table
+        .inner_join(decks::table)
+        .select(ASSIGNMENT_COLUMNS)
+        .filter(group_assignments::id.eq(id))
+        .get_result::<GroupAssignment>(conn)?,
+    )
+  })
+}
+
+pub fn unassign(
+  ctx: &GQLContext<DBConnection>,
+  assignment: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  let group = group_assignments::table
+    .select(group_assignments::group_id)
+    .filter(group_assignments::id.eq(assignment))
+    .get_result::<i32>(conn)?;
+  authorize_group(conn, user_id, group, GroupRole::TEACHER)?;
+
+  conn.transaction(|| {
+    let before = GroupAssignmentRow::load(conn, &[assignment])?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let removed =
+      diesel::delete(group_assignments::table.filter(group_assignments::id.eq(assignment)))
+        .execute(conn)?;
+    let decks = before.iter().map(|row| row.deck).collect::<Vec<_>>();
+    Audit::new(Some(user_id), group_owner(conn, group)?, time).deleted(conn, before)?;
+    for member in accepted_members(conn, group)? {
+      record_access(conn, member, &decks, time)?;
+    }
+    Ok(removed > 0)
+  })
+}
+
+/// Days until a card is due again after `run` right answers in a row, as in
+/// SM-2 with its default ease
+fn interval_days(run: i32) -> f64 {
+  match run {
+    0 | 1 => 1.0,
+    2 => 6.0,
+    run => 6.0 * 2.5f64.powi(run - 2),
+  }
+}
+
+/// A student's reviews of one card, oldest first
+#[derive(Default)]
+struct CardReviews {
+  latest: Option<(ScoreValue, i64)>,
+  /// Right answers in a row, up to the latest
+  run: i32,
+}
+
+/// Summaries of how each student of a group is doing on the assigned cards,
+/// for its teachers. Only figures derived from scores of assigned cards are
+/// shown, never the scores themselves.
+pub fn progress(
+  ctx: &GQLContext<DBConnection>,
+  group: i32,
+) -> FieldResult<Vec<StudentProgress>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_group(conn, user_id, group, GroupRole::TEACHER)?;
+  let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+
+  let students = group_members::table
+    .inner_join(users::table)
+    .select((users::id, users::username))
+    .filter(group_members::group_id.eq(group))
+    .filter(group_members::role.eq(GroupRole::STUDENT))
+    .filter(group_members::accepted.eq(true))
+    .order(users::username.asc())
+    .load::<(i32, String)>(conn)?;
+
+  let mut assigned = HashSet::new();
+  let assignments = group_assignments::table
+    .inner_join(decks::table)
+    .select((group_assignments::deck, group_assignments::set_id))
+    .filter(group_assignments::group_id.eq(group))
+    .filter(decks::deleted_at.is_null())
+    .load::<(i32, Option<i32>)>(conn)?;
+  for (deck, set) in assignments {
+    let ids = match set {
+      Some(set) => set_cards::table
+        .inner_join(cards::table)
+        .inner_join(sets::table)
+        .select(cards::id)
+        .filter(set_cards::set_id.eq(set))
+        .filter(sets::deleted_at.is_null())
+        .filter(cards::deleted_at.is_null())
+        .load::<i32>(conn)?,
+      None => cards::table
+        .select(cards::id)
+        .filter(cards::deck.eq(deck))
+        .filter(cards::deleted_at.is_null())
+        .load::<i32>(conn)?,
+    };
+    assigned.extend(ids);
+  }
+
+  let reviews = scores::table
+    .select((
+      scores::owner,
+      scores::card,
+      scores::value,
+      scores::created_at,
+    ))
+    .filter(scores::owner.eq_any(students.iter().map(|(id, _)| *id).collect::<Vec<_>>()))
+    .filter(scores::card.eq_any(assigned.iter().copied().collect::<Vec<_>>()))
+    .order(scores::created_at.asc())
+    .load::<(i32, i32, ScoreValue, i64)>(conn)?;
+  let right = |value: ScoreValue| value as i16 >= ScoreValue::THREE as i16;
+
+  let mut by_student = HashMap::<i32, HashMap<i32, CardReviews>>::new();
+  let mut recent = HashMap::<i32, (i32, i32)>::new();
+  for (student, card, value, time) in reviews {
+    let card = by_student
+      .entry(student)
+      .or_default()
+      .entry(card)
+      .or_default();
+    card.run = if right(value) { card.run + 1 } else { 0 };
+    card.latest = Some((value, time));
+    if now - time <= RETENTION_WINDOW {
+      let (reviewed, remembered) = recent.entry(student).or_default();
+      *reviewed += 1;
+      if right(value) {
+        *remembered += 1;
+      }
+    }
+  }
+
+  Ok(
+    students
+      .into_iter()
+      .map(|(student, username)| {
+        let cards = by_student.remove(&student).unwrap_or_default();
+        let latest = cards
+          .values()
+          .filter_map(|card| card.latest)
+          .collect::<Vec<_>>();
+        StudentProgress {
+          user_id: student,
+          username,
+          assigned_cards: assigned.len() as i32,
+          studied_cards: latest.len() as i32,
+          learned_cards: latest.iter().filter(|(value, _)| right(*value)).count() as i32,
+          retention: recent
+            .get(&student)
+            .map(|(reviewed, remembered)| f64::from(*remembered) / f64::from(*reviewed)),
+          overdue_cards: cards
+            .values()
+            .filter(|card| match card.latest {
+              Some((_, time)) => (time as f64) + interval_days(card.run) * DAY < now as f64,
+              None => false,
+            })
+            .count() as i32,
+          last_review: latest.iter().map(|(_, time)| *time).max(),
+        }
+      })
+      .collect(),
+  )
+}
@@ -0,0 +1,113 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_card, Access},
+  GQLContext,
+};
+use crate::{
+  db::{
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::CardRow,
+    schema::{backs, cards, media},
+    DBConnection,
+  },
+  media::{self as store, ImageUrls, MediaRow},
+  TRCError,
+};
+
+#[derive(Debug, Copy, Clone, GraphQLEnum, Eq, PartialEq)]
+pub enum MediaKind {
+  AUDIO,
+  IMAGE,
+}
+
+impl MediaKind {
+  fn of(mime: &str) -> Self {
+    if mime.starts_with("audio/") {
+      MediaKind::AUDIO
+    } else {
+      MediaKind::IMAGE
+    }
+  }
+}
+
+/// Replaces the audio or image of a card's back with stored media, usually
+/// an upload, or removes it when no hash is given. Returns the new URL.
+pub fn set_back_media(
+  ctx: &GQLContext<DBConnection>,
+  card: i32,
+  kind: MediaKind,
+  hash: Option<String>,
+) -> FieldResult<Option<String>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let config = &ctx.config.media;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let owner = authorize_card(conn, user_id, card, Access::Edit)?;
+    store::lock_shared(conn)?;
+    let stored = match &hash {
+      Some(hash) => {
+        let stored = media::table
+          .select(MediaRow::COLUMNS)
+          .filter(media::hash.eq(hash))
+          .get_result::<MediaRow>(conn)
+          .optional()?
+          .ok_or_else(|| TRCError::Unknown(format!("No media with hash {}", hash)))?;
+        if MediaKind::of(&stored.mime) != kind {
+          return Err(TRCError::Unknown(format!("{} isn't {:?}", stored.mime, kind)).into());
+        }
+        Some(stored)
+      }
+      None => None,
+    };
+    let url = stored.as_ref().map(|stored| config.url(&stored.path()));
+    let id = stored.as_ref().map(|stored| stored.id);
+
+    let back = cards::table
+      .select(cards::back)
+      .filter(cards::id.eq(card))
+      .get_result::<i32>(conn)?;
+    let before = CardRow::load(conn, &[card])?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let updated = diesel::update(backs::table.filter(backs::id.eq(back)));
+    match kind {
+      MediaKind::AUDIO => updated
+        .set((
+          backs::audio.eq(&url),
+          backs::audio_media.eq(id),
+          backs::updated_at.eq(time),
+        ))
+        .execute(conn)?,
+      MediaKind::IMAGE => {
+        let image = match stored {
+          Some(stored) => store::stored_image(conn, stored)?.urls(config),
+          None => ImageUrls::default(),
+        };
+        updated
+          .set((
+            backs::image.eq(image.image),
+            backs::image_media.eq(image.image_media),
+            backs::image_thumbnail.eq(image.thumbnail),
+            backs::image_card.eq(image.card),
+            backs::updated_at.eq(time),
+          ))
+          .execute(conn)?
+      }
+    };
+    diesel::update(cards::table.filter(cards::id.eq(card)))
+      .set((
+        cards::updated_at.eq(time),
+        cards::version.eq(cards::version + 1),
+      ))
+      .execute(conn)?;
+    changes::record(conn, owner, Entity::Back, &[back], ChangeKind::Upsert, time)?;
+    changes::record(conn, owner, Entity::Card, &[card], ChangeKind::Upsert, time)?;
+    Audit::new(Some(user_id), owner, time).updated(conn, before)?;
+
+    Ok(url)
+  })
+}
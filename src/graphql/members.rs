@@ -0,0 +1,200 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_deck, record_access, Access, DeckRole},
+  GQLContext,
+};
+use crate::{
+  db::{
+    audit::Audit,
+    models::DeckMemberRow,
+    schema::{deck_members, decks, users},
+    DBConnection,
+  },
+  TRCError,
+};
+
+#[derive(Debug, Clone, Queryable, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct DeckMember {
+  deck: i32,
+  deck_name: String,
+  user_id: i32,
+  username: String,
+  role: DeckRole,
+  /// Whether the user has accepted the invitation yet
+  accepted: bool,
+}
+
+type Columns = (
+  deck_members::deck,
+  decks::name,
+  deck_members::user_id,
+  users::username,
+  deck_members::role,
+  deck_members::accepted,
+);
+
+const COLUMNS: Columns = (
+  deck_members::deck,
+  decks::name,
+  deck_members::user_id,
+  users::username,
+  deck_members::role,
+  deck_members::accepted,
+);
+
+/// A user's membership of a deck, along with the owner of the deck
+fn membership(
+  conn: &DBConnection,
+  deck: i32,
+  user: i32,
+) -> QueryResult<Option<(DeckMemberRow, i32)>> {
+  deck_members::table
+    .inner_join(decks::table)
+    .select((DeckMemberRow::COLUMNS, decks::owner))
+    .filter(deck_members::deck.eq(deck))
+    .filter(deck_members::user_id.eq(user))
+    .get_result(conn)
+    .optional()
+}
+
+/// Members of a deck, visible to its owner and anyone who joined it
+pub fn list(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+) -> FieldResult<Vec<DeckMember>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  authorize_deck(conn, user_id, deck, Access::View)?;
+
+  Ok(
+    deck_members::table
+      .inner_join(decks::table)
+      .inner_join(users::table)
+      .select(COLUMNS)
+      .filter(deck_members::deck.eq(deck))
+      .order(users::username.asc())
+      .load::<DeckMember>(conn)?,
+  )
+}
+
+/// Invitations the caller hasn't answered yet
+pub fn invitations(
+  ctx: &GQLContext<DBConnection>,
+) -> FieldResult<Vec<DeckMember>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+
+  Ok(
+    deck_members::table
+      .inner_join(decks::table)
+      .inner_join(users::table)
+      .select(COLUMNS)
+      .filter(deck_members::user_id.eq(user_id))
+      .filter(deck_members::accepted.eq(false))
+      .filter(decks::deleted_at.is_null())
+      .load::<DeckMember>(ctx.get_connection())?,
+  )
+}
+
+/// Invites a user to a deck, or changes the role of an existing member.
+/// Only the owner of the deck may do this.
+pub fn invite(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+  username: String,
+  role: DeckRole,
+) -> FieldResult<DeckMember, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let owner = authorize_deck(conn, user_id, deck, Access::Own)?;
+    let member = users::table
+      .select(users::id)
+      .filter(users::username.eq(&username))
+      .get_result::<i32>(conn)?;
+    if member == user_id {
+      return Err(TRCError::Unknown("The owner can't be invited to their own deck".into()).into());
+    }
+
+    let before = membership(conn, deck, member)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let id = diesel::insert_into(deck_members::table)
+      .values((
+        deck_members::deck.eq(deck),
+        deck_members::user_id.eq(member),
+        deck_members::role.eq(role),
+        deck_members::created_at.eq(time),
+      ))
+      .on_conflict((deck_members::deck, deck_members::user_id))
+      .do_update()
+      .set(deck_members::role.eq(role))
+      .returning(deck_members::id)
+      .get_result::<i32>(conn)?;
+    let audit = Audit::new(Some(user_id), owner, time);
+    match before {
+      Some((before, _)) => audit.updated(conn, vec![before])?,
+      None => audit.inserted::<DeckMemberRow>(conn, &[id])?,
+    }
+
+    Ok(
+      deck_members::table
+        .inner_join(decks::table)
+        .inner_join(users::table)
+        .select(COLUMNS)
+        .filter(deck_members::deck.eq(deck))
+        .filter(deck_members::user_id.eq(member))
+        .get_result::<DeckMember>(conn)?,
+    )
+  })
+}
+
+pub fn accept(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let (before, owner) = match membership(conn, deck, user_id)? {
+      Some(membership) => membership,
+      None => return Ok(false),
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    diesel::update(deck_members::table.filter(deck_members::id.eq(before.id)))
+      .set(deck_members::accepted.eq(true))
+      .execute(conn)?;
+    Audit::new(Some(user_id), owner, time).updated(conn, vec![before])?;
+    record_access(conn, user_id, &[deck], time)?;
+    Ok(true)
+  })
+}
+
+/// Removes a member from a deck. The owner may remove anyone, members may
+/// only remove themselves, which also declines a pending invitation.
+pub fn remove(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+  user: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  if user != user_id {
+    authorize_deck(conn, user_id, deck, Access::Own)?;
+  }
+
+  conn.transaction(|| {
+    let (before, owner) = match membership(conn, deck, user)? {
+      Some(membership) => membership,
+      None => return Ok(false),
+    };
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    diesel::delete(deck_members::table.filter(deck_members::id.eq(before.id))).execute(conn)?;
+    Audit::new(Some(user_id), owner, time).deleted(conn, vec![before])?;
+    record_access(conn, user, &[deck], time)?;
+    Ok(true)
+  })
+}
@@ -1,9 +1,15 @@
 use diesel::{
   backend::Backend,
+  dsl::sql,
+  prelude::*,
   r2d2::{ConnectionManager, PooledConnection},
+  sql_types::Bool,
   Connection,
 };
-use juniper::LookAheadSelection;
+use juniper::{
+  meta::MetaType, Arguments, ExecutionResult, Executor, GraphQLType, LookAheadSelection, Registry,
+};
+use std::{collections::HashSet, sync::Arc};
 use wundergraph::{
   error::Result,
   query_builder::selection::{offset::ApplyOffset, BoxedQuery, LoadingHandler, QueryModifier},
@@ -11,10 +17,23 @@ use wundergraph::{
   WundergraphContext,
 };
 
-use super::db::DBConnection;
+use super::{config::Config, db::DBConnection, TRCError};
+use authorization::UserRole;
 
+pub mod admin;
+pub mod audit;
+pub mod authorization;
+pub mod catalogue;
+pub mod goals;
+pub mod grading;
+pub mod groups;
+pub mod media;
+pub mod members;
 pub mod mutations;
 pub mod query;
+pub mod quiz;
+pub mod trash;
+pub mod updates;
 
 #[derive(Debug)]
 pub struct GQLContext<Conn>
@@ -23,14 +42,42 @@ where
 {
   conn: PooledConnection<ConnectionManager<Conn>>,
   pub user_id: Option<i32>,
+  /// Set instead of `user_id` for users an admin reset the password of, who
+  /// may do nothing but set a new one
+  pub password_reset: Option<i32>,
+  pub role: UserRole,
+  pub config: Arc<Config>,
 }
 
 impl<Conn> GQLContext<Conn>
 where
   Conn: Connection + 'static,
 {
-  pub fn new(conn: PooledConnection<ConnectionManager<Conn>>, user_id: Option<i32>) -> Self {
-    Self { conn, user_id }
+  pub fn new(
+    conn: PooledConnection<ConnectionManager<Conn>>,
+    user_id: Option<i32>,
+    role: UserRole,
+    config: Arc<Config>,
+  ) -> Self {
+    Self {
+      conn,
+      user_id,
+      password_reset: None,
+      role,
+      config,
+    }
+  }
+
+  /// The context of a user who has to set a new password before anything else
+  pub fn password_reset(
+    conn: PooledConnection<ConnectionManager<Conn>>,
+    user_id: i32,
+    config: Arc<Config>,
+  ) -> Self {
+    Self {
+      password_reset: Some(user_id),
+      ..Self::new(conn, None, UserRole::default(), config)
+    }
   }
 }
 
@@ -47,7 +94,28 @@ where
     _select: &LookAheadSelection<'_, WundergraphScalarValue>,
     query: BoxedQuery<'a, T, DB, Self>,
   ) -> Result<BoxedQuery<'a, T, DB, Self>> {
+    // Rows in the trash, or whose parent is in the trash, stay hidden
+    let hidden = match T::TYPE_NAME {
+      "Deck" => "decks.deleted_at IS NULL",
+      "Card" => "cards.id IN (SELECT cards.id FROM cards INNER JOIN decks ON decks.id = cards.deck \
+        WHERE cards.deleted_at IS NULL AND decks.deleted_at IS NULL)",
+      "Set" => "sets.id IN (SELECT sets.id FROM sets INNER JOIN decks ON decks.id = sets.deck \
+        WHERE sets.deleted_at IS NULL AND decks.deleted_at IS NULL)",
+      "SetCard" => "set_cards.card_id IN (SELECT id FROM cards WHERE deleted_at IS NULL) \
+        AND set_cards.set_id IN (SELECT sets.id FROM sets INNER JOIN decks ON decks.id = sets.deck \
+        WHERE sets.deleted_at IS NULL AND decks.deleted_at IS NULL)",
+      "Score" => "scores.card IN (SELECT cards.id FROM cards INNER JOIN decks ON decks.id = cards.deck \
+        WHERE cards.deleted_at IS NULL AND decks.deleted_at IS NULL)",
+      _ => return Ok(query),
+    };
+    let query = query.filter(sql::<Bool>(hidden));
+
     match T::TYPE_NAME {
+      // Scores stay private to whoever studied, also on shared decks
+      "Score" => Ok(query.filter(sql::<Bool>(&format!(
+        "scores.owner = {}",
+        self.user_id.unwrap_or(-1)
+      )))),
       _ => Ok(query),
     }
   }
@@ -63,5 +131,149 @@ impl WundergraphContext for GQLContext<DBConnection> {
 
 impl juniper::Context for GQLContext<DBConnection> {}
 
-pub type Schema<Ctx> =
-  juniper::RootNode<'static, query::Query<Ctx>, mutations::Mutation<Ctx>, WundergraphScalarValue>;
+/// A generated wundergraph root object together with hand written fields.
+/// Fields of `Ext` are merged into the schema of `Base` and resolved by `Ext`.
+pub struct Extended<Base, Ext> {
+  base: Base,
+  extension: Ext,
+  extension_fields: HashSet<String>,
+}
+
+impl<Base, Ext> Extended<Base, Ext>
+where
+  Ext: GraphQLType<WundergraphScalarValue, TypeInfo = ()>,
+{
+  pub fn new(base: Base, extension: Ext) -> Self {
+    let mut registry = Registry::new(Default::default());
+    let extension_fields = match Ext::meta(&(), &mut registry) {
+      MetaType::Object(meta) => meta
+        .fields
+        .into_iter()
+        .map(|field| field.name)
+        .filter(|name| !name.starts_with("__"))
+        .collect(),
+      _ => HashSet::new(),
+    };
+    Self {
+      base,
+      extension,
+      extension_fields,
+    }
+  }
+}
+
+impl<Base, Ext, Ctx> GraphQLType<WundergraphScalarValue> for Extended<Base, Ext>
+where
+  Base: GraphQLType<WundergraphScalarValue, Context = Ctx, TypeInfo = ()>,
+  Ext: GraphQLType<WundergraphScalarValue, Context = Ctx, TypeInfo = ()>,
+{
+  type Context = Ctx;
+  type TypeInfo = ();
+
+  fn name(info: &()) -> Option<&str> {
+    Base::name(info)
+  }
+
+  fn meta<'r>(
+    info: &(),
+    registry: &mut Registry<'r, WundergraphScalarValue>,
+  ) -> MetaType<'r, WundergraphScalarValue>
+  where
+    WundergraphScalarValue: 'r,
+  {
+    let mut meta = Base::meta(info, registry);
+    if let (MetaType::Object(base), MetaType::Object(extension)) =
+      (&mut meta, Ext::meta(info, registry))
+    {
+      base.fields.extend(
+        extension
+          .fields
+          .into_iter()
+          .filter(|field| !field.name.starts_with("__")),
+      );
+    }
+    meta
+  }
+
+  fn resolve_field(
+    &self,
+    info: &(),
+    field_name: &str,
+    arguments: &Arguments<WundergraphScalarValue>,
+    executor: &Executor<Ctx, WundergraphScalarValue>,
+  ) -> ExecutionResult<WundergraphScalarValue> {
+    if self.extension_fields.contains(field_name) {
+      self
+        .extension
+        .resolve_field(info, field_name, arguments, executor)
+    } else {
+      self.base.resolve_field(info, field_name, arguments, executor)
+    }
+  }
+}
+
+/// Fields that only administrators may resolve. The role is checked before
+/// any of the wrapped fields run.
+pub struct AdminOnly<T>(T);
+
+impl<T> GraphQLType<WundergraphScalarValue> for AdminOnly<T>
+where
+  T: GraphQLType<WundergraphScalarValue, Context = GQLContext<DBConnection>, TypeInfo = ()>,
+{
+  type Context = GQLContext<DBConnection>;
+  type TypeInfo = ();
+
+  fn name(info: &()) -> Option<&str> {
+    T::name(info)
+  }
+
+  fn meta<'r>(
+    info: &(),
+    registry: &mut Registry<'r, WundergraphScalarValue>,
+  ) -> MetaType<'r, WundergraphScalarValue>
+  where
+    WundergraphScalarValue: 'r,
+  {
+    T::meta(info, registry)
+  }
+
+  fn resolve_field(
+    &self,
+    info: &(),
+    field_name: &str,
+    arguments: &Arguments<WundergraphScalarValue>,
+    executor: &Executor<Self::Context, WundergraphScalarValue>,
+  ) -> ExecutionResult<WundergraphScalarValue> {
+    let ctx = executor.context();
+    if ctx.user_id.is_none() || ctx.role != UserRole::ADMIN {
+      return ExecutionResult::from(TRCError::Unauthorized);
+    }
+    self.0.resolve_field(info, field_name, arguments, executor)
+  }
+}
+
+pub type Schema<Ctx> = juniper::RootNode<
+  'static,
+  Extended<
+    Extended<query::Query<Ctx>, query::QueryExtensions>,
+    AdminOnly<admin::AdminQuery>,
+  >,
+  Extended<
+    Extended<mutations::Mutation<Ctx>, mutations::MutationExtensions>,
+    AdminOnly<admin::AdminMutation>,
+  >,
+  WundergraphScalarValue,
+>;
+
+pub fn create_schema() -> Schema<GQLContext<DBConnection>> {
+  Schema::new(
+    Extended::new(
+      Extended::new(query::Query::default(), query::QueryExtensions),
+      AdminOnly(admin::AdminQuery),
+    ),
+    Extended::new(
+      Extended::new(mutations::Mutation::default(), mutations::MutationExtensions),
+      AdminOnly(admin::AdminMutation),
+    ),
+  )
+}
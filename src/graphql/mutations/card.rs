@@ -1,6 +1,5 @@
 use diesel::{pg::Pg, prelude::*};
 use juniper::*;
-use sanitize_filename::sanitize;
 use std::time::{SystemTime, UNIX_EPOCH};
 use wundergraph::{
   query_builder::{
@@ -11,13 +10,21 @@ use wundergraph::{
   WundergraphContext,
 };
 
-use super::utilities::{get_audio_from_google, get_image_from_google};
+use super::utilities::{front_language, insert_back, insert_front_audio, update_card, CardEdit};
 use crate::{
+  config::MediaConfig,
   db::{
-    schema::{backs, cards, decks, languages},
-    DBConnection,
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::CardRow,
+    schema::{cards, decks},
+    trash, DBConnection,
+  },
+  graphql::{
+    authorization::{authorize_card, authorize_deck, Access},
+    query::Card,
+    GQLContext,
   },
-  graphql::{query::Card, GQLContext},
   TRCError,
 };
 
@@ -27,6 +34,77 @@ pub struct NewCard {
   back: String,
   deck: i32,
   link: Option<String>,
+  /// Defaults to the front language of the deck
+  front_language: Option<i32>,
+  /// Generate audio for the front as well as the back
+  front_audio: Option<bool>,
+}
+
+/// Inserts a card along with its back into a deck the user may edit.
+/// Returns the new card id.
+fn insert_card(
+  conn: &DBConnection,
+  media: &MediaConfig,
+  user_id: i32,
+  card: NewCard,
+  time: i64,
+) -> Result<i32, TRCError> {
+  let owner = authorize_deck(conn, user_id, card.deck, Access::Edit)?;
+  let language = decks::table
+    .select(decks::language)
+    .filter(decks::id.eq(card.deck))
+    .get_result::<i32>(conn)?;
+
+  let front_language = match card.front_language {
+    Some(language) => Some(language),
+    None => front_language(conn, card.deck)?,
+  };
+  let front_audio = if card.front_audio.unwrap_or(false) {
+    Some(insert_front_audio(
+      conn,
+      media,
+      front_language,
+      &card.front,
+      time,
+    )?)
+  } else {
+    None
+  };
+
+  let inserted_back = insert_back(conn, media, language, card.back, time)?;
+  let (front_audio, front_audio_media) = front_audio.unzip();
+  let inserted = diesel::insert_into(cards::table)
+    .values((
+      cards::front.eq(card.front),
+      cards::deck.eq(card.deck),
+      cards::link.eq(card.link),
+      cards::created_at.eq(time),
+      cards::updated_at.eq(time),
+      cards::back.eq(inserted_back),
+      cards::front_language.eq(front_language),
+      cards::front_audio.eq(front_audio),
+      cards::front_audio_media.eq(front_audio_media),
+    ))
+    .returning(cards::id)
+    .get_result::<i32>(conn)?;
+  changes::record(
+    conn,
+    owner,
+    Entity::Back,
+    &[inserted_back],
+    ChangeKind::Upsert,
+    time,
+  )?;
+  changes::record(
+    conn,
+    owner,
+    Entity::Card,
+    &[inserted],
+    ChangeKind::Upsert,
+    time,
+  )?;
+  Audit::new(Some(user_id), owner, time).inserted::<CardRow>(conn, &[inserted])?;
+  Ok(inserted)
 }
 
 impl HandleInsert<Card, NewCard, Pg, GQLContext<DBConnection>> for cards::table {
@@ -39,41 +117,10 @@ impl HandleInsert<Card, NewCard, Pg, GQLContext<DBConnection>> for cards::table
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
-      let (abbr, (target_user_id, language)) = decks::table
-        .inner_join(languages::table)
-        .select((languages::abbreviation, (decks::owner, decks::language)))
-        .filter(decks::id.eq(insertable.deck))
-        .get_result::<(String, (i32, i32))>(conn)?;
-      if id != target_user_id {
-        return ExecutionResult::from(TRCError::Unauthorized);
-      }
-
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let look_ahead = executor.look_ahead();
-      let sanitized = sanitize(&insertable.back);
-
-      get_audio_from_google(&abbr, &insertable.back, &sanitized)?;
-      get_image_from_google(&abbr, &insertable.back, &sanitized)?;
-
-      let inserted_back = diesel::insert_into(backs::table)
-        .values((
-          backs::text.eq(insertable.back),
-          backs::language.eq(language),
-          backs::image.eq(Some(format!("images/{}/{}.jpg", &abbr, &sanitized))),
-          backs::audio.eq(Some(format!("audio/{}/{}.mp3", &abbr, &sanitized))),
-        ))
-        .returning(backs::id)
-        .get_result::<i32>(conn)?;
-      let inserted = diesel::insert_into(cards::table)
-        .values((
-          cards::front.eq(insertable.front),
-          cards::deck.eq(insertable.deck),
-          cards::link.eq(insertable.link),
-          cards::created_at.eq(time),
-          cards::back.eq(inserted_back),
-        ))
-        .returning(cards::id)
-        .get_result::<i32>(conn)?;
+      let inserted = insert_card(conn, &ctx.config.media, id, insertable, time)?;
+
       let query = <Card as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(cards::id.eq(inserted));
       let items = Card::load(&look_ahead, selection, executor, query)?;
@@ -92,66 +139,17 @@ impl HandleBatchInsert<Card, NewCard, Pg, GQLContext<DBConnection>> for cards::t
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let look_ahead = executor.look_ahead();
 
-      let mut deck_ids = vec![];
-      for card in &insertable {
-        deck_ids.push(card.deck)
-      }
-
-      let decks_owners_languages = decks::table
-        .inner_join(languages::table)
-        .select((languages::abbreviation, (decks::owner, decks::language)))
-        .filter(decks::id.eq_any(deck_ids))
-        .get_results::<(String, (i32, i32))>(conn)?;
-
-      for (_, (owner, _)) in &decks_owners_languages {
-        if &id != owner {
-          return ExecutionResult::from(TRCError::Unauthorized);
+      // Every card may wait on audio and images to download
+      let inserted = changes::deferred(conn, || {
+        let mut inserted = vec![];
+        for card in insertable {
+          inserted.push(insert_card(conn, &ctx.config.media, id, card, time)?);
         }
-      }
-
-      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
-
-      let mut insert = vec![];
-      for (
-        i,
-        NewCard {
-          front,
-          deck,
-          link,
-          back,
-        },
-      ) in insertable.into_iter().enumerate()
-      {
-        let sanitized = sanitize(&back);
-        let abbr = &decks_owners_languages[i].0;
-
-        get_audio_from_google(abbr, &back, &sanitized)?;
-        get_image_from_google(abbr, &back, &sanitized)?;
-        let inserted_back = diesel::insert_into(backs::table)
-          .values((
-            backs::text.eq(back),
-            backs::language.eq((decks_owners_languages[i].1).1),
-            backs::image.eq(Some(format!("images/{}/{}.jpg", abbr, &sanitized))),
-            backs::audio.eq(Some(format!("audio/{}/{}.mp3", abbr, &sanitized))),
-          ))
-          .returning(backs::id)
-          .get_result::<i32>(conn)?;
-
-        insert.push((
-          cards::front.eq(front),
-          cards::deck.eq(deck),
-          cards::link.eq(link),
-          cards::created_at.eq(time),
-          cards::back.eq(inserted_back),
-        ));
-      }
-
-      let inserted = diesel::insert_into(cards::table)
-        .values(insert)
-        .returning(cards::id)
-        .get_results::<i32>(conn)?;
+        Ok::<_, TRCError>(inserted)
+      })?;
 
       let query = <Card as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(cards::id.eq_any(inserted));
@@ -165,7 +163,12 @@ impl HandleBatchInsert<Card, NewCard, Pg, GQLContext<DBConnection>> for cards::t
 #[table_name = "cards"]
 pub struct CardChangeset {
   id: i32,
+  front: Option<String>,
+  back: Option<String>,
   link: Option<String>,
+  front_language: Option<i32>,
+  /// Adds audio to the front, or removes it
+  front_audio: Option<bool>,
 }
 
 impl HandleUpdate<Card, CardChangeset, Pg, GQLContext<DBConnection>> for cards::table {
@@ -178,19 +181,18 @@ impl HandleUpdate<Card, CardChangeset, Pg, GQLContext<DBConnection>> for cards::
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
-      let (owner_id, current_link) = cards::table
-        .filter(cards::id.eq(update.id))
-        .inner_join(decks::table)
-        .select((decks::owner, cards::link))
-        .get_result::<(i32, Option<String>)>(conn)?;
-
-      if id != owner_id {
-        return ExecutionResult::from(TRCError::Unauthorized);
-      }
-
-      diesel::update(cards::table.filter(cards::id.eq(update.id)))
-        .set(cards::link.eq(update.link.as_ref().or(current_link.as_ref())))
-        .execute(conn)?;
+      let owner = authorize_card(conn, id, update.id, Access::Edit)?;
+      let before = CardRow::load(conn, &[update.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      let edit = CardEdit {
+        front: update.front.as_deref(),
+        back: update.back.as_deref(),
+        link: update.link.as_deref(),
+        front_language: update.front_language,
+        front_audio: update.front_audio,
+      };
+      update_card(conn, &ctx.config.media, owner, update.id, edit, time)?;
+      Audit::new(Some(id), owner, time).updated(conn, before)?;
 
       let look_ahead = executor.look_ahead();
 
@@ -216,23 +218,14 @@ impl HandleDelete<Card, CardDeleteset, Pg, GQLContext<DBConnection>> for cards::
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
-      let target_user_id = cards::table
-        .filter(cards::id.eq(to_delete.id))
-        .inner_join(decks::table)
-        .select(decks::owner)
-        .get_result::<i32>(conn)?;
-
-      if id != target_user_id {
-        return ExecutionResult::from(TRCError::Unauthorized);
-      }
-
-      let d = diesel::delete(cards::table.filter(cards::id.eq(to_delete.id)));
-      executor.resolve_with_ctx(
-        &(),
-        &DeletedCount {
-          count: d.execute(conn)? as _,
-        },
-      )
+      let owner = authorize_card(conn, id, to_delete.id, Access::Edit)?;
+
+      let before = CardRow::load(conn, &[to_delete.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      let count = trash::trash_card(conn, owner, to_delete.id, time)?;
+      Audit::new(Some(id), owner, time).deleted(conn, before)?;
+
+      executor.resolve_with_ctx(&(), &DeletedCount { count: count as _ })
     })
   }
 }
@@ -1,5 +1,6 @@
 use diesel::{pg::Pg, prelude::*};
 use juniper::*;
+use std::time::{SystemTime, UNIX_EPOCH};
 use wundergraph::{
   query_builder::{
     mutations::{DeletedCount, HandleBatchInsert, HandleDelete, HandleInsert, HandleUpdate},
@@ -9,8 +10,15 @@ use wundergraph::{
   WundergraphContext,
 };
 
+use super::utilities::check_language;
 use crate::{
-  db::{schema::decks, DBConnection},
+  db::{
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::DeckRow,
+    schema::decks,
+    trash, DBConnection,
+  },
   graphql::{query::Deck, GQLContext},
   TRCError,
 };
@@ -19,6 +27,10 @@ use crate::{
 pub struct NewDeck {
   name: String,
   language: i32,
+  description: Option<String>,
+  licence: Option<String>,
+  /// For fronts that aren't in the owner's native language
+  front_language: Option<i32>,
 }
 
 impl HandleInsert<Deck, NewDeck, Pg, GQLContext<DBConnection>> for decks::table {
@@ -31,15 +43,33 @@ impl HandleInsert<Deck, NewDeck, Pg, GQLContext<DBConnection>> for decks::table
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      check_language(conn, insertable.language)?;
+      if let Some(language) = insertable.front_language {
+        check_language(conn, language)?;
+      }
       let look_ahead = executor.look_ahead();
       let inserted = diesel::insert_into(decks::table)
         .values((
           decks::name.eq(insertable.name),
           decks::owner.eq(id),
           decks::language.eq(insertable.language),
+          decks::description.eq(insertable.description),
+          decks::licence.eq(insertable.licence),
+          decks::front_language.eq(insertable.front_language),
+          decks::updated_at.eq(time),
         ))
         .returning(decks::id)
         .get_result::<i32>(conn)?;
+      changes::record(
+        conn,
+        id,
+        Entity::Deck,
+        &[inserted],
+        ChangeKind::Upsert,
+        time,
+      )?;
+      Audit::new(Some(id), id, time).inserted::<DeckRow>(conn, &[inserted])?;
       let query = <Deck as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(decks::id.eq(inserted));
       let items = Deck::load(&look_ahead, selection, executor, query)?;
@@ -58,21 +88,43 @@ impl HandleBatchInsert<Deck, NewDeck, Pg, GQLContext<DBConnection>> for decks::t
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let look_ahead = executor.look_ahead();
+      for deck in &insertable {
+        check_language(conn, deck.language)?;
+        if let Some(language) = deck.front_language {
+          check_language(conn, language)?;
+        }
+      }
       let insert = insertable
         .into_iter()
-        .map(|NewDeck { name, language }| {
-          (
-            decks::name.eq(name),
-            decks::owner.eq(id),
-            decks::language.eq(language),
-          )
-        })
+        .map(
+          |NewDeck {
+             name,
+             language,
+             description,
+             licence,
+             front_language,
+           }| {
+            (
+              decks::name.eq(name),
+              decks::owner.eq(id),
+              decks::language.eq(language),
+              decks::description.eq(description),
+              decks::licence.eq(licence),
+              decks::front_language.eq(front_language),
+              decks::updated_at.eq(time),
+            )
+          },
+        )
         .collect::<Vec<_>>();
       let inserted = diesel::insert_into(decks::table)
         .values(insert)
         .returning(decks::id)
         .get_results::<i32>(conn)?;
+      changes::record(conn, id, Entity::Deck, &inserted, ChangeKind::Upsert, time)?;
+
+      Audit::new(Some(id), id, time).inserted::<DeckRow>(conn, &inserted)?;
 
       let query = <Deck as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(decks::id.eq_any(inserted));
@@ -87,6 +139,11 @@ impl HandleBatchInsert<Deck, NewDeck, Pg, GQLContext<DBConnection>> for decks::t
 pub struct DeckChangeset {
   id: i32,
   name: String,
+  description: Option<String>,
+  licence: Option<String>,
+  /// Lists the deck in the public catalogue
+  published: Option<bool>,
+  front_language: Option<i32>,
 }
 
 impl HandleUpdate<Deck, DeckChangeset, Pg, GQLContext<DBConnection>> for decks::table {
@@ -102,15 +159,50 @@ impl HandleUpdate<Deck, DeckChangeset, Pg, GQLContext<DBConnection>> for decks::
       let owner_id = decks::table
         .select(decks::owner)
         .filter(decks::id.eq(update.id))
+        .filter(decks::deleted_at.is_null())
         .get_result::<i32>(conn)?;
 
       if id != owner_id {
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
+      if let Some(language) = update.front_language {
+        check_language(conn, language)?;
+      }
+
+      let before = DeckRow::load(conn, &[update.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       diesel::update(decks::table.filter(decks::id.eq(update.id)))
-        .set(decks::name.eq(&update.name))
+        .set((
+          decks::name.eq(&update.name),
+          update
+            .description
+            .as_ref()
+            .map(|description| decks::description.eq(description)),
+          update
+            .licence
+            .as_ref()
+            .map(|licence| decks::licence.eq(licence)),
+          update
+            .published
+            .map(|published| decks::published.eq(published)),
+          update
+            .front_language
+            .map(|language| decks::front_language.eq(language)),
+          decks::updated_at.eq(time),
+          decks::version.eq(decks::version + 1),
+        ))
         .execute(conn)?;
+      changes::record(
+        conn,
+        id,
+        Entity::Deck,
+        &[update.id],
+        ChangeKind::Upsert,
+        time,
+      )?;
+
+      Audit::new(Some(id), id, time).updated(conn, before)?;
 
       let look_ahead = executor.look_ahead();
 
@@ -139,19 +231,19 @@ impl HandleDelete<Deck, DeckDeleteset, Pg, GQLContext<DBConnection>> for decks::
       let target_user_id = decks::table
         .select(decks::owner)
         .filter(decks::id.eq(to_delete.id))
+        .filter(decks::deleted_at.is_null())
         .get_result::<i32>(conn)?;
 
       if id != target_user_id {
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
-      let d = diesel::delete(decks::table.filter(decks::id.eq(to_delete.id)));
-      executor.resolve_with_ctx(
-        &(),
-        &DeletedCount {
-          count: d.execute(conn)? as _,
-        },
-      )
+      let before = DeckRow::load(conn, &[to_delete.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      let count = trash::trash_deck(conn, id, to_delete.id, time)?;
+      Audit::new(Some(id), id, time).deleted(conn, before)?;
+
+      executor.resolve_with_ctx(&(), &DeletedCount { count: count as _ })
     })
   }
 }
@@ -1,11 +1,27 @@
-use super::query::{Card, Deck, Score, Set, User};
+use juniper::FieldResult;
+use wundergraph::scalar::WundergraphScalarValue;
+
+use super::{
+  authorization::{DeckRole, GroupRole},
+  catalogue,
+  grading::{self, AnswerCheck},
+  groups::{self, Group, GroupAssignment, GroupMember},
+  media::{self, MediaKind},
+  members::{self, DeckMember},
+  query::{Card, Deck, Score, Set, User},
+  quiz::{self, QuizAnswer, QuizResult},
+  trash::{self, TrashKind},
+  updates,
+  GQLContext,
+};
+use crate::db::DBConnection;
 
 mod card;
 mod deck;
 mod score;
 mod set;
 mod user;
-mod utilities;
+pub(crate) mod utilities;
 
 use card::{CardChangeset, CardDeleteset, NewCard};
 use deck::{DeckChangeset, DeckDeleteset, NewDeck};
@@ -22,3 +38,144 @@ Mutation {
   Set(insert = NewSet, update = SetChangeset, delete = SetDeleteset),
 }
 }
+
+/// Mutation fields that don't map onto a single entity
+pub struct MutationExtensions;
+
+#[juniper::object(Context = GQLContext<DBConnection>, Scalar = WundergraphScalarValue)]
+impl MutationExtensions {
+  /// Takes a deck, card or set back out of the trash
+  fn restore(
+    context: &GQLContext<DBConnection>,
+    kind: TrashKind,
+    id: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    trash::restore(context, kind, id)
+  }
+
+  /// Copies a published or shared deck into the caller's account and
+  /// returns the id of the copy
+  fn clone_deck(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+    name: Option<String>,
+    include_sets: Option<bool>,
+  ) -> FieldResult<i32, WundergraphScalarValue> {
+    catalogue::clone_deck(context, deck, name, include_sets.unwrap_or(false))
+  }
+
+  /// Merges upstream changes into a cloned deck, optionally only those for
+  /// the given upstream cards. Returns how many were applied.
+  fn apply_deck_updates(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+    origins: Option<Vec<i32>>,
+  ) -> FieldResult<i32, WundergraphScalarValue> {
+    updates::apply_deck_updates(context, deck, origins)
+  }
+
+  /// Shares a deck with another user, or changes their role
+  fn invite_member(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+    username: String,
+    role: DeckRole,
+  ) -> FieldResult<DeckMember, WundergraphScalarValue> {
+    members::invite(context, deck, username, role)
+  }
+
+  fn accept_invitation(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    members::accept(context, deck)
+  }
+
+  /// Stops sharing a deck with a user, or leaves a deck shared with the caller
+  fn remove_member(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+    user: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    members::remove(context, deck, user)
+  }
+
+  /// Sets the audio or image of a card's back to media uploaded to
+  /// `/upload`, or removes it. Returns the new URL.
+  fn set_back_media(
+    context: &GQLContext<DBConnection>,
+    card: i32,
+    kind: MediaKind,
+    hash: Option<String>,
+  ) -> FieldResult<Option<String>, WundergraphScalarValue> {
+    media::set_back_media(context, card, kind, hash)
+  }
+
+  /// Grades an answer typed for a card without recording a score
+  fn check_answer(
+    context: &GQLContext<DBConnection>,
+    card: i32,
+    typed: String,
+  ) -> FieldResult<AnswerCheck, WundergraphScalarValue> {
+    grading::check_answer(context, card, typed)
+  }
+
+  /// Records scores for the answers picked in a quiz
+  fn submit_quiz(
+    context: &GQLContext<DBConnection>,
+    answers: Vec<QuizAnswer>,
+  ) -> FieldResult<Vec<QuizResult>, WundergraphScalarValue> {
+    quiz::submit(context, answers)
+  }
+
+  /// Creates a group with the caller as its teacher
+  fn create_group(
+    context: &GQLContext<DBConnection>,
+    name: String,
+  ) -> FieldResult<Group, WundergraphScalarValue> {
+    groups::create(context, name)
+  }
+
+  /// Invites a user to a group, or changes their role in it
+  fn invite_to_group(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+    username: String,
+    role: GroupRole,
+  ) -> FieldResult<GroupMember, WundergraphScalarValue> {
+    groups::invite(context, group, username, role)
+  }
+
+  fn accept_group_invitation(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    groups::accept(context, group)
+  }
+
+  /// Removes a user from a group, or leaves a group
+  fn remove_group_member(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+    user: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    groups::remove(context, group, user)
+  }
+
+  /// Assigns a deck, or one of its sets, to a group for its members to study
+  fn assign_to_group(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+    deck: Option<i32>,
+    set: Option<i32>,
+  ) -> FieldResult<GroupAssignment, WundergraphScalarValue> {
+    groups::assign(context, group, deck, set)
+  }
+
+  fn unassign_from_group(
+    context: &GQLContext<DBConnection>,
+    assignment: i32,
+  ) -> FieldResult<bool, WundergraphScalarValue> {
+    groups::unassign(context, assignment)
+  }
+}
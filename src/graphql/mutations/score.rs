@@ -12,10 +12,15 @@ use wundergraph::{
 
 use crate::{
   db::{
-    schema::{cards, decks, scores},
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::ScoreRow,
+    schema::scores,
     DBConnection,
   },
   graphql::{
+    authorization::{authorize_card, Access},
+    goals,
     query::{Score, ScoreValue},
     GQLContext,
   },
@@ -38,15 +43,7 @@ impl HandleInsert<Score, NewScore, Pg, GQLContext<DBConnection>> for scores::tab
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
-      let target_user_id = cards::table
-        .filter(cards::id.eq(insertable.card))
-        .inner_join(decks::table)
-        .select(decks::owner)
-        .get_result::<i32>(conn)?;
-
-      if id != target_user_id {
-        return ExecutionResult::from(TRCError::Unauthorized);
-      }
+      authorize_card(conn, id, insertable.card, Access::View)?;
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let look_ahead = executor.look_ahead();
       let inserted = diesel::insert_into(scores::table)
@@ -54,9 +51,22 @@ impl HandleInsert<Score, NewScore, Pg, GQLContext<DBConnection>> for scores::tab
           scores::card.eq(insertable.card),
           scores::value.eq(insertable.value),
           scores::created_at.eq(time),
+          scores::updated_at.eq(time),
+          scores::owner.eq(id),
         ))
         .returning(scores::id)
         .get_result::<i32>(conn)?;
+      changes::record(
+        conn,
+        id,
+        Entity::Score,
+        &[inserted],
+        ChangeKind::Upsert,
+        time,
+      )?;
+
+      Audit::new(Some(id), id, time).inserted::<ScoreRow>(conn, &[inserted])?;
+      goals::unlock_achievements(conn, id, time)?;
 
       let query = <Score as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(scores::id.eq(inserted));
@@ -77,21 +87,8 @@ impl HandleBatchInsert<Score, NewScore, Pg, GQLContext<DBConnection>> for scores
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
       let look_ahead = executor.look_ahead();
-      let mut card_ids = vec![];
       for score in &insertable {
-        card_ids.push(score.card);
-      }
-
-      let target_ids = cards::table
-        .filter(cards::id.eq_any(card_ids))
-        .inner_join(decks::table)
-        .select(decks::owner)
-        .get_results::<i32>(conn)?;
-
-      for target_id in target_ids {
-        if id != target_id {
-          return ExecutionResult::from(TRCError::Unauthorized);
-        }
+        authorize_card(conn, id, score.card, Access::View)?;
       }
 
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
@@ -102,6 +99,8 @@ impl HandleBatchInsert<Score, NewScore, Pg, GQLContext<DBConnection>> for scores
             scores::card.eq(card),
             scores::value.eq(value),
             scores::created_at.eq(time),
+            scores::updated_at.eq(time),
+            scores::owner.eq(id),
           )
         })
         .collect::<Vec<_>>();
@@ -110,6 +109,10 @@ impl HandleBatchInsert<Score, NewScore, Pg, GQLContext<DBConnection>> for scores
         .values(insert)
         .returning(scores::id)
         .get_results::<i32>(conn)?;
+      changes::record(conn, id, Entity::Score, &inserted, ChangeKind::Upsert, time)?;
+
+      Audit::new(Some(id), id, time).inserted::<ScoreRow>(conn, &inserted)?;
+      goals::unlock_achievements(conn, id, time)?;
 
       let query = <Score as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(scores::id.eq_any(inserted));
@@ -135,19 +138,31 @@ impl HandleUpdate<Score, ScoreChangeset, Pg, GQLContext<DBConnection>> for score
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      // Scores are private, even on a shared deck
       let owner_id = scores::table
+        .select(scores::owner)
         .filter(scores::id.eq(update.id))
-        .inner_join(cards::table.inner_join(decks::table))
-        .select(decks::owner)
         .get_result::<i32>(conn)?;
 
       if id != owner_id {
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
+      let before = ScoreRow::load(conn, &[update.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       diesel::update(scores::table.filter(scores::id.eq(update.id)))
-        .set(scores::value.eq(update.value))
+        .set((scores::value.eq(update.value), scores::updated_at.eq(time)))
         .execute(conn)?;
+      changes::record(
+        conn,
+        id,
+        Entity::Score,
+        &[update.id],
+        ChangeKind::Upsert,
+        time,
+      )?;
+
+      Audit::new(Some(id), id, time).updated(conn, before)?;
 
       let look_ahead = executor.look_ahead();
 
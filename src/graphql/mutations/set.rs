@@ -10,15 +10,27 @@ use wundergraph::{
   WundergraphContext,
 };
 
+use super::utilities::cards_in_deck;
 use crate::{
   db::{
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::SetRow,
     schema::{set_cards, sets},
-    DBConnection,
+    trash, DBConnection,
+  },
+  graphql::{
+    authorization::{authorize_deck, Access},
+    query::Set,
+    GQLContext,
   },
-  graphql::{query::Set, GQLContext},
   TRCError,
 };
 
+fn foreign_cards() -> TRCError {
+  TRCError::Unknown("Sets can only hold cards of their own deck".to_owned())
+}
+
 #[derive(GraphQLInputObject, Clone, Debug)]
 pub struct NewSet {
   name: String,
@@ -36,6 +48,11 @@ impl HandleInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::table {
     let conn = ctx.get_connection();
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      // Sets are personal, anyone who can study the deck may group its cards
+      authorize_deck(conn, id, insertable.deck, Access::View)?;
+      if !cards_in_deck(conn, insertable.deck, &insertable.cards)? {
+        return Err(foreign_cards().into());
+      }
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let look_ahead = executor.look_ahead();
       let inserted = diesel::insert_into(sets::table)
@@ -44,11 +61,12 @@ impl HandleInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::table {
           sets::deck.eq(insertable.deck),
           sets::owner.eq(id),
           sets::created_at.eq(time),
+          sets::updated_at.eq(time),
         ))
         .returning(sets::id)
         .get_result::<i32>(conn)?;
 
-      diesel::insert_into(set_cards::table)
+      let set_card_ids = diesel::insert_into(set_cards::table)
         .values(
           insertable
             .cards
@@ -57,6 +75,7 @@ impl HandleInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::table {
               (
                 set_cards::card_id.eq(card_id),
                 set_cards::set_id.eq(inserted),
+                set_cards::updated_at.eq(time),
               )
             })
             .collect::<Vec<_>>(),
@@ -64,6 +83,18 @@ impl HandleInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::table {
         .returning(set_cards::id)
         .get_results::<i32>(conn)?;
 
+      changes::record(conn, id, Entity::Set, &[inserted], ChangeKind::Upsert, time)?;
+      changes::record(
+        conn,
+        id,
+        Entity::SetCard,
+        &set_card_ids,
+        ChangeKind::Upsert,
+        time,
+      )?;
+
+      Audit::new(Some(id), id, time).inserted::<SetRow>(conn, &[inserted])?;
+
       let query = <Set as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(sets::id.eq(inserted));
       let items = Set::load(&look_ahead, selection, executor, query)?;
@@ -83,6 +114,12 @@ impl HandleBatchInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::tabl
     conn.transaction(|| {
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
       let look_ahead = executor.look_ahead();
+      for set in &insertable {
+        authorize_deck(conn, id, set.deck, Access::View)?;
+        if !cards_in_deck(conn, set.deck, &set.cards)? {
+          return Err(foreign_cards().into());
+        }
+      }
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       let mut card_ids = vec![];
       let insert = insertable
@@ -94,6 +131,7 @@ impl HandleBatchInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::tabl
             sets::deck.eq(deck),
             sets::owner.eq(id),
             sets::created_at.eq(time),
+            sets::updated_at.eq(time),
           )
         })
         .collect::<Vec<_>>();
@@ -102,24 +140,36 @@ impl HandleBatchInsert<Set, NewSet, Pg, GQLContext<DBConnection>> for sets::tabl
         .values(insert)
         .returning(sets::id)
         .get_results::<i32>(conn)?;
+      changes::record(conn, id, Entity::Set, &inserted, ChangeKind::Upsert, time)?;
 
       for (i, ids) in card_ids.iter().enumerate() {
-        diesel::insert_into(set_cards::table)
+        let set_card_ids = diesel::insert_into(set_cards::table)
           .values(
             ids
-              .into_iter()
+              .iter()
               .map(|card_id| {
                 (
                   set_cards::card_id.eq(card_id),
                   set_cards::set_id.eq(inserted[i]),
+                  set_cards::updated_at.eq(time),
                 )
               })
               .collect::<Vec<_>>(),
           )
           .returning(set_cards::id)
           .get_results::<i32>(conn)?;
+        changes::record(
+          conn,
+          id,
+          Entity::SetCard,
+          &set_card_ids,
+          ChangeKind::Upsert,
+          time,
+        )?;
       }
 
+      Audit::new(Some(id), id, time).inserted::<SetRow>(conn, &inserted)?;
+
       let query = <Set as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(sets::id.eq_any(inserted));
       let items = Set::load(&look_ahead, selection, executor, query)?;
@@ -147,6 +197,7 @@ impl HandleUpdate<Set, SetChangeset, Pg, GQLContext<DBConnection>> for sets::tab
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
       let owner_id = sets::table
         .filter(sets::id.eq(update.id))
+        .filter(sets::deleted_at.is_null())
         .select(sets::owner)
         .get_result::<i32>(conn)?;
 
@@ -154,9 +205,25 @@ impl HandleUpdate<Set, SetChangeset, Pg, GQLContext<DBConnection>> for sets::tab
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
+      let before = SetRow::load(conn, &[update.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
       diesel::update(sets::table.filter(sets::id.eq(update.id)))
-        .set(sets::name.eq(&update.name))
+        .set((
+          sets::name.eq(&update.name),
+          sets::updated_at.eq(time),
+          sets::version.eq(sets::version + 1),
+        ))
         .execute(conn)?;
+      changes::record(
+        conn,
+        id,
+        Entity::Set,
+        &[update.id],
+        ChangeKind::Upsert,
+        time,
+      )?;
+
+      Audit::new(Some(id), id, time).updated(conn, before)?;
 
       let look_ahead = executor.look_ahead();
 
@@ -184,6 +251,7 @@ impl HandleDelete<Set, SetDeleteset, Pg, GQLContext<DBConnection>> for sets::tab
       let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
       let target_user_id = sets::table
         .filter(sets::id.eq(to_delete.id))
+        .filter(sets::deleted_at.is_null())
         .select(sets::owner)
         .get_result::<i32>(conn)?;
 
@@ -191,13 +259,12 @@ impl HandleDelete<Set, SetDeleteset, Pg, GQLContext<DBConnection>> for sets::tab
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
-      let d = diesel::delete(sets::table.filter(sets::id.eq(to_delete.id)));
-      executor.resolve_with_ctx(
-        &(),
-        &DeletedCount {
-          count: d.execute(conn)? as _,
-        },
-      )
+      let before = SetRow::load(conn, &[to_delete.id])?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      let count = trash::trash_set(conn, to_delete.id, time)?;
+      Audit::new(Some(id), id, time).deleted(conn, before)?;
+
+      executor.resolve_with_ctx(&(), &DeletedCount { count: count as _ })
     })
   }
 }
@@ -1,4 +1,5 @@
 use bcrypt::hash;
+use chrono_tz::Tz;
 use diesel::{pg::Pg, prelude::*};
 use juniper::*;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -12,8 +13,13 @@ use wundergraph::{
 };
 
 use crate::{
-  db::{schema::users, DBConnection},
-  graphql::{query::User, GQLContext},
+  db::{
+    audit::{Audit, Audited},
+    models::UserRow,
+    schema::users,
+    DBConnection,
+  },
+  graphql::{authorization::UserRole, query::User, GQLContext},
   TRCError,
 };
 
@@ -21,6 +27,7 @@ use crate::{
 pub struct NewUser {
   username: String,
   password: String,
+  native_language: Option<i32>,
 }
 
 impl HandleInsert<User, NewUser, Pg, GQLContext<DBConnection>> for users::table {
@@ -39,12 +46,15 @@ impl HandleInsert<User, NewUser, Pg, GQLContext<DBConnection>> for users::table
         .values((
           users::username.eq(insertable.username),
           users::password.eq(hashed),
+          users::native_language.eq(insertable.native_language),
           users::created_at.eq(time),
           users::updated_at.eq(time),
         ))
         .returning(users::id)
         .get_result::<i32>(conn)?;
 
+      Audit::new(ctx.user_id, inserted, time).inserted::<UserRow>(conn, &[inserted])?;
+
       let query = <User as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(users::id.eq(inserted));
       let items = User::load(&look_ahead, selection, executor, query)?;
@@ -66,20 +76,31 @@ impl HandleBatchInsert<User, NewUser, Pg, GQLContext<DBConnection>> for users::t
       let look_ahead = executor.look_ahead();
       let insert = insertable
         .into_iter()
-        .map(|NewUser { username, password }| {
-          (
-            users::username.eq(username),
-            users::password.eq(hash(&password, 10).unwrap()),
-            users::created_at.eq(time),
-            users::updated_at.eq(time),
-          )
-        })
+        .map(
+          |NewUser {
+             username,
+             password,
+             native_language,
+           }| {
+            (
+              users::username.eq(username),
+              users::password.eq(hash(&password, 10).unwrap()),
+              users::native_language.eq(native_language),
+              users::created_at.eq(time),
+              users::updated_at.eq(time),
+            )
+          },
+        )
         .collect::<Vec<_>>();
       let inserted = diesel::insert_into(users::table)
         .values(insert)
         .returning(users::id)
         .get_results::<i32>(conn)?;
 
+      for user in &inserted {
+        Audit::new(ctx.user_id, *user, time).inserted::<UserRow>(conn, &[*user])?;
+      }
+
       let query = <User as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
         .filter(users::id.eq_any(inserted));
       let items = User::load(&look_ahead, selection, executor, query)?;
@@ -91,7 +112,14 @@ impl HandleBatchInsert<User, NewUser, Pg, GQLContext<DBConnection>> for users::t
 #[derive(GraphQLInputObject, Debug)]
 pub struct UserChangeset {
   id: i32,
-  password: String,
+  password: Option<String>,
+  native_language: Option<i32>,
+  /// IANA time zone, such as Europe/Amsterdam
+  timezone: Option<String>,
+  /// Reviews to do each day, 0 for no goal
+  daily_review_goal: Option<i32>,
+  /// Cards to study for the first time each day, 0 for no goal
+  daily_new_card_goal: Option<i32>,
 }
 
 impl HandleUpdate<User, UserChangeset, Pg, GQLContext<DBConnection>> for users::table {
@@ -103,7 +131,12 @@ impl HandleUpdate<User, UserChangeset, Pg, GQLContext<DBConnection>> for users::
     let ctx = executor.context();
     let conn = ctx.get_connection();
     conn.transaction(|| {
-      let id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+      // A reset password has to be replaced before anything else changes
+      let id = match (ctx.user_id, ctx.password_reset) {
+        (Some(id), _) => id,
+        (None, Some(id)) if update.password.is_some() => id,
+        _ => return ExecutionResult::from(TRCError::Unauthorized),
+      };
       let target_user_id = users::table
         .select(users::id)
         .filter(users::id.eq(update.id))
@@ -113,13 +146,50 @@ impl HandleUpdate<User, UserChangeset, Pg, GQLContext<DBConnection>> for users::
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
-      let hashed = hash(&update.password, 10)?;
+      if let Some(timezone) = &update.timezone {
+        timezone
+          .parse::<Tz>()
+          .map_err(|_| TRCError::Unknown(format!("{} isn't an IANA time zone", timezone)))?;
+      }
+      let goals = [update.daily_review_goal, update.daily_new_card_goal];
+      if goals.iter().flatten().any(|goal| *goal < 0) {
+        return ExecutionResult::from(TRCError::Unknown(
+          "Daily goals can't be negative".to_owned(),
+        ));
+      }
+
+      let before = UserRow::load(conn, &[update.id])?;
       let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
 
+      if let Some(password) = &update.password {
+        diesel::update(users::table.filter(users::id.eq(update.id)))
+          .set((
+            users::password.eq(hash(password, 10)?),
+            users::password_reset.eq(false),
+          ))
+          .execute(conn)?;
+      }
       diesel::update(users::table.filter(users::id.eq(update.id)))
-        .set((users::password.eq(&hashed), users::updated_at.eq(time)))
+        .set((
+          update
+            .native_language
+            .map(|language| users::native_language.eq(language)),
+          update
+            .timezone
+            .as_ref()
+            .map(|timezone| users::timezone.eq(timezone)),
+          update
+            .daily_review_goal
+            .map(|goal| users::daily_review_goal.eq(Some(goal).filter(|goal| *goal > 0))),
+          update
+            .daily_new_card_goal
+            .map(|goal| users::daily_new_card_goal.eq(Some(goal).filter(|goal| *goal > 0))),
+          users::updated_at.eq(time),
+        ))
         .execute(conn)?;
 
+      Audit::new(Some(id), id, time).updated(conn, before)?;
+
       let look_ahead = executor.look_ahead();
 
       let query = <User as LoadingHandler<_, PgConnection>>::build_query(&[], &look_ahead)?
@@ -149,17 +219,17 @@ impl HandleDelete<User, UserDeleteset, Pg, GQLContext<DBConnection>> for users::
         .filter(users::id.eq(to_delete.id))
         .get_result::<i32>(conn)?;
 
-      if id != target_user_id {
+      // Administrators may remove any account
+      if id != target_user_id && ctx.role != UserRole::ADMIN {
         return ExecutionResult::from(TRCError::Unauthorized);
       };
 
-      let d = diesel::delete(users::table.filter(users::id.eq(to_delete.id)));
-      executor.resolve_with_ctx(
-        &(),
-        &DeletedCount {
-          count: d.execute(conn)? as _,
-        },
-      )
+      let before = UserRow::load(conn, &[to_delete.id])?;
+      let count = diesel::delete(users::table.filter(users::id.eq(to_delete.id))).execute(conn)?;
+      let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+      Audit::new(Some(id), target_user_id, time).deleted(conn, before)?;
+
+      executor.resolve_with_ctx(&(), &DeletedCount { count: count as _ })
     })
   }
 }
@@ -1,30 +1,44 @@
-use crate::TRCError;
+use crate::{
+    config::{ImageSource, MediaConfig, TtsConfig, TtsProvider},
+    db::{
+        changes::{self, ChangeKind, Entity},
+        schema::{backs, cards, decks, languages, users},
+        DBConnection,
+    },
+    media::{self, tts, ImageUrls, MediaRow, NewMedia},
+    TRCError,
+};
+use diesel::{dsl, prelude::*};
 use google_translate_tts;
-use reqwest;
+use reqwest::{self, header::CONTENT_TYPE};
 use select::{
     document::Document,
     predicate::{And, Attr, Name},
 };
-use std::{
-    fs::{create_dir, File},
-    io::copy,
-    path::Path,
-};
 
-pub fn get_image_from_google(
-    language_abbr: &str,
-    word: &str,
-    sanitized: &str,
-) -> Result<(), TRCError> {
-    if Path::new(&format!(
-        "./static/images/{}/{}.jpg",
-        language_abbr, sanitized
-    ))
-    .exists()
-    {
-        return Ok(());
-    }
+/// Downloads a file, taking its type from the response unless it is missing
+fn download(url: &str, default_mime: &str) -> Result<NewMedia, TRCError> {
+    let mut response = reqwest::get(url)?.error_for_status()?;
+    let mime = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty() && value != "application/octet-stream")
+        .unwrap_or_else(|| default_mime.to_owned());
+    let mut bytes = vec![];
+    response.copy_to(&mut bytes)?;
+
+    Ok(NewMedia {
+        bytes,
+        mime,
+        source: Some(url.to_owned()),
+        licence: None,
+    })
+}
 
+pub fn get_image_from_google(word: &str) -> Result<NewMedia, TRCError> {
     let mut search_response = reqwest::get(&format!(
         "https://www.google.com/search?q={}&tbm=isch&tbs=ift:jpg",
         word
@@ -35,53 +49,317 @@ pub fn get_image_from_google(
     let url = document
         .find(And(Attr("alt", ""), Name("img")))
         .filter_map(|n| n.attr("src"))
-        .nth(0)
+        .next()
         .ok_or(TRCError::Unknown(
             "Failed to find first image in document".to_owned(),
         ))?;
-    let mut source = reqwest::get(url)?;
-
-    if !Path::new(&format!("./static/images/{}", language_abbr)).exists() {
-        create_dir(&format!("./static/images/{}", language_abbr))?;
-    }
-
-    let mut dest = File::create(format!(
-        "./static/images/{}/{}.jpg",
-        language_abbr, sanitized
-    ))?;
 
-    copy(&mut source, &mut dest)?;
+    download(url, "image/jpeg")
+}
 
-    Ok(())
+pub fn get_audio_from_google(language_abbr: &str, word: &str) -> Result<NewMedia, TRCError> {
+    download(
+        &google_translate_tts::url(word, language_abbr),
+        "audio/mpeg",
+    )
 }
 
-pub fn get_audio_from_google(
+/// Pronunciation audio from the configured provider. `voice` overrides the
+/// voice configured for the language.
+pub fn get_audio(
+    config: &TtsConfig,
     language_abbr: &str,
+    voice: Option<&str>,
     word: &str,
-    sanitized: &str,
-) -> Result<(), TRCError> {
-    if Path::new(&format!(
-        "./static/audio/{}/{}.mp3",
-        language_abbr, sanitized
-    ))
-    .exists()
-    {
-        return Ok(());
+) -> Result<NewMedia, TRCError> {
+    match config.provider {
+        TtsProvider::Google => get_audio_from_google(language_abbr, word),
+        TtsProvider::Local => {
+            tts::speak(config, voice.unwrap_or(config.voice(language_abbr)), word)
+        }
     }
+}
 
-    let url = google_translate_tts::url(word, language_abbr);
-    let mut source = reqwest::get(&url)?;
+/// Pronunciation audio for text in one of the languages, in the voice set
+/// for the language if it has one
+fn language_audio(
+    conn: &DBConnection,
+    config: &TtsConfig,
+    language: i32,
+    text: &str,
+) -> Result<NewMedia, TRCError> {
+    let (abbr, voice) = languages::table
+        .select((languages::abbreviation, languages::tts_voice))
+        .filter(languages::id.eq(language))
+        .get_result::<(String, Option<String>)>(conn)?;
+    get_audio(config, &abbr, voice.as_deref(), text)
+}
 
-    if !Path::new(&format!("./static/audio/{}", language_abbr)).exists() {
-        create_dir(&format!("./static/audio/{}", language_abbr))?;
+/// Fetches media for a card back and stores it, returning the new row's id.
+/// Media already fetched for the same text in the same language is reused.
+pub fn insert_back(
+    conn: &DBConnection,
+    config: &MediaConfig,
+    language: i32,
+    text: String,
+    time: i64,
+) -> Result<i32, TRCError> {
+    media::lock_shared(conn)?;
+    let mut known = backs::table
+        .select((backs::audio_media, backs::image_media))
+        .filter(backs::language.eq(language))
+        .filter(backs::text.eq(&text))
+        .filter(backs::audio_media.is_not_null())
+        .into_boxed();
+    if config.images != ImageSource::None {
+        known = known.filter(backs::image_media.is_not_null());
     }
+    let known = known.first::<(Option<i32>, Option<i32>)>(conn).optional()?;
+    let (audio, image) = match known {
+        Some((Some(audio), image)) => (
+            MediaRow::find(conn, audio)?,
+            match image {
+                Some(image) => Some(media::stored_image(conn, MediaRow::find(conn, image)?)?),
+                None => None,
+            },
+        ),
+        _ => (
+            media::store(
+                conn,
+                config,
+                language_audio(conn, &config.tts, language, &text)?,
+                time,
+            )?,
+            match config.images {
+                ImageSource::Google => Some(media::store_image(
+                    conn,
+                    config,
+                    get_image_from_google(&text)?,
+                    time,
+                )?),
+                ImageSource::None => None,
+            },
+        ),
+    };
 
-    let mut dest = File::create(format!(
-        "./static/audio/{}/{}.mp3",
-        language_abbr, sanitized
-    ))?;
+    let image = image
+        .map(|image| image.urls(config))
+        .unwrap_or_else(ImageUrls::default);
 
-    copy(&mut source, &mut dest)?;
+    Ok(diesel::insert_into(backs::table)
+        .values((
+            backs::text.eq(text),
+            backs::language.eq(language),
+            backs::image.eq(image.image),
+            backs::audio.eq(Some(config.url(&audio.path()))),
+            backs::image_media.eq(image.image_media),
+            backs::audio_media.eq(audio.id),
+            backs::image_thumbnail.eq(image.thumbnail),
+            backs::image_card.eq(image.card),
+            backs::updated_at.eq(time),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(conn)?)
+}
+
+/// Generates audio for the text of a card back and stores it, returning its
+/// URL and media id. Audio already made for the same text in the same
+/// language is reused.
+pub fn insert_back_audio(
+    conn: &DBConnection,
+    config: &MediaConfig,
+    language: i32,
+    text: &str,
+    time: i64,
+) -> Result<(String, i32), TRCError> {
+    media::lock_shared(conn)?;
+    let known = backs::table
+        .select(backs::audio_media)
+        .filter(backs::language.eq(language))
+        .filter(backs::text.eq(text))
+        .filter(backs::audio_media.is_not_null())
+        .first::<Option<i32>>(conn)
+        .optional()?
+        .flatten();
+    let audio = match known {
+        Some(id) => MediaRow::find(conn, id)?,
+        None => media::store(
+            conn,
+            config,
+            language_audio(conn, &config.tts, language, text)?,
+            time,
+        )?,
+    };
+    Ok((config.url(&audio.path()), audio.id))
+}
 
+/// Makes sure a language exists and hasn't been disabled, so decks can use it
+pub fn check_language(conn: &DBConnection, language: i32) -> Result<(), TRCError> {
+    let disabled = languages::table
+        .select(languages::disabled)
+        .filter(languages::id.eq(language))
+        .get_result::<bool>(conn)
+        .optional()?;
+    match disabled {
+        Some(false) => Ok(()),
+        Some(true) => Err(TRCError::Unknown(format!(
+            "Language {} has been disabled",
+            language
+        ))),
+        None => Err(TRCError::Unknown(format!(
+            "No language with id {}",
+            language
+        ))),
+    }
+}
+
+/// Whether all the cards are in the deck and not in the trash, as sets may
+/// only hold cards of their own deck
+pub fn cards_in_deck(conn: &DBConnection, deck: i32, card_ids: &[i32]) -> QueryResult<bool> {
+    let mut card_ids = card_ids.to_vec();
+    card_ids.sort_unstable();
+    card_ids.dedup();
+    let found = cards::table
+        .select(dsl::count_star())
+        .filter(cards::id.eq_any(&card_ids))
+        .filter(cards::deck.eq(deck))
+        .filter(cards::deleted_at.is_null())
+        .get_result::<i64>(conn)?;
+    Ok(found == card_ids.len() as i64)
+}
+
+/// The language the fronts of a deck are written in: the deck's front
+/// language, or else its owner's native language
+pub fn front_language(conn: &DBConnection, deck: i32) -> QueryResult<Option<i32>> {
+    let (front_language, native_language) = decks::table
+        .inner_join(users::table)
+        .select((decks::front_language, users::native_language))
+        .filter(decks::id.eq(deck))
+        .get_result::<(Option<i32>, Option<i32>)>(conn)?;
+    Ok(front_language.or(native_language))
+}
+
+/// Generates audio for the front of a card and stores it, returning its URL
+/// and media id. Audio already made for the same front in the same language
+/// is reused.
+pub fn insert_front_audio(
+    conn: &DBConnection,
+    config: &MediaConfig,
+    language: Option<i32>,
+    front: &str,
+    time: i64,
+) -> Result<(String, i32), TRCError> {
+    let language = language.ok_or_else(|| {
+        TRCError::Unknown(
+            "Set the front language of the deck, or your native language, to generate audio for fronts"
+                .to_owned(),
+        )
+    })?;
+    media::lock_shared(conn)?;
+    let known = cards::table
+        .select(cards::front_audio_media)
+        .filter(cards::front_language.eq(language))
+        .filter(cards::front.eq(front))
+        .filter(cards::front_audio_media.is_not_null())
+        .first::<Option<i32>>(conn)
+        .optional()?
+        .flatten();
+    let audio = match known {
+        Some(id) => MediaRow::find(conn, id)?,
+        None => media::store(
+            conn,
+            config,
+            language_audio(conn, &config.tts, language, front)?,
+            time,
+        )?,
+    };
+    Ok((config.url(&audio.path()), audio.id))
+}
+
+/// Changes to a card, fields left `None` stay as they are
+#[derive(Default)]
+pub struct CardEdit<'a> {
+    pub front: Option<&'a str>,
+    pub back: Option<&'a str>,
+    pub link: Option<&'a str>,
+    pub front_language: Option<i32>,
+    /// Adds audio to the front, or removes it
+    pub front_audio: Option<bool>,
+}
+
+/// Applies an edit to a card of a deck of `owner` and records it in their
+/// change log. Audio follows the text, so it is made again for a front or
+/// back that changes.
+pub fn update_card(
+    conn: &DBConnection,
+    config: &MediaConfig,
+    owner: i32,
+    id: i32,
+    edit: CardEdit,
+    time: i64,
+) -> Result<(), TRCError> {
+    let (current_front, current_link, back, deck, current_language, had_audio) = cards::table
+        .select((
+            cards::front,
+            cards::link,
+            cards::back,
+            cards::deck,
+            cards::front_language,
+            cards::front_audio_media.is_not_null(),
+        ))
+        .filter(cards::id.eq(id))
+        .get_result::<(String, Option<String>, i32, i32, Option<i32>, bool)>(conn)?;
+
+    let front = edit.front.unwrap_or(&current_front);
+    // Cards made before their deck had a front language pick it up here
+    let language = match edit.front_language.or(current_language) {
+        Some(language) => Some(language),
+        None => front_language(conn, deck)?,
+    };
+    let wants_audio = edit.front_audio.unwrap_or(had_audio);
+    let front_audio =
+        if wants_audio && (!had_audio || front != current_front || language != current_language) {
+            let (url, media) = insert_front_audio(conn, config, language, front, time)?;
+            Some((Some(url), Some(media)))
+        } else if !wants_audio && had_audio {
+            Some((None, None))
+        } else {
+            None
+        };
+    diesel::update(cards::table.filter(cards::id.eq(id)))
+        .set((
+            cards::front.eq(front),
+            cards::link.eq(edit.link.or(current_link.as_deref())),
+            cards::front_language.eq(language),
+            front_audio.map(|(url, media)| {
+                (
+                    cards::front_audio.eq(url),
+                    cards::front_audio_media.eq(media),
+                )
+            }),
+            cards::updated_at.eq(time),
+            cards::version.eq(cards::version + 1),
+        ))
+        .execute(conn)?;
+    if let Some(text) = edit.back {
+        let (current_text, back_language) = backs::table
+            .select((backs::text, backs::language))
+            .filter(backs::id.eq(back))
+            .get_result::<(String, i32)>(conn)?;
+        if text != current_text {
+            // The old recording would read out the old text
+            let (audio, media) = insert_back_audio(conn, config, back_language, text, time)?;
+            diesel::update(backs::table.filter(backs::id.eq(back)))
+                .set((
+                    backs::text.eq(text),
+                    backs::audio.eq(Some(audio)),
+                    backs::audio_media.eq(Some(media)),
+                    backs::updated_at.eq(time),
+                ))
+                .execute(conn)?;
+            changes::record(conn, owner, Entity::Back, &[back], ChangeKind::Upsert, time)?;
+        }
+    }
+    changes::record(conn, owner, Entity::Card, &[id], ChangeKind::Upsert, time)?;
     Ok(())
 }
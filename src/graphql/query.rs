@@ -5,13 +5,26 @@ use diesel::{
   sql_types::SmallInt,
   Identifiable,
 };
+use juniper::FieldResult;
 use std::io::Write;
 use wundergraph::{
   query_builder::types::{HasMany, HasOne, WundergraphValue},
+  scalar::WundergraphScalarValue,
   WundergraphEntity,
 };
 
-use crate::db::schema::*;
+use super::{
+  audit::{self, AuditEntry},
+  catalogue::{self, PublicDeck},
+  goals::{self, Progress},
+  groups::{self, Group, GroupAssignment, GroupMember, StudentProgress},
+  members::{self, DeckMember},
+  quiz::{self, QuizQuestion},
+  trash::{self, TrashItem},
+  updates::{self, DeckUpdate},
+  GQLContext,
+};
+use crate::db::{changes::Entity, schema::*, DBConnection};
 
 #[derive(
   Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, WundergraphValue, Eq, PartialEq, Hash,
@@ -55,7 +68,45 @@ where
   }
 }
 
+/// Which way text in a language runs
+#[derive(
+  Debug, Copy, Clone, AsExpression, FromSqlRow, GraphQLEnum, WundergraphValue, Eq, PartialEq, Hash,
+)]
+#[sql_type = "SmallInt"]
+pub enum TextDirection {
+  LTR = 0,
+  RTL = 1,
+}
+
+impl<DB> ToSql<SmallInt, DB> for TextDirection
+where
+  DB: Backend,
+  i16: ToSql<SmallInt, DB>,
+{
+  fn to_sql<W: Write>(&self, out: &mut serialize::Output<'_, W, DB>) -> serialize::Result {
+    (*self as i16).to_sql(out)
+  }
+}
+
+impl<DB> FromSql<SmallInt, DB> for TextDirection
+where
+  DB: Backend,
+  i16: FromSql<SmallInt, DB>,
+{
+  fn from_sql(bytes: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+    let value = i16::from_sql(bytes)?;
+    Ok(match value {
+      0 => TextDirection::LTR,
+      1 => TextDirection::RTL,
+      _ => return Err(format!("Unknown text direction {}", value).into()),
+    })
+  }
+}
+
+// Wundergraph resolves entity fields straight from the query, so the
+// structs only describe the schema and their fields are never read.
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "users"]
 pub struct User {
@@ -63,18 +114,30 @@ pub struct User {
   pub username: String,
   created_at: i64,
   updated_at: i64,
+  native_language: Option<HasOne<i32, Language>>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "languages"]
 pub struct Language {
   id: i32,
   name: String,
   abbreviation: String,
+  /// BCP-47 tag
+  tag: String,
+  /// ISO 15924 script code
+  script: Option<String>,
+  direction: TextDirection,
+  /// Voice used for text to speech, instead of the configured one
+  tts_voice: Option<String>,
+  /// Disabled languages can't be picked for new decks
+  disabled: bool,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "decks"]
 pub struct Deck {
@@ -82,9 +145,18 @@ pub struct Deck {
   name: String,
   owner: HasOne<i32, User>,
   language: HasOne<i32, Language>,
+  updated_at: i64,
+  version: i32,
+  description: Option<String>,
+  licence: Option<String>,
+  published: bool,
+  source: Option<i32>,
+  /// Language of the fronts, when they aren't in the owner's native language
+  front_language: Option<HasOne<i32, Language>>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "cards"]
 pub struct Card {
@@ -94,11 +166,17 @@ pub struct Card {
   back: HasOne<i32, Back>,
   deck: HasOne<i32, Deck>,
   link: Option<String>,
+  updated_at: i64,
+  version: i32,
+  origin: Option<i32>,
+  front_language: Option<HasOne<i32, Language>>,
+  front_audio: Option<String>,
   sets: HasMany<SetCard, set_cards::card_id>,
   scores: HasMany<Score, scores::card>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "scores"]
 pub struct Score {
@@ -106,9 +184,12 @@ pub struct Score {
   created_at: i64,
   card: HasOne<i32, Card>,
   value: ScoreValue,
+  updated_at: i64,
+  owner: HasOne<i32, User>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "backs"]
 pub struct Back {
@@ -117,9 +198,14 @@ pub struct Back {
   language: HasOne<i32, Language>,
   audio: Option<String>,
   image: Option<String>,
+  updated_at: i64,
+  /// Smaller versions of the image
+  image_thumbnail: Option<String>,
+  image_card: Option<String>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "sets"]
 pub struct Set {
@@ -128,10 +214,13 @@ pub struct Set {
   name: String,
   deck: HasOne<i32, Deck>,
   owner: HasOne<i32, User>,
+  updated_at: i64,
+  version: i32,
   cards: HasMany<SetCard, set_cards::set_id>,
 }
 
 #[derive(Clone, Debug, Identifiable, Queryable, WundergraphEntity)]
+#[allow(dead_code)]
 #[primary_key(id)]
 #[table_name = "set_cards"]
 pub struct SetCard {
@@ -139,6 +228,7 @@ pub struct SetCard {
   id: i32,
   card_id: HasOne<i32, Card>,
   set_id: HasOne<i32, Set>,
+  updated_at: i64,
 }
 
 wundergraph::query_object! {
@@ -153,3 +243,115 @@ wundergraph::query_object! {
     SetCard,
   }
 }
+
+/// Query fields that don't map onto a single entity
+pub struct QueryExtensions;
+
+#[juniper::object(Context = GQLContext<DBConnection>, Scalar = WundergraphScalarValue)]
+impl QueryExtensions {
+  /// Decks, cards and sets in the trash, most recently deleted first
+  fn trash(
+    context: &GQLContext<DBConnection>,
+  ) -> FieldResult<Vec<TrashItem>, WundergraphScalarValue> {
+    trash::list(context)
+  }
+
+  /// Changes made to the caller's data, newest first. Administrators see
+  /// changes to everyone's data.
+  fn audit_log(
+    context: &GQLContext<DBConnection>,
+    entity: Option<Entity>,
+    entity_id: Option<i32>,
+    limit: Option<i32>,
+  ) -> FieldResult<Vec<AuditEntry>, WundergraphScalarValue> {
+    audit::list(context, entity, entity_id, limit)
+  }
+
+  /// Published decks, most cloned first
+  fn public_decks(
+    context: &GQLContext<DBConnection>,
+    language: Option<String>,
+    search: Option<String>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+  ) -> FieldResult<Vec<PublicDeck>, WundergraphScalarValue> {
+    catalogue::public_decks(context, language, search, limit, offset)
+  }
+
+  /// Upstream changes not yet merged into a cloned deck
+  fn deck_updates(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+  ) -> FieldResult<Vec<DeckUpdate>, WundergraphScalarValue> {
+    updates::deck_updates(context, deck)
+  }
+
+  /// Users a deck is shared with
+  fn deck_members(
+    context: &GQLContext<DBConnection>,
+    deck: i32,
+  ) -> FieldResult<Vec<DeckMember>, WundergraphScalarValue> {
+    members::list(context, deck)
+  }
+
+  /// Decks others have invited the caller to, waiting for an answer
+  fn invitations(
+    context: &GQLContext<DBConnection>,
+  ) -> FieldResult<Vec<DeckMember>, WundergraphScalarValue> {
+    members::invitations(context)
+  }
+
+  /// Multiple choice questions on a deck, or on one of its sets, for the
+  /// cards most in need of practice or, with `random`, any cards
+  fn quiz(
+    context: &GQLContext<DBConnection>,
+    deck: Option<i32>,
+    set: Option<i32>,
+    size: Option<i32>,
+    choices: Option<i32>,
+    random: Option<bool>,
+  ) -> FieldResult<Vec<QuizQuestion>, WundergraphScalarValue> {
+    quiz::quiz(context, deck, set, size, choices, random.unwrap_or(false))
+  }
+
+  /// Progress towards the caller's daily goals, their streaks and
+  /// achievements
+  fn progress(context: &GQLContext<DBConnection>) -> FieldResult<Progress, WundergraphScalarValue> {
+    goals::progress(context)
+  }
+
+  /// Groups the caller teaches or studies in
+  fn groups(context: &GQLContext<DBConnection>) -> FieldResult<Vec<Group>, WundergraphScalarValue> {
+    groups::list(context)
+  }
+
+  fn group_members(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+  ) -> FieldResult<Vec<GroupMember>, WundergraphScalarValue> {
+    groups::members(context, group)
+  }
+
+  /// Groups others have invited the caller to, waiting for an answer
+  fn group_invitations(
+    context: &GQLContext<DBConnection>,
+  ) -> FieldResult<Vec<GroupMember>, WundergraphScalarValue> {
+    groups::invitations(context)
+  }
+
+  /// Decks and sets assigned to a group
+  fn group_assignments(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+  ) -> FieldResult<Vec<GroupAssignment>, WundergraphScalarValue> {
+    groups::assignments(context, group)
+  }
+
+  /// How each student of a group is doing on its assignments, for teachers
+  fn group_progress(
+    context: &GQLContext<DBConnection>,
+    group: i32,
+  ) -> FieldResult<Vec<StudentProgress>, WundergraphScalarValue> {
+    groups::progress(context, group)
+  }
+}
@@ -0,0 +1,322 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use rand::{seq::SliceRandom, thread_rng};
+use std::{
+  collections::{HashMap, HashSet},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_card, authorize_deck, Access},
+  query::ScoreValue,
+  GQLContext,
+};
+use crate::{
+  db::{
+    audit::Audit,
+    changes::{self, ChangeKind, Entity},
+    models::ScoreRow,
+    schema::{backs, cards, decks, scores, set_cards, sets},
+    DBConnection,
+  },
+  TRCError,
+};
+
+const DEFAULT_SIZE: i32 = 10;
+const MAX_SIZE: i32 = 100;
+const DEFAULT_CHOICES: i32 = 4;
+const MAX_CHOICES: i32 = 10;
+/// Backs from other decks looked at when a deck has too few of its own
+const LANGUAGE_POOL: i64 = 200;
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct QuizQuestion {
+  card: i32,
+  front: String,
+  front_audio: Option<String>,
+  /// The back of the card among backs of other cards, in random order
+  choices: Vec<String>,
+}
+
+#[derive(GraphQLInputObject, Debug)]
+pub struct QuizAnswer {
+  card: i32,
+  /// The choice that was picked
+  answer: String,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct QuizResult {
+  card: i32,
+  correct: bool,
+  expected: String,
+  /// The score recorded for the answer
+  score: ScoreValue,
+}
+
+/// A card in the quiz, along with its back
+#[derive(Queryable)]
+struct Candidate {
+  card: i32,
+  front: String,
+  front_audio: Option<String>,
+  back: String,
+  language: i32,
+}
+
+/// Multiple choice questions on the cards of a deck or a set. Cards the user
+/// never scored come first, then those with the lowest latest score, unless
+/// `random` is set.
+pub fn quiz(
+  ctx: &GQLContext<DBConnection>,
+  deck: Option<i32>,
+  set: Option<i32>,
+  size: Option<i32>,
+  choices: Option<i32>,
+  random: bool,
+) -> FieldResult<Vec<QuizQuestion>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  let deck = match (deck, set) {
+    (Some(deck), None) => deck,
+    (None, Some(set)) => sets::table
+      .select(sets::deck)
+      .filter(sets::id.eq(set))
+      .filter(sets::deleted_at.is_null())
+      .get_result::<i32>(conn)?,
+    _ => {
+      let message = "Quizzes are on either a deck or a set".to_owned();
+      return Err(TRCError::Unknown(message).into());
+    }
+  };
+  authorize_deck(conn, user_id, deck, Access::View)?;
+  let size = size.unwrap_or(DEFAULT_SIZE).clamp(0, MAX_SIZE) as usize;
+  let choices = choices.unwrap_or(DEFAULT_CHOICES).clamp(1, MAX_CHOICES) as usize;
+
+  let deck_cards = cards::table
+    .inner_join(backs::table)
+    .select((
+      cards::id,
+      cards::front,
+      cards::front_audio,
+      backs::text,
+      backs::language,
+    ))
+    .filter(cards::deck.eq(deck))
+    .filter(cards::deleted_at.is_null())
+    .order(cards::id.asc())
+    .load::<Candidate>(conn)?;
+  let card_sets = set_cards::table
+    .inner_join(sets::table)
+    .select((set_cards::card_id, set_cards::set_id))
+    .filter(sets::deck.eq(deck))
+    .filter(sets::deleted_at.is_null())
+    .load::<(i32, i32)>(conn)?
+    .into_iter()
+    .fold(
+      HashMap::<i32, HashSet<i32>>::new(),
+      |mut card_sets, (card, set)| {
+        card_sets.entry(card).or_default().insert(set);
+        card_sets
+      },
+    );
+
+  let mut picked = deck_cards
+    .iter()
+    .filter(|candidate| match set {
+      Some(set) => card_sets
+        .get(&candidate.card)
+        .is_some_and(|sets| sets.contains(&set)),
+      None => true,
+    })
+    .collect::<Vec<_>>();
+  picked.shuffle(&mut thread_rng());
+  if !random {
+    let latest = latest_scores(conn, user_id, &picked)?;
+    picked.sort_by_key(|candidate| match latest.get(&candidate.card) {
+      None => (false, 0, 0),
+      Some((value, time)) => (true, *value as i16, *time),
+    });
+  }
+  picked.truncate(size);
+
+  // Backs of the same deck first, then of visible decks in the same language
+  let mut pool = deck_cards
+    .iter()
+    .map(|candidate| (Some(candidate.card), candidate.back.clone()))
+    .collect::<Vec<_>>();
+  let languages = picked
+    .iter()
+    .map(|candidate| candidate.language)
+    .collect::<HashSet<_>>();
+  if deck_cards.len() < choices + 1 {
+    pool.extend(
+      cards::table
+        .inner_join(backs::table)
+        .inner_join(decks::table)
+        .select(backs::text)
+        .filter(backs::language.eq_any(languages))
+        .filter(decks::published.eq(true).or(decks::owner.eq(user_id)))
+        .filter(decks::id.ne(deck))
+        .filter(cards::deleted_at.is_null())
+        .filter(decks::deleted_at.is_null())
+        .limit(LANGUAGE_POOL)
+        .load::<String>(conn)?
+        .into_iter()
+        .map(|text| (None, text)),
+    );
+  }
+
+  Ok(
+    picked
+      .into_iter()
+      .map(|candidate| QuizQuestion {
+        card: candidate.card,
+        front: candidate.front.clone(),
+        front_audio: candidate.front_audio.clone(),
+        choices: choose(candidate, &pool, &card_sets, choices),
+      })
+      .collect(),
+  )
+}
+
+/// The value and time of the latest score the user gave each card
+fn latest_scores(
+  conn: &DBConnection,
+  user_id: i32,
+  candidates: &[&Candidate],
+) -> QueryResult<HashMap<i32, (ScoreValue, i64)>> {
+  let ids = candidates
+    .iter()
+    .map(|candidate| candidate.card)
+    .collect::<Vec<_>>();
+  Ok(
+    scores::table
+      .select((scores::card, scores::value, scores::created_at))
+      .filter(scores::owner.eq(user_id))
+      .filter(scores::card.eq_any(ids))
+      .order(scores::created_at.asc())
+      .load::<(i32, ScoreValue, i64)>(conn)?
+      .into_iter()
+      .map(|(card, value, time)| (card, (value, time)))
+      .collect(),
+  )
+}
+
+/// The back of a card along with plausible wrong answers: backs of cards in
+/// the same sets first, then those closest in length
+fn choose(
+  candidate: &Candidate,
+  pool: &[(Option<i32>, String)],
+  card_sets: &HashMap<i32, HashSet<i32>>,
+  choices: usize,
+) -> Vec<String> {
+  let sets = card_sets.get(&candidate.card);
+  let shares_set = |card: Option<i32>| match (sets, card.and_then(|card| card_sets.get(&card))) {
+    (Some(sets), Some(other)) => !sets.is_disjoint(other),
+    _ => false,
+  };
+  let length = candidate.back.chars().count() as i64;
+  let answer = candidate.back.trim().to_lowercase();
+
+  let mut seen = HashSet::new();
+  seen.insert(answer);
+  let mut distractors = pool
+    .iter()
+    .filter(|(_, text)| seen.insert(text.trim().to_lowercase()))
+    .collect::<Vec<_>>();
+  distractors.shuffle(&mut thread_rng());
+  distractors.sort_by_key(|(card, text)| {
+    (
+      !shares_set(*card),
+      (text.chars().count() as i64 - length).abs(),
+    )
+  });
+
+  let mut choices = distractors
+    .into_iter()
+    .take(choices - 1)
+    .map(|(_, text)| text.clone())
+    .chain(Some(candidate.back.clone()))
+    .collect::<Vec<_>>();
+  choices.shuffle(&mut thread_rng());
+  choices
+}
+
+/// Checks the answers picked in a quiz and records a score for each: four
+/// for a right answer, since picking it is easier than recalling it, and
+/// one for a wrong answer.
+pub fn submit(
+  ctx: &GQLContext<DBConnection>,
+  answers: Vec<QuizAnswer>,
+) -> FieldResult<Vec<QuizResult>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  if answers.len() > MAX_SIZE as usize {
+    return Err(
+      TRCError::Unknown(format!(
+        "Quizzes can't have more than {} questions",
+        MAX_SIZE
+      ))
+      .into(),
+    );
+  }
+
+  conn.transaction(|| {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let mut results = vec![];
+    for answer in answers {
+      authorize_card(conn, user_id, answer.card, Access::View)?;
+      let expected = cards::table
+        .inner_join(backs::table)
+        .select(backs::text)
+        .filter(cards::id.eq(answer.card))
+        .get_result::<String>(conn)?;
+      let correct = answer.answer.trim() == expected.trim();
+      results.push(QuizResult {
+        card: answer.card,
+        correct,
+        expected,
+        score: if correct {
+          ScoreValue::FOUR
+        } else {
+          ScoreValue::ONE
+        },
+      });
+    }
+    if results.is_empty() {
+      return Ok(results);
+    }
+
+    let inserted = diesel::insert_into(scores::table)
+      .values(
+        results
+          .iter()
+          .map(|result| {
+            (
+              scores::card.eq(result.card),
+              scores::value.eq(result.score),
+              scores::created_at.eq(time),
+              scores::updated_at.eq(time),
+              scores::owner.eq(user_id),
+            )
+          })
+          .collect::<Vec<_>>(),
+      )
+      .returning(scores::id)
+      .get_results::<i32>(conn)?;
+    changes::record(
+      conn,
+      user_id,
+      Entity::Score,
+      &inserted,
+      ChangeKind::Upsert,
+      time,
+    )?;
+    Audit::new(Some(user_id), user_id, time).inserted::<ScoreRow>(conn, &inserted)?;
+    Ok(results)
+  })
+}
@@ -0,0 +1,108 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::GQLContext;
+use crate::{
+  db::{
+    audit::Audit,
+    changes::Entity,
+    models::{CardRow, DeckRow, SetRow},
+    schema::{cards, decks, sets},
+    trash, DBConnection,
+  },
+  TRCError,
+};
+
+#[derive(Debug, Copy, Clone, GraphQLEnum, Eq, PartialEq)]
+pub enum TrashKind {
+  DECK,
+  CARD,
+  SET,
+}
+
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct TrashItem {
+  kind: TrashKind,
+  id: i32,
+  name: String,
+  deleted_at: i64,
+}
+
+pub fn list(ctx: &GQLContext<DBConnection>) -> FieldResult<Vec<TrashItem>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let entries = trash::list(ctx.get_connection(), user_id)?;
+  Ok(
+    entries
+      .into_iter()
+      .map(|entry| TrashItem {
+        kind: match entry.entity {
+          Entity::Deck => TrashKind::DECK,
+          Entity::Card => TrashKind::CARD,
+          _ => TrashKind::SET,
+        },
+        id: entry.id,
+        name: entry.name,
+        deleted_at: entry.deleted_at,
+      })
+      .collect(),
+  )
+}
+
+pub fn restore(
+  ctx: &GQLContext<DBConnection>,
+  kind: TrashKind,
+  id: i32,
+) -> FieldResult<bool, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let (owner, deck_deleted_at) = match kind {
+      TrashKind::DECK => (
+        decks::table
+          .select(decks::owner)
+          .filter(decks::id.eq(id))
+          .get_result::<i32>(conn)?,
+        None,
+      ),
+      TrashKind::CARD => cards::table
+        .inner_join(decks::table)
+        .select((decks::owner, decks::deleted_at))
+        .filter(cards::id.eq(id))
+        .get_result::<(i32, Option<i64>)>(conn)?,
+      TrashKind::SET => sets::table
+        .inner_join(decks::table)
+        .select((sets::owner, decks::deleted_at))
+        .filter(sets::id.eq(id))
+        .get_result::<(i32, Option<i64>)>(conn)?,
+    };
+
+    if owner != user_id {
+      return Err(TRCError::Unauthorized.into());
+    }
+    // Cards and sets can only come back into a deck that isn't trashed itself
+    if deck_deleted_at.is_some() {
+      return Err(
+        TRCError::Unknown("Restore the deck first, it is in the trash".to_owned()).into(),
+      );
+    }
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let audit = Audit::new(Some(user_id), owner, time);
+    let restored = match kind {
+      TrashKind::DECK => trash::restore_deck(conn, user_id, id, time)?,
+      TrashKind::CARD => trash::restore_card(conn, user_id, id, time)?,
+      TrashKind::SET => trash::restore_set(conn, id, time)?,
+    };
+    if restored > 0 {
+      match kind {
+        TrashKind::DECK => audit.restored::<DeckRow>(conn, &[id])?,
+        TrashKind::CARD => audit.restored::<CardRow>(conn, &[id])?,
+        TrashKind::SET => audit.restored::<SetRow>(conn, &[id])?,
+      }
+    }
+    Ok(restored > 0)
+  })
+}
@@ -0,0 +1,324 @@
+use diesel::prelude::*;
+use juniper::FieldResult;
+use std::{
+  collections::{HashMap, HashSet},
+  time::{SystemTime, UNIX_EPOCH},
+};
+use wundergraph::{scalar::WundergraphScalarValue, WundergraphContext};
+
+use super::{
+  authorization::{authorize_deck, deck_access, Access},
+  catalogue::{copy_card, source_cards, SourceCard},
+  GQLContext,
+};
+use crate::{
+  db::{
+    audit::{Audit, Audited},
+    changes::{self, ChangeKind, Entity},
+    models::CardRow,
+    schema::{backs, cards, decks},
+    trash, DBConnection,
+  },
+  media, TRCError,
+};
+
+#[derive(Debug, Copy, Clone, GraphQLEnum, Eq, PartialEq)]
+pub enum UpdateKind {
+  ADDED,
+  CHANGED,
+  REMOVED,
+  /// A card removed upstream is back, and so is the copy applying the
+  /// removal trashed
+  RESTORED,
+}
+
+/// A difference between a cloned deck and the deck it was cloned from
+#[derive(Debug, Clone, GraphQLObject)]
+#[graphql(scalar = WundergraphScalarValue)]
+pub struct DeckUpdate {
+  kind: UpdateKind,
+  /// The upstream card
+  origin: i32,
+  /// The card in the clone, missing for added cards
+  card: Option<i32>,
+  /// Upstream content, missing for removed cards
+  front: Option<String>,
+  back: Option<String>,
+  /// What the clone currently has, missing for added cards
+  local_front: Option<String>,
+  local_back: Option<String>,
+  /// The learner changed the card since it was last merged. Such cards are
+  /// left alone by `applyDeckUpdates`.
+  locally_edited: bool,
+}
+
+#[derive(Queryable)]
+struct LocalCard {
+  id: i32,
+  front: String,
+  back: i32,
+  text: String,
+  version: i32,
+  origin: Option<i32>,
+  origin_version: Option<i32>,
+  merged_version: Option<i32>,
+  deleted_at: Option<i64>,
+  removed_upstream: bool,
+}
+
+impl LocalCard {
+  fn locally_edited(&self) -> bool {
+    self.merged_version != Some(self.version)
+  }
+}
+
+enum Pending {
+  Added(SourceCard),
+  Changed(LocalCard, SourceCard),
+  Removed(LocalCard),
+  Restored(LocalCard, SourceCard),
+}
+
+impl Pending {
+  fn origin(&self) -> i32 {
+    match self {
+      Pending::Added(source) | Pending::Changed(_, source) | Pending::Restored(_, source) => {
+        source.id
+      }
+      Pending::Removed(local) => local.origin.unwrap_or_default(),
+    }
+  }
+
+  fn locally_edited(&self) -> bool {
+    match self {
+      Pending::Added(_) => false,
+      Pending::Changed(local, _) | Pending::Removed(local) | Pending::Restored(local, _) => {
+        local.locally_edited()
+      }
+    }
+  }
+}
+
+impl From<&Pending> for DeckUpdate {
+  fn from(pending: &Pending) -> Self {
+    let (kind, local, source) = match pending {
+      Pending::Added(source) => (UpdateKind::ADDED, None, Some(source)),
+      Pending::Changed(local, source) => (UpdateKind::CHANGED, Some(local), Some(source)),
+      Pending::Removed(local) => (UpdateKind::REMOVED, Some(local), None),
+      Pending::Restored(local, source) => (UpdateKind::RESTORED, Some(local), Some(source)),
+    };
+    DeckUpdate {
+      kind,
+      origin: pending.origin(),
+      card: local.map(|local| local.id),
+      front: source.map(|source| source.front.clone()),
+      back: source.map(|source| source.text.clone()),
+      local_front: local.map(|local| local.front.clone()),
+      local_back: local.map(|local| local.text.clone()),
+      locally_edited: pending.locally_edited(),
+    }
+  }
+}
+
+/// Compares a cloned deck with its source. Returns the clone's owner along
+/// with the pending updates, ordered by upstream card.
+fn pending(conn: &DBConnection, user_id: i32, deck: i32) -> Result<(i32, Vec<Pending>), TRCError> {
+  let owner = authorize_deck(conn, user_id, deck, Access::Edit)?;
+  let source = decks::table
+    .select(decks::source)
+    .filter(decks::id.eq(deck))
+    .get_result::<Option<i32>>(conn)?;
+  let source = match source {
+    Some(source) => source,
+    None => return Ok((owner, vec![])),
+  };
+  let (published, deleted_at) = decks::table
+    .select((decks::published, decks::deleted_at))
+    .filter(decks::id.eq(source))
+    .get_result::<(bool, Option<i64>)>(conn)?;
+  // A trashed upstream deck may still come back, so it isn't treated as empty
+  if deleted_at.is_some() {
+    return Ok((owner, vec![]));
+  }
+  if !published && deck_access(conn, user_id, source)?.is_none() {
+    return Err(TRCError::Unauthorized);
+  }
+
+  // Trashed cards are included so they don't get added again. Those trashed
+  // by the learner stay trashed, those trashed by an update come back with
+  // their upstream card.
+  let local = cards::table
+    .inner_join(backs::table)
+    .select((
+      cards::id,
+      cards::front,
+      cards::back,
+      backs::text,
+      cards::version,
+      cards::origin,
+      cards::origin_version,
+      cards::merged_version,
+      cards::deleted_at,
+      cards::removed_upstream,
+    ))
+    .filter(cards::deck.eq(deck))
+    .filter(cards::origin.is_not_null())
+    .load::<LocalCard>(conn)?;
+  let mut local = local
+    .into_iter()
+    .map(|card| (card.origin.unwrap_or_default(), card))
+    .collect::<HashMap<_, _>>();
+
+  let upstream = source_cards(conn, source)?;
+  let upstream_ids = upstream.iter().map(|card| card.id).collect::<HashSet<_>>();
+  let mut pending = vec![];
+  for card in upstream {
+    match local.remove(&card.id) {
+      None => pending.push(Pending::Added(card)),
+      Some(copy) if copy.deleted_at.is_some() => {
+        if copy.removed_upstream {
+          pending.push(Pending::Restored(copy, card));
+        }
+      }
+      Some(copy) => {
+        if copy.origin_version < Some(card.version) {
+          pending.push(Pending::Changed(copy, card));
+        }
+      }
+    }
+  }
+  pending.extend(
+    local
+      .into_iter()
+      .filter(|(origin, copy)| copy.deleted_at.is_none() && !upstream_ids.contains(origin))
+      .map(|(_, copy)| Pending::Removed(copy)),
+  );
+  pending.sort_by_key(Pending::origin);
+
+  Ok((owner, pending))
+}
+
+/// Brings a copy up to date with its upstream card, keeping its scores
+fn merge(
+  conn: &DBConnection,
+  owner: i32,
+  local: &LocalCard,
+  source: SourceCard,
+  time: i64,
+) -> QueryResult<()> {
+  // The back is pointed at the source's media, which gc mustn't take
+  media::lock_shared(conn)?;
+  diesel::update(cards::table.filter(cards::id.eq(local.id)))
+    .set((
+      cards::front.eq(source.front),
+      cards::link.eq(source.link),
+      cards::front_language.eq(source.front_language),
+      cards::front_audio.eq(source.front_audio),
+      cards::front_audio_media.eq(source.front_audio_media),
+      cards::updated_at.eq(time),
+      cards::version.eq(cards::version + 1),
+      cards::origin_version.eq(source.version),
+      cards::merged_version.eq(local.version + 1),
+    ))
+    .execute(conn)?;
+  diesel::update(backs::table.filter(backs::id.eq(local.back)))
+    .set((
+      backs::text.eq(source.text),
+      backs::language.eq(source.language),
+      backs::audio.eq(source.audio),
+      backs::image.eq(source.image),
+      backs::audio_media.eq(source.audio_media),
+      backs::image_media.eq(source.image_media),
+      backs::image_thumbnail.eq(source.image_thumbnail),
+      backs::image_card.eq(source.image_card),
+      backs::updated_at.eq(time),
+    ))
+    .execute(conn)?;
+  changes::record(
+    conn,
+    owner,
+    Entity::Card,
+    &[local.id],
+    ChangeKind::Upsert,
+    time,
+  )?;
+  changes::record(
+    conn,
+    owner,
+    Entity::Back,
+    &[local.back],
+    ChangeKind::Upsert,
+    time,
+  )?;
+  Ok(())
+}
+
+/// Lists what changed in the deck a clone was made from since it was cloned,
+/// or since updates were last applied.
+pub fn deck_updates(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+) -> FieldResult<Vec<DeckUpdate>, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let (_, pending) = pending(ctx.get_connection(), user_id, deck)?;
+  Ok(pending.iter().map(DeckUpdate::from).collect())
+}
+
+/// Merges upstream updates into a clone, either all of them or those for the
+/// given upstream cards. Cards the learner edited are skipped, and scores are
+/// kept since changed cards are updated in place. Returns how many updates
+/// were applied.
+pub fn apply_deck_updates(
+  ctx: &GQLContext<DBConnection>,
+  deck: i32,
+  origins: Option<Vec<i32>>,
+) -> FieldResult<i32, WundergraphScalarValue> {
+  let user_id = ctx.user_id.ok_or(TRCError::Unauthorized)?;
+  let conn = ctx.get_connection();
+  conn.transaction(|| {
+    let (owner, pending) = pending(conn, user_id, deck)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let audit = Audit::new(Some(user_id), owner, time);
+
+    let mut applied = 0;
+    for update in pending {
+      if update.locally_edited()
+        || origins
+          .as_ref()
+          .is_some_and(|origins| !origins.contains(&update.origin()))
+      {
+        continue;
+      }
+
+      match update {
+        Pending::Added(source) => {
+          let card = copy_card(conn, &source, deck, time)?;
+          changes::record_cards(conn, owner, &[card], ChangeKind::Upsert, time)?;
+          audit.inserted::<CardRow>(conn, &[card])?;
+        }
+        Pending::Changed(local, source) => {
+          let before = CardRow::load(conn, &[local.id])?;
+          merge(conn, owner, &local, source, time)?;
+          audit.updated(conn, before)?;
+        }
+        Pending::Restored(local, source) => {
+          let before = CardRow::load(conn, &[local.id])?;
+          trash::restore_card(conn, owner, local.id, time)?;
+          merge(conn, owner, &local, source, time)?;
+          audit.updated(conn, before)?;
+        }
+        Pending::Removed(local) => {
+          let before = CardRow::load(conn, &[local.id])?;
+          trash::trash_card(conn, owner, local.id, time)?;
+          diesel::update(cards::table.filter(cards::id.eq(local.id)))
+            .set(cards::removed_upstream.eq(true))
+            .execute(conn)?;
+          audit.deleted(conn, before)?;
+        }
+      }
+      applied += 1;
+    }
+
+    Ok(applied)
+  })
+}
@@ -7,18 +7,25 @@ extern crate juniper;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod cli;
+pub mod config;
 pub mod db;
 pub mod graphql;
+pub mod media;
 pub mod service;
 
 use juniper::{ExecutionResult, FieldError};
-use std::{error, fmt};
+use std::{error, fmt, time::SystemTimeError};
 use wundergraph::scalar::WundergraphScalarValue;
 
 #[derive(Debug)]
 pub enum TRCError {
+  Database(diesel::result::Error),
   Request(reqwest::Error),
   FileSystem(std::io::Error),
+  Configuration(String),
+  Migration(String),
+  Media(String),
   Unauthorized,
   Unknown(String),
 }
@@ -26,8 +33,12 @@ pub enum TRCError {
 impl fmt::Display for TRCError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match *self {
+      TRCError::Database(ref err) => write!(f, "Database error: {}", err),
       TRCError::Request(ref err) => write!(f, "Request error: {}", err),
       TRCError::FileSystem(ref err) => write!(f, "File system error: {}", err),
+      TRCError::Configuration(ref err) => write!(f, "Configuration error: {}", err),
+      TRCError::Migration(ref err) => write!(f, "Migration error: {}", err),
+      TRCError::Media(ref err) => write!(f, "Media error: {}", err),
       TRCError::Unauthorized => write!(f, "Unauthorized"),
       TRCError::Unknown(ref err) => write!(f, "Unknown error: {}", err),
     }
@@ -37,6 +48,7 @@ impl fmt::Display for TRCError {
 impl error::Error for TRCError {
   fn cause(&self) -> Option<&(dyn error::Error)> {
     match *self {
+      TRCError::Database(ref err) => Some(err),
       TRCError::Request(ref err) => Some(err),
       TRCError::FileSystem(ref err) => Some(err),
       _ => None,
@@ -44,12 +56,36 @@ impl error::Error for TRCError {
   }
 }
 
+impl From<diesel::result::Error> for TRCError {
+  fn from(err: diesel::result::Error) -> TRCError {
+    TRCError::Database(err)
+  }
+}
+
+impl From<SystemTimeError> for TRCError {
+  fn from(err: SystemTimeError) -> TRCError {
+    TRCError::Unknown(err.to_string())
+  }
+}
+
 impl From<std::io::Error> for TRCError {
   fn from(err: std::io::Error) -> TRCError {
     TRCError::FileSystem(err)
   }
 }
 
+impl From<diesel_migrations::RunMigrationsError> for TRCError {
+  fn from(err: diesel_migrations::RunMigrationsError) -> TRCError {
+    TRCError::Migration(err.to_string())
+  }
+}
+
+impl From<image::ImageError> for TRCError {
+  fn from(err: image::ImageError) -> TRCError {
+    TRCError::Media(err.to_string())
+  }
+}
+
 impl From<reqwest::Error> for TRCError {
   fn from(err: reqwest::Error) -> TRCError {
     TRCError::Request(err)
@@ -59,7 +95,7 @@ impl From<reqwest::Error> for TRCError {
 impl From<TRCError> for ExecutionResult<WundergraphScalarValue> {
   fn from(err: TRCError) -> ExecutionResult<WundergraphScalarValue> {
     Err(FieldError::new(
-      format!("{}", err.to_string()),
+      err.to_string(),
       graphql_value!({
           "type": "INTERNAL"
       }),
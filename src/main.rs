@@ -1,81 +1,374 @@
 extern crate total_recall;
 
-use std::{
-    io::Result,
-    path::PathBuf,
-    sync::Arc,
-};
+use std::{fs::File, io, path::PathBuf, process, sync::Arc, thread, time::Duration};
 
 use actix_files::NamedFile;
 use actix_web::{
     middleware::Logger,
-    web::{get, post},
-    App, HttpRequest, HttpServer,
+    web::{get, post, resource, Data, PayloadConfig},
+    App, Error, HttpRequest, HttpResponse, HttpServer,
 };
-use diesel::r2d2::{ConnectionManager, Pool};
 use structopt::StructOpt;
 
 use total_recall::{
-    db::DBConnection,
-    graphql::{mutations::Mutation, query::Query, GQLContext, Schema},
+    cli::{self, deck, stats, user},
+    config::{safe_join, Config},
+    db::{self, migrations, trash, DBPool},
+    graphql::{authorization::UserRole, create_schema},
+    media,
     service::{
-        endpoints::{graphiql, graphql, login},
+        cors::Cors,
+        endpoints::{
+            graphiql, graphql, login, media_response, pull_changes, push_changes, serve_media,
+            upload_media,
+        },
         AppState,
     },
+    TRCError,
 };
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "total_recall")]
 struct Opt {
+    /// Configuration file, defaults to total_recall.toml if present
+    #[structopt(short = "c", long = "config", parse(from_os_str))]
+    config: Option<PathBuf>,
+    /// Overrides database.url
     #[structopt(short = "u", long = "db-url")]
-    database_url: String,
-    #[structopt(short = "s", long = "socket", default_value = "127.0.0.1:8000")]
-    socket: String,
+    database_url: Option<String>,
+    // Used when no subcommand is given
+    #[structopt(flatten)]
+    serve: ServeOpt,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ServeOpt {
+    /// Overrides server.socket
+    #[structopt(short = "s", long = "socket")]
+    socket: Option<String>,
+    /// Overrides server.trash_retention_days
+    #[structopt(long = "trash-retention-days")]
+    trash_retention_days: Option<u64>,
+    /// Don't apply pending migrations on startup
+    #[structopt(long = "no-migrate")]
+    no_migrate: bool,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Runs the web server, which is also what happens without a subcommand
+    Serve(ServeOpt),
+    #[structopt(flatten)]
+    Admin(AdminCommand),
+}
+
+#[derive(Debug, StructOpt)]
+enum AdminCommand {
+    /// Applies pending database migrations
+    Migrate {
+        /// Only list the migrations that would be applied
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Manages user accounts
+    User(UserCommand),
+    /// Moves decks between servers
+    Deck(DeckCommand),
+    /// Maintains generated audio and images
+    Media(MediaCommand),
+    /// Prints row counts and media usage
+    Stats,
+}
+
+#[derive(Debug, StructOpt)]
+enum UserCommand {
+    /// Creates an account, asking for its password or reading it from stdin
+    Create {
+        username: String,
+        #[structopt(long = "admin")]
+        admin: bool,
+    },
+    /// Sets a temporary password the user has to change, asking for it or
+    /// reading it from stdin
+    ResetPassword { username: String },
+    /// Deletes an account along with everything it owns
+    Delete { username: String },
+}
+
+#[derive(Debug, StructOpt)]
+enum DeckCommand {
+    /// Writes a deck as JSON to a file, or to stdout
+    Export {
+        deck: i32,
+        #[structopt(short = "o", long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Reads a deck exported with `deck export` into a user's account
+    Import {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        #[structopt(long = "owner")]
+        owner: String,
+        /// Where to find the media files of the deck, defaults to media.root
+        #[structopt(long = "media", parse(from_os_str))]
+        media: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum MediaCommand {
+    /// Removes media no card uses any more
+    Gc {
+        /// Only list what would be removed
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Moves media stored under file names into content addressed storage
+    Rehash,
+}
+
+impl Opt {
+    /// Server options, unless a command other than `serve` was given
+    fn serve_options(&self) -> Option<&ServeOpt> {
+        match &self.command {
+            None => Some(&self.serve),
+            Some(Command::Serve(serve)) => Some(serve),
+            Some(Command::Admin(_)) => None,
+        }
+    }
+}
+
+/// Serves the web client. Media is served under `/media`; URLs stored before
+/// that were relative paths starting with `audio/` or `images/`, which are
+/// still answered from the media root here so old cards keep working.
+async fn index(req: HttpRequest, st: Data<AppState>) -> Result<HttpResponse, Error> {
+    let config = &st.get_ref().config;
+    let path = req.match_info().query("path");
+    let file = match path.split('/').next().unwrap_or_default() {
+        "" | "login" | "register" | "manual" | "study" | "cards" | "sets" | "user" => {
+            config.web.root.join("index.html")
+        }
+        first if media::MEDIA_DIRS.contains(&first) => {
+            return Ok(media_response(&req, st.get_ref(), path, None)?);
+        }
+        _ => match safe_join(&config.web.root, path) {
+            Some(file) => file,
+            None => return Ok(HttpResponse::NotFound().finish()),
+        },
+    };
+    NamedFile::open(file)?.into_response(&req)
 }
 
-async fn index(req: HttpRequest) -> Result<NamedFile> {
-    let mut path = req.match_info().query("path").split("/").peekable();
-    match path.peek() {
-        Some(&"") | Some(&"login") | Some(&"register") | Some(&"manual") | Some(&"study") | Some(&"cards")
-        | Some(&"sets") | Some(&"user") | None => Ok(NamedFile::open::<PathBuf>(
-            "./static/index.html".parse().unwrap(),
-        )?),
-        Some(_) => Ok(NamedFile::open::<PathBuf>(
-            format!("./static/{}", path.collect::<Vec<&str>>().join("/"))
-                .parse()
-                .unwrap(),
-        )?),
+fn run_command(config: &Config, pool: &DBPool, command: AdminCommand) -> Result<(), TRCError> {
+    let conn = pool
+        .get()
+        .map_err(|err| TRCError::Unknown(err.to_string()))?;
+    match command {
+        AdminCommand::Migrate { dry_run: true } => {
+            let pending = migrations::pending(&conn)?;
+            for version in &pending {
+                println!("{}", version);
+            }
+            println!("{} pending migrations", pending.len());
+        }
+        AdminCommand::Migrate { dry_run: false } => migrations::run(&conn, &mut io::stdout())?,
+        AdminCommand::User(UserCommand::Create { username, admin }) => {
+            let role = if admin { UserRole::ADMIN } else { UserRole::USER };
+            let password = user::read_password("Password: ")?;
+            let id = user::create(&conn, &username, &password, role)?;
+            println!("Created user {} with id {}", username, id);
+        }
+        AdminCommand::User(UserCommand::ResetPassword { username }) => {
+            let password = user::read_password("Temporary password: ")?;
+            user::reset_password(&conn, &username, &password)?;
+            println!("Reset the password of {}", username);
+        }
+        AdminCommand::User(UserCommand::Delete { username }) => {
+            user::delete(&conn, &username)?;
+            println!("Deleted user {}", username);
+        }
+        AdminCommand::Deck(DeckCommand::Export { deck, output }) => {
+            let exported = deck::export(&conn, &config.media, deck)?;
+            let json = match output {
+                Some(path) => serde_json::to_writer_pretty(File::create(path)?, &exported),
+                None => serde_json::to_writer_pretty(io::stdout(), &exported).map(|_| println!()),
+            };
+            json.map_err(|err| TRCError::Unknown(err.to_string()))?;
+        }
+        AdminCommand::Deck(DeckCommand::Import { file, owner, media }) => {
+            let exported = serde_json::from_reader(File::open(file)?)
+                .map_err(|err| TRCError::Unknown(err.to_string()))?;
+            let media_dir = media.as_ref().unwrap_or(&config.media.root);
+            let id = deck::import(&conn, &config.media, media_dir, &owner, exported)?;
+            println!("Imported deck {}", id);
+        }
+        AdminCommand::Media(MediaCommand::Gc { dry_run }) => {
+            let report = media::gc(&conn, &config.media, dry_run)?;
+            for path in &report.files {
+                println!("{}", path.display());
+            }
+            println!(
+                "{} {} unused media rows and {} files, {} bytes",
+                if dry_run { "Found" } else { "Removed" },
+                report.rows,
+                report.files.len(),
+                report.bytes
+            );
+        }
+        AdminCommand::Media(MediaCommand::Rehash) => {
+            let updated = cli::media::rehash(&conn, &config.media)?;
+            println!("Moved the media of {} backs", updated);
+        }
+        AdminCommand::Stats => println!("{}", stats::collect(&conn, &config.media.root)?),
     }
+    Ok(())
+}
+
+/// Reads the configuration and applies command line overrides on top
+fn load_config(opt: &Opt) -> Result<Config, TRCError> {
+    let mut config = Config::load(opt.config.as_deref())?;
+    if let Some(url) = &opt.database_url {
+        config.database.url = url.clone();
+    }
+    if let Some(serve) = opt.serve_options() {
+        if let Some(socket) = &serve.socket {
+            config.server.socket = socket.clone();
+        }
+        if let Some(days) = serve.trash_retention_days {
+            config.server.trash_retention_days = days;
+        }
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+/// Brings the schema up to date, unless told not to. Never starts against a
+/// schema that is newer than this binary.
+fn migrate_on_start(pool: &DBPool, no_migrate: bool) -> Result<(), TRCError> {
+    let conn = pool
+        .get()
+        .map_err(|err| TRCError::Unknown(err.to_string()))?;
+    if !no_migrate {
+        return migrations::run(&conn, &mut io::stdout());
+    }
+
+    migrations::check(&conn)?;
+    let pending = migrations::pending(&conn)?;
+    if !pending.is_empty() {
+        eprintln!("Skipping {} pending migrations", pending.len());
+    }
+    Ok(())
 }
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     let opt = Opt::from_args();
-    env_logger::init();
-    let manager = ConnectionManager::<DBConnection>::new(opt.database_url);
-    let pool = Pool::builder()
-        .build(manager)
-        .expect("Failed to initialize connection pool");
+    let config = match load_config(&opt) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+    env_logger::from_env(env_logger::Env::default().default_filter_or(&config.log.level)).init();
 
-    let query = Query::<GQLContext<DBConnection>>::default();
-    let mutation = Mutation::<GQLContext<DBConnection>>::default();
-    let schema = Schema::new(query, mutation);
+    let pool = match db::connect(&config.database.url, Some(config.database.pool_size)) {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("Failed to initialize connection pool: {}", err);
+            process::exit(1);
+        }
+    };
 
-    let schema = Arc::new(schema);
-    let pool = Arc::new(pool);
-    let data = AppState { schema, pool };
+    let no_migrate = match opt.command {
+        None => opt.serve.no_migrate,
+        Some(Command::Serve(serve)) => serve.no_migrate,
+        Some(Command::Admin(command)) => {
+            if let Err(err) = run_command(&config, &pool, command) {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+            return Ok(());
+        }
+    };
 
-    let url = opt.socket;
+    if let Err(err) = migrate_on_start(&pool, no_migrate) {
+        eprintln!("Refusing to start: {}", err);
+        process::exit(1);
+    }
+    if let Err(err) = media::create_dirs(&config.media.root) {
+        eprintln!("Failed to create the media directories: {}", err);
+        process::exit(1);
+    }
+
+    let retention = Duration::from_secs(config.server.trash_retention_days * 24 * 60 * 60);
+    let purge_pool = pool.clone();
+    thread::spawn(move || loop {
+        match purge_pool.get() {
+            Ok(conn) => match trash::purge(&conn, retention) {
+                Ok(0) => {}
+                Ok(removed) => println!("Purged {} items from the trash", removed),
+                Err(err) => eprintln!("Failed to purge the trash: {}", err),
+            },
+            Err(err) => eprintln!("Failed to purge the trash: {}", err),
+        }
+        thread::sleep(Duration::from_secs(60 * 60));
+    });
+
+    if config.media.gc_interval_hours > 0 {
+        let interval = Duration::from_secs(config.media.gc_interval_hours * 60 * 60);
+        let gc_pool = pool.clone();
+        let media_config = config.media.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match gc_pool.get() {
+                Ok(conn) => match media::gc(&conn, &media_config, false) {
+                    Ok(report) if report.files.is_empty() && report.rows == 0 => {}
+                    Ok(report) => println!(
+                        "Removed {} unused media files, {} bytes",
+                        report.files.len(),
+                        report.bytes
+                    ),
+                    Err(err) => eprintln!("Failed to collect unused media: {}", err),
+                },
+                Err(err) => eprintln!("Failed to collect unused media: {}", err),
+            }
+        });
+    }
+
+    let schema = Arc::new(create_schema());
+    let pool = Arc::new(pool);
+    let url = config.server.socket.clone();
+    let cors = config.cors.clone();
+    // Room for the rest of the form around the file
+    let upload_limit = config.media.max_upload_bytes as usize + 64 * 1024;
+    let config = Arc::new(config);
+    let data = AppState {
+        schema,
+        pool,
+        config,
+    };
 
     println!("Total recall running at: http://{}", url);
 
     HttpServer::new(move || {
         App::new()
             .data(data.clone())
+            .wrap(Cors::new(cors.clone()))
             .wrap(Logger::default())
             .route("/login", post().to(login))
             .route("/graphql", post().to(graphql))
             .route("/graphiql", get().to(graphiql))
+            .route("/sync", get().to(pull_changes))
+            .route("/sync", post().to(push_changes))
+            .service(
+                resource("/upload")
+                    .app_data(PayloadConfig::new(upload_limit))
+                    .route(post().to(upload_media)),
+            )
+            .route("/media/{path:.*}", get().to(serve_media))
             .route("{path:.*}", get().to(index))
     })
     .bind(&url)
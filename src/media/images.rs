@@ -0,0 +1,84 @@
+use image::{imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use std::io::Cursor;
+
+use crate::TRCError;
+
+/// Longest side of the image kept in place of the original
+const MAX_SIZE: u32 = 1600;
+
+/// Images with a longer side than this aren't decoded at all
+const MAX_DECODED_SIZE: u32 = 10_000;
+
+/// Smaller copies made of every image, stored as their own media
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ImageVariant {
+  Thumbnail = 0,
+  Card = 1,
+}
+
+impl ImageVariant {
+  pub const ALL: [ImageVariant; 2] = [ImageVariant::Thumbnail, ImageVariant::Card];
+
+  /// Longest side of the variant
+  fn size(self) -> u32 {
+    match self {
+      ImageVariant::Thumbnail => 160,
+      ImageVariant::Card => 480,
+    }
+  }
+
+  pub fn from_i16(value: i16) -> Option<Self> {
+    match value {
+      0 => Some(ImageVariant::Thumbnail),
+      1 => Some(ImageVariant::Card),
+      _ => None,
+    }
+  }
+}
+
+/// An image re-encoded as WebP, along with its variants
+#[derive(Debug)]
+pub struct Processed {
+  pub image: Vec<u8>,
+  pub variants: Vec<(ImageVariant, Vec<u8>)>,
+}
+
+fn shrink(image: &DynamicImage, size: u32) -> DynamicImage {
+  if image.width() <= size && image.height() <= size {
+    image.clone()
+  } else {
+    image.resize(size, size, FilterType::CatmullRom)
+  }
+}
+
+/// Encodes as lossless WebP, the only kind the image crate writes
+fn encode(image: &DynamicImage) -> Result<Vec<u8>, TRCError> {
+  let mut encoded = Cursor::new(vec![]);
+  DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut encoded, ImageFormat::WebP)?;
+  Ok(encoded.into_inner())
+}
+
+/// Decodes an image in any supported format, whatever its name or claimed
+/// type, turns it upright and re-encodes it along with smaller variants.
+/// Only the pixels are kept, so metadata like the location a photo was
+/// taken at is dropped.
+pub fn process(bytes: &[u8]) -> Result<Processed, TRCError> {
+  let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+  let mut limits = Limits::default();
+  limits.max_image_width = Some(MAX_DECODED_SIZE);
+  limits.max_image_height = Some(MAX_DECODED_SIZE);
+  reader.limits(limits);
+  let mut decoder = reader.into_decoder()?;
+  let orientation = decoder.orientation()?;
+  let mut image = DynamicImage::from_decoder(decoder)?;
+  image.apply_orientation(orientation);
+
+  let mut variants = vec![];
+  for variant in ImageVariant::ALL.iter() {
+    variants.push((*variant, encode(&shrink(&image, variant.size()))?));
+  }
+  Ok(Processed {
+    image: encode(&shrink(&image, MAX_SIZE))?,
+    variants,
+  })
+}
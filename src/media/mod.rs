@@ -0,0 +1,509 @@
+use diesel::{
+  dsl::{exists, not},
+  prelude::*,
+  sql_types::BigInt,
+};
+use sha2::{Digest, Sha256};
+use std::{
+  collections::HashSet,
+  fs,
+  path::{Path, PathBuf},
+  thread,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use self::images::ImageVariant;
+use crate::{
+  config::MediaConfig,
+  db::{
+    schema::{backs, cards, media, media_variants},
+    DBConnection,
+  },
+  TRCError,
+};
+
+pub mod images;
+pub mod tts;
+
+/// Directories under the media root that hold media, one level of
+/// subdirectories deep
+pub const MEDIA_DIRS: [&str; 2] = ["audio", "images"];
+
+/// Advisory lock held while media is stored or collected
+const MEDIA_LOCK: i64 = 0x006d_6564_6961;
+
+/// Files and media rows stored this recently are never collected, so uploads
+/// can be attached to a back after they were stored.
+const GC_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// A stored file, addressed by the SHA-256 of its content so identical files
+/// are only kept once no matter how many backs use them.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct MediaRow {
+  pub id: i32,
+  pub hash: String,
+  pub mime: String,
+  pub size: i64,
+  /// Where the file was fetched from, if it wasn't uploaded
+  pub source: Option<String>,
+  pub licence: Option<String>,
+  pub created_at: i64,
+}
+
+impl MediaRow {
+  pub const COLUMNS: (
+    media::id,
+    media::hash,
+    media::mime,
+    media::size,
+    media::source,
+    media::licence,
+    media::created_at,
+  ) = (
+    media::id,
+    media::hash,
+    media::mime,
+    media::size,
+    media::source,
+    media::licence,
+    media::created_at,
+  );
+
+  pub fn find(conn: &DBConnection, id: i32) -> QueryResult<MediaRow> {
+    media::table
+      .select(MediaRow::COLUMNS)
+      .filter(media::id.eq(id))
+      .get_result(conn)
+  }
+
+  /// Where the file lives, relative to the media root
+  pub fn path(&self) -> String {
+    file_path(&self.hash, &self.mime)
+  }
+}
+
+/// Everything needed to store a new file
+#[derive(Debug)]
+pub struct NewMedia {
+  pub bytes: Vec<u8>,
+  pub mime: String,
+  pub source: Option<String>,
+  pub licence: Option<String>,
+}
+
+pub fn hash(bytes: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(bytes))
+}
+
+/// The file extension for a MIME type, `bin` for unknown ones
+pub fn extension(mime: &str) -> &'static str {
+  match mime {
+    "audio/mpeg" => "mp3",
+    "audio/ogg" => "ogg",
+    "audio/wav" | "audio/x-wav" => "wav",
+    "image/jpeg" => "jpg",
+    "image/png" => "png",
+    "image/gif" => "gif",
+    "image/webp" => "webp",
+    _ => "bin",
+  }
+}
+
+/// The MIME type for a file extension, for files stored before their type
+/// was recorded
+pub fn mime_for_extension(extension: &str) -> Option<&'static str> {
+  Some(match extension {
+    "mp3" => "audio/mpeg",
+    "ogg" => "audio/ogg",
+    "wav" => "audio/wav",
+    "jpg" | "jpeg" => "image/jpeg",
+    "png" => "image/png",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    _ => return None,
+  })
+}
+
+/// Works out the type of a file from its first bytes, for the types media
+/// can be stored as
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+  let riff = |format: &[u8]| bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == format;
+  Some(if bytes.starts_with(b"\xFF\xD8\xFF") {
+    "image/jpeg"
+  } else if bytes.starts_with(b"\x89PNG\r\n\x1A\n") {
+    "image/png"
+  } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+    "image/gif"
+  } else if riff(b"WEBP") {
+    "image/webp"
+  } else if riff(b"WAVE") {
+    "audio/wav"
+  } else if bytes.starts_with(b"OggS") {
+    "audio/ogg"
+  } else if bytes.starts_with(b"ID3")
+    || (bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] & 0xE0 == 0xE0)
+  {
+    "audio/mpeg"
+  } else {
+    return None;
+  })
+}
+
+/// Audio goes in `audio/`, everything else in `images/`, fanned out by the
+/// first two characters of the hash to keep directories small.
+pub fn file_path(hash: &str, mime: &str) -> String {
+  let dir = if mime.starts_with("audio/") {
+    "audio"
+  } else {
+    "images"
+  };
+  format!("{}/{}/{}.{}", dir, &hash[..2], hash, extension(mime))
+}
+
+/// The hash of the file at `path`, relative to the media root, if it is
+/// stored by content hash. Such files never change.
+pub fn hash_of(path: &str) -> Option<&str> {
+  let name = path.rsplit('/').next()?;
+  let (hash, ext) = name.split_once('.')?;
+  let hex = hash.len() == 64
+    && hash
+      .bytes()
+      .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'));
+  if hex && file_path(hash, mime_for_extension(ext)?) == path {
+    Some(hash)
+  } else {
+    None
+  }
+}
+
+/// Writes a file under the media root unless it is there already. The file
+/// is written next to its destination first so readers never see it half
+/// written.
+fn write_file(config: &MediaConfig, path: &str, bytes: &[u8]) -> Result<(), TRCError> {
+  let path = config.root.join(path);
+  if path.exists() {
+    return Ok(());
+  }
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  let partial = path.with_extension(format!("{:?}.part", thread::current().id()));
+  fs::write(&partial, bytes)?;
+  fs::rename(&partial, &path)?;
+  Ok(())
+}
+
+/// Keeps media referenced by the current transaction from being collected.
+/// Taken by anything that stores media or points backs at it, and held until
+/// the transaction ends.
+pub fn lock_shared(conn: &DBConnection) -> QueryResult<()> {
+  diesel::sql_query("SELECT pg_advisory_xact_lock_shared($1)")
+    .bind::<BigInt, _>(MEDIA_LOCK)
+    .execute(conn)
+    .map(|_| ())
+}
+
+/// Stores a file and returns its row. Call it within the transaction that
+/// goes on to reference the file. Storing content that is already known
+/// returns the existing row, even when another request stores the same
+/// content at the same time.
+pub fn store(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  new: NewMedia,
+  time: i64,
+) -> Result<MediaRow, TRCError> {
+  lock_shared(conn)?;
+  let hash = hash(&new.bytes);
+  let existing = media::table
+    .select(MediaRow::COLUMNS)
+    .filter(media::hash.eq(&hash))
+    .get_result::<MediaRow>(conn)
+    .optional()?;
+  if let Some(existing) = existing {
+    // Put the file back in case it went missing
+    write_file(config, &existing.path(), &new.bytes)?;
+    diesel::update(media::table.filter(media::id.eq(existing.id)))
+      .set(media::stored_at.eq(time))
+      .execute(conn)?;
+    return Ok(existing);
+  }
+
+  write_file(config, &file_path(&hash, &new.mime), &new.bytes)?;
+  diesel::insert_into(media::table)
+    .values((
+      media::hash.eq(&hash),
+      media::mime.eq(&new.mime),
+      media::size.eq(new.bytes.len() as i64),
+      media::source.eq(&new.source),
+      media::licence.eq(&new.licence),
+      media::created_at.eq(time),
+      media::stored_at.eq(time),
+    ))
+    .on_conflict(media::hash)
+    .do_nothing()
+    .execute(conn)?;
+  Ok(
+    media::table
+      .select(MediaRow::COLUMNS)
+      .filter(media::hash.eq(&hash))
+      .get_result(conn)?,
+  )
+}
+
+/// An image stored along with its variants
+#[derive(Debug)]
+pub struct StoredImage {
+  pub image: MediaRow,
+  pub variants: Vec<(ImageVariant, MediaRow)>,
+}
+
+/// What goes in the image columns of a back
+#[derive(Debug, Default)]
+pub struct ImageUrls {
+  pub image: Option<String>,
+  pub image_media: Option<i32>,
+  pub thumbnail: Option<String>,
+  pub card: Option<String>,
+}
+
+impl StoredImage {
+  pub fn urls(&self, config: &MediaConfig) -> ImageUrls {
+    let variant = |wanted: ImageVariant| {
+      self
+        .variants
+        .iter()
+        .find(|(variant, _)| *variant == wanted)
+        .map(|(_, row)| config.url(&row.path()))
+    };
+    ImageUrls {
+      image: Some(config.url(&self.image.path())),
+      image_media: Some(self.image.id),
+      thumbnail: variant(ImageVariant::Thumbnail),
+      card: variant(ImageVariant::Card),
+    }
+  }
+}
+
+/// Stores an image as WebP, whatever format it came in, along with its
+/// variants. Fails with `TRCError::Media` for anything that isn't an image.
+pub fn store_image(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  new: NewMedia,
+  time: i64,
+) -> Result<StoredImage, TRCError> {
+  let processed = images::process(&new.bytes)?;
+  let as_webp = |bytes: Vec<u8>| NewMedia {
+    bytes,
+    mime: "image/webp".to_owned(),
+    source: new.source.clone(),
+    licence: new.licence.clone(),
+  };
+  let image = store(conn, config, as_webp(processed.image), time)?;
+
+  let mut variants = vec![];
+  for (variant, bytes) in processed.variants {
+    let stored = store(conn, config, as_webp(bytes), time)?;
+    diesel::insert_into(media_variants::table)
+      .values((
+        media_variants::media_id.eq(image.id),
+        media_variants::variant.eq(variant as i16),
+        media_variants::variant_id.eq(stored.id),
+      ))
+      .on_conflict((media_variants::media_id, media_variants::variant))
+      .do_update()
+      .set(media_variants::variant_id.eq(stored.id))
+      .execute(conn)?;
+    variants.push((variant, stored));
+  }
+  Ok(StoredImage { image, variants })
+}
+
+/// An image stored before along with the variants made of it
+pub fn stored_image(conn: &DBConnection, image: MediaRow) -> QueryResult<StoredImage> {
+  let variants = media_variants::table
+    .inner_join(media::table.on(media::id.eq(media_variants::variant_id)))
+    .select((media_variants::variant, MediaRow::COLUMNS))
+    .filter(media_variants::media_id.eq(image.id))
+    .load::<(i16, MediaRow)>(conn)?
+    .into_iter()
+    .filter_map(|(variant, row)| Some((ImageVariant::from_i16(variant)?, row)))
+    .collect();
+  Ok(StoredImage { image, variants })
+}
+
+/// Creates the media directories under `root` if they don't exist yet
+pub fn create_dirs(root: &Path) -> Result<(), TRCError> {
+  for dir in MEDIA_DIRS.iter() {
+    fs::create_dir_all(root.join(dir))?;
+  }
+  Ok(())
+}
+
+/// Every media file under `root` along with its size
+pub fn files(root: &Path) -> Result<Vec<(PathBuf, u64)>, TRCError> {
+  let mut found = vec![];
+  for dir in MEDIA_DIRS.iter() {
+    let dir = root.join(dir);
+    if !dir.is_dir() {
+      continue;
+    }
+    for subdir in fs::read_dir(dir)? {
+      let subdir = subdir?.path();
+      if !subdir.is_dir() {
+        continue;
+      }
+      for file in fs::read_dir(subdir)? {
+        let file = file?;
+        let metadata = file.metadata()?;
+        if metadata.is_file() {
+          found.push((file.path(), metadata.len()));
+        }
+      }
+    }
+  }
+  Ok(found)
+}
+
+/// What a garbage collection removed, or would remove on a dry run
+#[derive(Debug, Default)]
+pub struct GcReport {
+  /// Media rows no card uses any more
+  pub rows: usize,
+  pub files: Vec<PathBuf>,
+  pub bytes: u64,
+}
+
+/// Removes media that no card uses: rows of the media table along with their
+/// files, and files that are neither in the media table nor referenced by a
+/// card or back. Backs left behind by deleted accounts don't count as references.
+///
+/// Runs under an exclusive advisory lock, so media stored by a transaction
+/// that hasn't committed yet is never taken for garbage. Files are only
+/// removed once the rows are gone for good, and the lock is held until then
+/// so nothing writes them again in between.
+pub fn gc(conn: &DBConnection, config: &MediaConfig, dry_run: bool) -> Result<GcReport, TRCError> {
+  diesel::sql_query("SELECT pg_advisory_lock($1)")
+    .bind::<BigInt, _>(MEDIA_LOCK)
+    .execute(conn)?;
+  let result = conn
+    .transaction(|| collect_garbage(conn, config, dry_run))
+    .and_then(|report| {
+      if !dry_run {
+        for path in &report.files {
+          fs::remove_file(path)?;
+        }
+      }
+      Ok(report)
+    });
+  diesel::sql_query("SELECT pg_advisory_unlock($1)")
+    .bind::<BigInt, _>(MEDIA_LOCK)
+    .execute(conn)?;
+  result
+}
+
+/// Deletes unused media rows and lists the files to remove
+fn collect_garbage(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  dry_run: bool,
+) -> Result<GcReport, TRCError> {
+  let used = backs::table
+    .filter(exists(cards::table.filter(cards::back.eq(backs::id))))
+    .filter(
+      backs::audio_media
+        .eq(media::id.nullable())
+        .or(backs::image_media.eq(media::id.nullable())),
+    );
+  let front_of_card = cards::table.filter(cards::front_audio_media.eq(media::id.nullable()));
+  let variant_of_used = media_variants::table
+    .filter(media_variants::variant_id.eq(media::id))
+    .filter(exists(
+      backs::table
+        .filter(exists(cards::table.filter(cards::back.eq(backs::id))))
+        .filter(backs::image_media.eq(media_variants::media_id.nullable())),
+    ));
+  let cutoff = SystemTime::now() - GC_GRACE;
+  let stored_before = cutoff.duration_since(UNIX_EPOCH)?.as_millis() as i64;
+  let unused = media::table
+    .select(MediaRow::COLUMNS)
+    .filter(not(exists(used)))
+    .filter(not(exists(front_of_card)))
+    .filter(not(exists(variant_of_used)))
+    .filter(media::stored_at.lt(stored_before))
+    .load::<MediaRow>(conn)?;
+  if !dry_run {
+    diesel::delete(
+      media::table.filter(media::id.eq_any(unused.iter().map(|row| row.id).collect::<Vec<_>>())),
+    )
+    .execute(conn)?;
+  }
+
+  let unused_paths = unused
+    .iter()
+    .map(|row| config.root.join(row.path()))
+    .collect::<HashSet<_>>();
+  let mut referenced = media::table
+    .select((media::hash, media::mime))
+    .load::<(String, String)>(conn)?
+    .into_iter()
+    .map(|(hash, mime)| config.root.join(file_path(&hash, &mime)))
+    .filter(|path| !unused_paths.contains(path))
+    .collect::<HashSet<_>>();
+  for (audio, image, thumbnail, card) in backs::table
+    .select((
+      backs::audio,
+      backs::image,
+      backs::image_thumbnail,
+      backs::image_card,
+    ))
+    .filter(exists(cards::table.filter(cards::back.eq(backs::id))))
+    .load::<(
+      Option<String>,
+      Option<String>,
+      Option<String>,
+      Option<String>,
+    )>(conn)?
+  {
+    referenced.extend(
+      audio
+        .into_iter()
+        .chain(image)
+        .chain(thumbnail)
+        .chain(card)
+        .filter_map(|url| config.path(&url)),
+    );
+  }
+  for front_audio in cards::table
+    .select(cards::front_audio)
+    .filter(cards::front_audio.is_not_null())
+    .load::<Option<String>>(conn)?
+  {
+    referenced.extend(front_audio.and_then(|url| config.path(&url)));
+  }
+
+  let mut report = GcReport {
+    rows: unused.len(),
+    ..GcReport::default()
+  };
+  for (path, size) in files(&config.root)? {
+    if referenced.contains(&path) {
+      continue;
+    }
+    // Files not named after their hash weren't stored here, leave them be
+    let relative = path.strip_prefix(&config.root).ok().and_then(Path::to_str);
+    if relative.and_then(hash_of).is_none() {
+      continue;
+    }
+    let recent = fs::metadata(&path)?
+      .modified()
+      .is_ok_and(|modified| modified > cutoff);
+    if recent && !unused_paths.contains(&path) {
+      continue;
+    }
+    report.files.push(path);
+    report.bytes += size;
+  }
+  Ok(report)
+}
@@ -0,0 +1,92 @@
+use std::{
+  env, fs,
+  io::Write,
+  process::{self, Command, Stdio},
+  sync::atomic::{AtomicUsize, Ordering},
+  thread,
+  time::{Duration, Instant},
+};
+
+use super::{sniff, NewMedia};
+use crate::{config::TtsConfig, TRCError};
+
+/// Tells apart the files of commands running at the same time
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn failed(message: String) -> TRCError {
+  TRCError::Media(format!("Text to speech failed: {}", message))
+}
+
+/// Speaks `text` with the locally installed engine configured in `config`
+pub fn speak(config: &TtsConfig, voice: &str, text: &str) -> Result<NewMedia, TRCError> {
+  let output = env::temp_dir().join(format!(
+    "total_recall_tts_{}_{}",
+    process::id(),
+    RUNS.fetch_add(1, Ordering::Relaxed)
+  ));
+  let args = config
+    .command
+    .iter()
+    .map(|arg| {
+      arg
+        .replace("{voice}", voice)
+        .replace("{output}", &output.to_string_lossy())
+    })
+    .collect::<Vec<_>>();
+  let (program, args) = args
+    .split_first()
+    .ok_or_else(|| failed("no command configured".to_owned()))?;
+
+  let result = run(
+    program,
+    args,
+    text,
+    Duration::from_secs(config.timeout_secs),
+  )
+  .and_then(|()| Ok(fs::read(&output)?));
+  let _ = fs::remove_file(&output);
+  let bytes = result?;
+
+  match sniff(&bytes) {
+    Some(mime) if mime.starts_with("audio/") => Ok(NewMedia {
+      bytes,
+      mime: mime.to_owned(),
+      source: None,
+      licence: None,
+    }),
+    _ => Err(failed(format!("{} didn't write audio", program))),
+  }
+}
+
+/// Runs a command with `text` as its input, killing it if it takes longer
+/// than `timeout`
+fn run(program: &str, args: &[String], text: &str, timeout: Duration) -> Result<(), TRCError> {
+  let mut child = Command::new(program)
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()
+    .map_err(|err| failed(format!("couldn't run {}: {}", program, err)))?;
+  // Engines that don't read their input fail on their own if they needed it
+  if let Some(mut stdin) = child.stdin.take() {
+    let _ = stdin.write_all(text.as_bytes());
+  }
+
+  let started = Instant::now();
+  loop {
+    if let Some(status) = child.try_wait()? {
+      return if status.success() {
+        Ok(())
+      } else {
+        Err(failed(format!("{} exited with {}", program, status)))
+      };
+    }
+    if started.elapsed() > timeout {
+      let _ = child.kill();
+      let _ = child.wait();
+      return Err(failed(format!("{} timed out", program)));
+    }
+    thread::sleep(Duration::from_millis(20));
+  }
+}
@@ -0,0 +1,118 @@
+use actix_service::{Service, Transform};
+use actix_web::{
+  dev::{ServiceRequest, ServiceResponse},
+  http::{header, HeaderValue, Method},
+  Error, HttpResponse,
+};
+use futures::future::{ok, FutureExt, LocalBoxFuture, Ready};
+use std::{
+  rc::Rc,
+  task::{Context, Poll},
+};
+
+use crate::config::CorsConfig;
+
+/// Lets browsers on the configured origins call the API. Answers preflight
+/// requests and adds the allow headers to every other response.
+pub struct Cors {
+  config: Rc<CorsConfig>,
+}
+
+impl Cors {
+  pub fn new(config: CorsConfig) -> Self {
+    Cors {
+      config: Rc::new(config),
+    }
+  }
+}
+
+impl<S> Transform<S> for Cors
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+  S::Future: 'static,
+{
+  type Request = ServiceRequest;
+  type Response = ServiceResponse;
+  type Error = Error;
+  type InitError = ();
+  type Transform = CorsMiddleware<S>;
+  type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+  fn new_transform(&self, service: S) -> Self::Future {
+    ok(CorsMiddleware {
+      service,
+      config: self.config.clone(),
+    })
+  }
+}
+
+pub struct CorsMiddleware<S> {
+  service: S,
+  config: Rc<CorsConfig>,
+}
+
+/// Whether `origin` may call the API
+pub fn allowed(config: &CorsConfig, origin: &str) -> bool {
+  config
+    .allowed_origins
+    .iter()
+    .any(|allowed| allowed == "*" || allowed == origin)
+}
+
+impl<S> Service for CorsMiddleware<S>
+where
+  S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+  S::Future: 'static,
+{
+  type Request = ServiceRequest;
+  type Response = ServiceResponse;
+  type Error = Error;
+  type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    self.service.poll_ready(cx)
+  }
+
+  fn call(&mut self, req: ServiceRequest) -> Self::Future {
+    let origin = req
+      .headers()
+      .get(header::ORIGIN)
+      .filter(|origin| {
+        origin
+          .to_str()
+          .is_ok_and(|origin| allowed(&self.config, origin))
+      })
+      .cloned();
+    let origin = match origin {
+      Some(origin) => origin,
+      None => return self.service.call(req).boxed_local(),
+    };
+
+    if req.method() == Method::OPTIONS {
+      let response = HttpResponse::NoContent()
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
+        .header(
+          header::ACCESS_CONTROL_ALLOW_HEADERS,
+          "Authorization, Content-Type",
+        )
+        .header(
+          header::ACCESS_CONTROL_MAX_AGE,
+          self.config.max_age.to_string(),
+        )
+        .header(header::VARY, "Origin")
+        .finish();
+      return ok(req.into_response(response)).boxed_local();
+    }
+
+    let response = self.service.call(req);
+    async move {
+      let mut response = response.await?;
+      let headers = response.headers_mut();
+      headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+      headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+      Ok(response)
+    }
+    .boxed_local()
+  }
+}
@@ -1,22 +1,38 @@
+use actix_files::NamedFile;
 use actix_web::{
-  web::{Data, Json},
+  http::{
+    header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    StatusCode,
+  },
+  web::{Bytes, Data, Json, Query},
   HttpRequest, HttpResponse,
 };
 use bcrypt::verify;
 use diesel::prelude::*;
-use failure::Error;
+use failure::{err_msg, Error};
 use juniper::{graphiql::graphiql_source, http::GraphQLRequest};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
 use wundergraph::scalar::WundergraphScalarValue;
 
 use crate::{
-  db::schema::users,
-  graphql::GQLContext,
+  config::{safe_join, MediaConfig},
+  db::{
+    schema::{backs, cards, decks, users},
+    DBConnection,
+  },
+  graphql::{
+    authorization::{deck_access, UserRole},
+    GQLContext,
+  },
+  media,
   service::{
-    jwt::{encode_jwt, verify_jwt, LoginAttempt},
-    AppState,
+    jwt::{encode_jwt, verify_jwt, Claims, LoginAttempt},
+    sync::{apply_batch, changes_since, PushBatch, SyncParams},
+    upload, AppState,
   },
+  TRCError,
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -25,6 +41,51 @@ pub struct GraphQLData(GraphQLRequest<WundergraphScalarValue>);
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginData(LoginAttempt);
 
+/// The claims of the request's token, unless it's invalid or belongs to an
+/// account that has since been disabled or removed. The role comes from the
+/// account rather than the token, so role changes apply straight away.
+/// Accounts an admin reset the password of are refused as well, until they
+/// set a new password through the GraphQL endpoint.
+fn authorized_user(st: &AppState, conn: &DBConnection, req: &HttpRequest) -> Option<Claims> {
+  match request_claims(st, conn, req)? {
+    (claims, false) => Some(claims),
+    (_, true) => None,
+  }
+}
+
+/// The verified claims of the request's token and whether the account has a
+/// password reset pending
+fn request_claims(st: &AppState, conn: &DBConnection, req: &HttpRequest) -> Option<(Claims, bool)> {
+  let header = req.headers().get("Authorization")?;
+  verified_claims(st, conn, header.to_str().ok()?)
+}
+
+fn verified_claims(st: &AppState, conn: &DBConnection, token: &str) -> Option<(Claims, bool)> {
+  let t = verify_jwt(&st.config.auth.jwt_secret, String::from(token)).ok()?;
+  let (role, disabled, password_reset) = users::table
+    .select((users::role, users::disabled, users::password_reset))
+    .filter(users::id.eq(t.claims.user_id))
+    .get_result::<(UserRole, bool, bool)>(conn)
+    .ok()?;
+  if disabled {
+    None
+  } else {
+    Some((Claims { role, ..t.claims }, password_reset))
+  }
+}
+
+fn unauthorized() -> HttpResponse {
+  HttpResponse::Unauthorized()
+    .content_type("application/json")
+    .body(json!({ "error": "Unauthorized" }))
+}
+
+fn bad_request(message: String) -> HttpResponse {
+  HttpResponse::BadRequest()
+    .content_type("application/json")
+    .body(json!({ "error": message }))
+}
+
 pub async fn graphiql() -> HttpResponse {
   let html = graphiql_source("/graphql");
   HttpResponse::Ok()
@@ -39,13 +100,14 @@ pub async fn graphql(
 ) -> Result<HttpResponse, Error> {
   let conn = st.get_ref().pool.get()?;
 
-  let user_id = req.headers().get("Authorization").and_then(|header| {
-    let auth_string = header.to_str().ok()?;
-    let t = verify_jwt(String::from(auth_string)).ok()?;
-    Some(t.claims.user_id)
-  });
-
-  let ctx = GQLContext::new(conn, user_id);
+  let claims = request_claims(st.get_ref(), &conn, &req);
+  let config = st.get_ref().config.clone();
+  let ctx = match claims {
+    Some((claims, false)) => GQLContext::new(conn, Some(claims.user_id), claims.role, config),
+    // Only the mutation that sets a new password looks at the user
+    Some((claims, true)) => GQLContext::password_reset(conn, claims.user_id, config),
+    None => GQLContext::new(conn, None, UserRole::default(), config),
+  };
   let res = data.execute(&st.get_ref().schema, &ctx);
   Ok(
     HttpResponse::Ok()
@@ -60,10 +122,16 @@ pub async fn login(
 ) -> Result<HttpResponse, Error> {
   let conn = st.get_ref().pool.get()?;
 
-  let (id, password) = users::table
-    .select((users::id, users::password))
+  let (id, password, role, disabled, password_reset) = users::table
+    .select((
+      users::id,
+      users::password,
+      users::role,
+      users::disabled,
+      users::password_reset,
+    ))
     .filter(users::username.eq(attempt.username))
-    .get_result::<(i32, String)>(&conn)?;
+    .get_result::<(i32, String, UserRole, bool, bool)>(&conn)?;
   let valid = verify(attempt.password, &password)?;
   if !valid {
     return Ok(
@@ -72,11 +140,255 @@ pub async fn login(
         .body(json!({ "error": "Login failed" })),
     );
   }
+  if disabled {
+    return Ok(
+      HttpResponse::Forbidden()
+        .content_type("application/json")
+        .body(json!({ "error": "Account disabled" })),
+    );
+  }
+
+  let auth = &st.get_ref().config.auth;
+  let t = encode_jwt(&auth.jwt_secret, id, role, auth.token_days)?;
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/json")
+      .body(json!({ "token": t, "user_id": id, "password_reset": password_reset })),
+  )
+}
+
+pub async fn pull_changes(
+  req: HttpRequest,
+  Query(params): Query<SyncParams>,
+  st: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+  let conn = st.get_ref().pool.get()?;
+  let user_id = match authorized_user(st.get_ref(), &conn, &req) {
+    Some(claims) => claims.user_id,
+    None => return Ok(unauthorized()),
+  };
+  let changes = changes_since(&conn, user_id, params.cursor.unwrap_or(0))?;
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/json")
+      .body(serde_json::to_string(&changes)?),
+  )
+}
+
+pub async fn push_changes(
+  req: HttpRequest,
+  Json(batch): Json<PushBatch>,
+  st: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+  let conn = st.get_ref().pool.get()?;
+  let user_id = match authorized_user(st.get_ref(), &conn, &req) {
+    Some(claims) => claims.user_id,
+    None => return Ok(unauthorized()),
+  };
+  let results = apply_batch(&conn, &st.get_ref().config.media, user_id, batch)?;
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/json")
+      .body(json!({ "results": results })),
+  )
+}
+
+/// Stores an uploaded image or audio file. The returned hash is what
+/// `setBackMedia` takes to attach it to a card.
+pub async fn upload_media(
+  req: HttpRequest,
+  body: Bytes,
+  st: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+  let conn = st.get_ref().pool.get()?;
+  if authorized_user(st.get_ref(), &conn, &req).is_none() {
+    return Ok(unauthorized());
+  }
+
+  let config = &st.get_ref().config.media;
+  let content_type = req
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default();
+  let parsed = upload::boundary(content_type)
+    .ok_or_else(|| "Expected a multipart/form-data body".to_owned())
+    .and_then(|boundary| upload::parse(boundary, &body, config.max_upload_bytes));
+  let new = match parsed {
+    Ok(new) => new,
+    Err(message) => return Ok(bad_request(message)),
+  };
 
-  let t = encode_jwt(id, 30)?;
+  // Images are converted, so the hash to attach is that of the converted file
+  let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+  let stored = conn.transaction(|| {
+    if new.mime.starts_with("image/") {
+      let image = media::store_image(&conn, config, new, time)?;
+      let urls = image.urls(config);
+      Ok((image.image, urls.thumbnail, urls.card))
+    } else {
+      media::store(&conn, config, new, time).map(|stored| (stored, None, None))
+    }
+  });
+  let (stored, thumbnail, card) = match stored {
+    Ok(stored) => stored,
+    Err(TRCError::Media(message)) => return Ok(bad_request(message)),
+    Err(err) => return Err(err.into()),
+  };
   Ok(
     HttpResponse::Ok()
       .content_type("application/json")
-      .body(json!({ "token": t, "user_id": id })),
+      .body(json!({
+        "hash": stored.hash,
+        "mime": stored.mime,
+        "size": stored.size,
+        "url": config.url(&stored.path()),
+        "thumbnail": thumbnail,
+        "card": card,
+      })),
   )
 }
+
+#[derive(Deserialize, Debug)]
+pub struct MediaParams {
+  /// For clients that can't set headers on media requests, like `<img>`
+  token: Option<String>,
+}
+
+/// Whether some deck using the media file at `path` is published or can be
+/// seen by the user
+fn can_see_media(
+  conn: &DBConnection,
+  config: &MediaConfig,
+  user_id: Option<i32>,
+  path: &str,
+) -> QueryResult<bool> {
+  // Cards store media by URL, which is relative when it predates the base URL
+  let urls = vec![config.url(path), path.to_owned()];
+  let using = cards::table
+    .inner_join(backs::table)
+    .inner_join(decks::table)
+    .select((decks::id, decks::published))
+    .filter(cards::deleted_at.is_null())
+    .filter(decks::deleted_at.is_null())
+    .filter(
+      backs::audio
+        .eq_any(&urls)
+        .or(backs::image.eq_any(&urls))
+        .or(backs::image_thumbnail.eq_any(&urls))
+        .or(backs::image_card.eq_any(&urls))
+        .or(cards::front_audio.eq_any(&urls)),
+    )
+    .distinct()
+    .load::<(i32, bool)>(conn)?;
+  for (deck, published) in using {
+    if published {
+      return Ok(true);
+    }
+    if let Some(user_id) = user_id {
+      if deck_access(conn, user_id, deck)?.is_some() {
+        return Ok(true);
+      }
+    }
+  }
+  Ok(false)
+}
+
+/// Serves the file at `path` under the media root. Files stored by content
+/// hash never change, so they are tagged with their hash and may be cached
+/// for good.
+pub fn media_response(
+  req: &HttpRequest,
+  st: &AppState,
+  path: &str,
+  token: Option<&str>,
+) -> Result<HttpResponse, Error> {
+  let config = &st.config.media;
+  let not_found = || Ok(HttpResponse::NotFound().finish());
+  let file = match safe_join(&config.root, path) {
+    Some(file)
+      if media::MEDIA_DIRS
+        .iter()
+        .any(|dir| path.starts_with(&format!("{}/", dir))) =>
+    {
+      file
+    }
+    _ => return not_found(),
+  };
+
+  if config.private {
+    let conn = st.pool.get()?;
+    let token = req
+      .headers()
+      .get("Authorization")
+      .and_then(|header| header.to_str().ok())
+      .or(token);
+    let user = match token {
+      Some(token) => match verified_claims(st, &conn, token) {
+        Some((claims, false)) => Some(claims.user_id),
+        _ => return Ok(unauthorized()),
+      },
+      None => None,
+    };
+    if !can_see_media(&conn, config, user, path)? {
+      return not_found();
+    }
+  }
+
+  let cache = if config.private { "private" } else { "public" };
+  let named = match NamedFile::open(file) {
+    Ok(named) => named.disable_content_disposition(),
+    Err(_) => return not_found(),
+  };
+  // Errors from actix can't cross threads, which failure's can
+  let respond = |named: NamedFile| {
+    named
+      .into_response(req)
+      .map_err(|err| err_msg(err.to_string()))
+  };
+  let hash = match media::hash_of(path) {
+    Some(hash) => hash,
+    None => {
+      // Files stored under their old names may still be replaced
+      let mut resp = respond(named)?;
+      resp.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_str(&format!("{}, no-cache", cache))?,
+      );
+      return Ok(resp);
+    }
+  };
+
+  let etag = format!("\"{}\"", hash);
+  let cache_control = format!("{}, max-age=31536000, immutable", cache);
+  let unchanged = req
+    .headers()
+    .get(IF_NONE_MATCH)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| {
+      value
+        .split(',')
+        .any(|tag| tag.trim().trim_start_matches("W/") == etag || tag.trim() == "*")
+    });
+  let mut resp = if unchanged {
+    HttpResponse::build(StatusCode::NOT_MODIFIED).finish()
+  } else {
+    respond(named.use_etag(false).use_last_modified(false))?
+  };
+  resp
+    .headers_mut()
+    .insert(ETAG, HeaderValue::from_str(&etag)?);
+  resp
+    .headers_mut()
+    .insert(CACHE_CONTROL, HeaderValue::from_str(&cache_control)?);
+  Ok(resp)
+}
+
+pub async fn serve_media(
+  req: HttpRequest,
+  Query(params): Query<MediaParams>,
+  st: Data<AppState>,
+) -> Result<HttpResponse, Error> {
+  let path = req.match_info().query("path");
+  media_response(&req, st.get_ref(), path, params.token.as_deref())
+}
@@ -4,6 +4,8 @@ extern crate serde_derive;
 use jwt::{decode, encode, Header, Validation};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::graphql::authorization::UserRole;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginAttempt {
     pub username: String,
@@ -13,36 +15,35 @@ pub struct LoginAttempt {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: i32,
+    /// Tokens issued before roles existed belong to regular users
+    #[serde(default)]
+    pub role: UserRole,
     pub exp: i32,
 }
 
-struct SecretKey {
-    key: &'static str,
-}
-
-impl SecretKey {
-    fn get_secret_key() -> SecretKey {
-        SecretKey { key: "secret" }
-    }
-}
-
-pub fn encode_jwt(user_id: i32, exp_day: i32) -> Result<String, jwt::errors::Error> {
+pub fn encode_jwt(
+    secret: &str,
+    user_id: i32,
+    role: UserRole,
+    exp_day: i32,
+) -> Result<String, jwt::errors::Error> {
     let exp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
         .as_secs() as i32
         + exp_day * 24 * 60 * 60;
 
-    let jwt_secret_key = SecretKey::get_secret_key();
-    let my_claims = Claims { user_id, exp };
-    let token = encode(&Header::default(), &my_claims, jwt_secret_key.key.as_ref())?;
+    let my_claims = Claims { user_id, role, exp };
+    let token = encode(&Header::default(), &my_claims, secret.as_ref())?;
 
     Ok(token)
 }
 
-pub fn verify_jwt(token: String) -> Result<jwt::TokenData<Claims>, jwt::errors::Error> {
-    let jwt_secret_key = SecretKey::get_secret_key();
+pub fn verify_jwt(
+    secret: &str,
+    token: String,
+) -> Result<jwt::TokenData<Claims>, jwt::errors::Error> {
     let validation = Validation::default();
 
-    decode::<Claims>(&token, jwt_secret_key.key.as_ref(), &validation)
+    decode::<Claims>(&token, secret.as_ref(), &validation)
 }
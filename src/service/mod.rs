@@ -2,15 +2,20 @@ use diesel::r2d2::{ConnectionManager, Pool};
 use std::sync::Arc;
 
 use super::{
+  config::Config,
   db::DBConnection,
   graphql::{GQLContext, Schema},
 };
 
+pub mod cors;
 pub mod endpoints;
 pub mod jwt;
+pub mod sync;
+pub mod upload;
 
 #[derive(Clone)]
 pub struct AppState {
   pub schema: Arc<Schema<GQLContext<DBConnection>>>,
   pub pool: Arc<Pool<ConnectionManager<DBConnection>>>,
+  pub config: Arc<Config>,
 }
@@ -0,0 +1,841 @@
+use diesel::{dsl, prelude::*};
+use std::{
+  collections::HashMap,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  config::MediaConfig,
+  db::{
+    audit::{Audit, Audited},
+    changes::{self as change_log, ChangeKind, Entity},
+    models::{BackRow, CardRow, DeckRow, ScoreRow, SetCardRow, SetRow},
+    schema::{
+      backs, cards, changes, deck_members, decks, group_assignments, group_members, scores,
+      set_cards, sets,
+    },
+    trash, DBConnection,
+  },
+  graphql::{
+    authorization::{accessible_decks, authorize_card, authorize_deck, Access},
+    mutations::utilities::{
+      cards_in_deck, check_language, front_language, insert_back, update_card, CardEdit,
+    },
+  },
+  TRCError,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncParams {
+  pub cursor: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tombstone {
+  pub entity: Entity,
+  pub id: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Changes {
+  pub cursor: i64,
+  pub decks: Vec<DeckRow>,
+  pub cards: Vec<CardRow>,
+  pub backs: Vec<BackRow>,
+  pub sets: Vec<SetRow>,
+  pub set_cards: Vec<SetCardRow>,
+  pub scores: Vec<ScoreRow>,
+  pub deleted: Vec<Tombstone>,
+}
+
+fn latest_cursor(conn: &DBConnection) -> QueryResult<i64> {
+  Ok(
+    changes::table
+      .select(dsl::max(changes::id))
+      .get_result::<Option<i64>>(conn)?
+      .unwrap_or(0),
+  )
+}
+
+/// Owners of decks shared with the user, in the trash or not. Changes to
+/// those decks are logged under their owners.
+fn shared_owners(conn: &DBConnection, user_id: i32) -> QueryResult<Vec<i32>> {
+  let mut owners = deck_members::table
+    .inner_join(decks::table)
+    .select(decks::owner)
+    .filter(deck_members::user_id.eq(user_id))
+    .filter(deck_members::accepted.eq(true))
+    .load::<i32>(conn)?;
+  owners.extend(
+    group_assignments::table
+      .inner_join(decks::table)
+      .inner_join(group_members::table.on(group_members::group_id.eq(group_assignments::group_id)))
+      .select(decks::owner)
+      .filter(group_members::user_id.eq(user_id))
+      .filter(group_members::accepted.eq(true))
+      .load::<i32>(conn)?,
+  );
+  owners.sort_unstable();
+  owners.dedup();
+  owners.retain(|owner| *owner != user_id);
+  Ok(owners)
+}
+
+/// Every deck the user has access to with its cards, and the user's own sets
+/// and scores on them, used when a client has no cursor yet.
+fn snapshot(conn: &DBConnection, user_id: i32) -> QueryResult<Changes> {
+  // Read the cursor first: anything written while the snapshot is taken will
+  // be sent again on the next pull, which clients treat as an upsert.
+  let cursor = latest_cursor(conn)?;
+
+  let deck_ids = accessible_decks(conn, user_id)?;
+  let decks = decks::table
+    .select(DeckRow::COLUMNS)
+    .filter(decks::id.eq_any(&deck_ids))
+    .load::<DeckRow>(conn)?;
+  let cards = cards::table
+    .inner_join(backs::table)
+    .select(CardRow::COLUMNS)
+    .filter(cards::deck.eq_any(&deck_ids))
+    .filter(cards::deleted_at.is_null())
+    .load::<CardRow>(conn)?;
+  let card_ids = cards.iter().map(|card| card.id).collect::<Vec<_>>();
+  let back_ids = cards.iter().map(|card| card.back).collect::<Vec<_>>();
+  let backs = backs::table
+    .select(BackRow::COLUMNS)
+    .filter(backs::id.eq_any(back_ids))
+    .load::<BackRow>(conn)?;
+  let sets = sets::table
+    .select(SetRow::COLUMNS)
+    .filter(sets::owner.eq(user_id))
+    .filter(sets::deck.eq_any(&deck_ids))
+    .filter(sets::deleted_at.is_null())
+    .load::<SetRow>(conn)?;
+  let set_ids = sets.iter().map(|set| set.id).collect::<Vec<_>>();
+  let set_cards = set_cards::table
+    .select(SetCardRow::COLUMNS)
+    .filter(set_cards::set_id.eq_any(set_ids))
+    .filter(set_cards::card_id.eq_any(&card_ids))
+    .load::<SetCardRow>(conn)?;
+  let scores = scores::table
+    .select(ScoreRow::COLUMNS)
+    .filter(scores::card.eq_any(card_ids))
+    .filter(scores::owner.eq(user_id))
+    .load::<ScoreRow>(conn)?;
+
+  Ok(Changes {
+    cursor,
+    decks,
+    cards,
+    backs,
+    sets,
+    set_cards,
+    scores,
+    deleted: vec![],
+  })
+}
+
+/// Collects the current state of every row touched since `cursor`, and
+/// tombstones for the ones whose latest change was a deletion.
+pub fn changes_since(conn: &DBConnection, user_id: i32, cursor: i64) -> QueryResult<Changes> {
+  if cursor <= 0 {
+    return snapshot(conn, user_id);
+  }
+
+  let log = changes::table
+    .select((
+      changes::id,
+      changes::entity,
+      changes::entity_id,
+      changes::kind,
+    ))
+    .filter(
+      changes::owner.eq(user_id).or(
+        changes::owner
+          .eq_any(shared_owners(conn, user_id)?)
+          .and(changes::entity.eq_any(vec![Entity::Deck, Entity::Card, Entity::Back])),
+      ),
+    )
+    .filter(changes::id.gt(cursor))
+    .order(changes::id.asc())
+    .load::<(i64, Entity, i32, ChangeKind)>(conn)?;
+
+  let mut result = Changes {
+    cursor: log.last().map(|(id, _, _, _)| *id).unwrap_or(cursor),
+    ..Changes::default()
+  };
+
+  let mut latest = HashMap::new();
+  for (_, entity, id, kind) in log {
+    latest.insert((entity, id), kind);
+  }
+
+  let mut upserted: HashMap<Entity, Vec<i32>> = HashMap::new();
+  for ((entity, id), kind) in latest {
+    match kind {
+      ChangeKind::Upsert => upserted.entry(entity).or_default().push(id),
+      ChangeKind::Delete => result.deleted.push(Tombstone { entity, id }),
+    }
+  }
+
+  // Only rows the user has access to are sent, whatever ended up in the logs
+  let deck_ids = accessible_decks(conn, user_id)?;
+  let owned_sets = sets::table.select(sets::id).filter(sets::owner.eq(user_id));
+  for (entity, ids) in upserted {
+    match entity {
+      Entity::Deck => {
+        result.decks = decks::table
+          .select(DeckRow::COLUMNS)
+          .filter(decks::id.eq_any(ids))
+          .filter(decks::id.eq_any(&deck_ids))
+          .load(conn)?
+      }
+      Entity::Card => {
+        result.cards = cards::table
+          .inner_join(backs::table)
+          .select(CardRow::COLUMNS)
+          .filter(cards::id.eq_any(ids))
+          .filter(cards::deck.eq_any(&deck_ids))
+          .load(conn)?
+      }
+      Entity::Back => {
+        result.backs = backs::table
+          .select(BackRow::COLUMNS)
+          .filter(backs::id.eq_any(ids))
+          .filter(
+            backs::id.eq_any(
+              cards::table
+                .select(cards::back)
+                .filter(cards::deck.eq_any(&deck_ids)),
+            ),
+          )
+          .load(conn)?
+      }
+      Entity::Set => {
+        result.sets = sets::table
+          .select(SetRow::COLUMNS)
+          .filter(sets::id.eq_any(ids))
+          .filter(sets::owner.eq(user_id))
+          .load(conn)?
+      }
+      Entity::SetCard => {
+        result.set_cards = set_cards::table
+          .select(SetCardRow::COLUMNS)
+          .filter(set_cards::id.eq_any(ids))
+          .filter(set_cards::set_id.eq_any(owned_sets))
+          .load(conn)?
+      }
+      Entity::Score => {
+        result.scores = scores::table
+          .select(ScoreRow::COLUMNS)
+          .filter(scores::id.eq_any(ids))
+          .filter(scores::owner.eq(user_id))
+          .load(conn)?
+      }
+      // Accounts, memberships, groups and languages aren't part of the
+      // synced state
+      Entity::User
+      | Entity::DeckMember
+      | Entity::Group
+      | Entity::GroupMember
+      | Entity::GroupAssignment
+      | Entity::Language => {}
+    }
+  }
+
+  Ok(result)
+}
+
+/// How to treat an offline edit made against an outdated version
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+  LastWriterWins,
+  #[default]
+  Reject,
+}
+
+/// Either a server id, or the `client_id` of a row created earlier in the
+/// same batch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Reference {
+  Id(i32),
+  Client(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+  CreateDeck {
+    client_id: Option<String>,
+    name: String,
+    language: i32,
+  },
+  UpdateDeck {
+    id: Reference,
+    version: i32,
+    name: String,
+  },
+  DeleteDeck {
+    id: Reference,
+    version: i32,
+  },
+  CreateCard {
+    client_id: Option<String>,
+    deck: Reference,
+    front: String,
+    back: String,
+    link: Option<String>,
+  },
+  /// Fields left out stay as they are
+  UpdateCard {
+    id: Reference,
+    version: i32,
+    front: Option<String>,
+    back: Option<String>,
+    link: Option<String>,
+  },
+  DeleteCard {
+    id: Reference,
+    version: i32,
+  },
+  CreateSet {
+    client_id: Option<String>,
+    deck: Reference,
+    name: String,
+    cards: Vec<Reference>,
+  },
+  UpdateSet {
+    id: Reference,
+    version: i32,
+    name: String,
+  },
+  DeleteSet {
+    id: Reference,
+    version: i32,
+  },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PushBatch {
+  #[serde(default)]
+  pub policy: ConflictPolicy,
+  pub operations: Vec<Operation>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+  Applied,
+  Conflict,
+  NotFound,
+  Unauthorized,
+  /// The operation can't be applied as it is, e.g. it picks a language that
+  /// has been disabled since
+  Invalid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OperationResult {
+  pub status: OperationStatus,
+  pub id: Option<i32>,
+  pub version: Option<i32>,
+}
+
+impl OperationResult {
+  fn applied(id: i32, version: Option<i32>) -> Self {
+    OperationResult {
+      status: OperationStatus::Applied,
+      id: Some(id),
+      version,
+    }
+  }
+
+  fn conflict(id: i32, version: i32) -> Self {
+    OperationResult {
+      status: OperationStatus::Conflict,
+      id: Some(id),
+      version: Some(version),
+    }
+  }
+
+  fn failed(status: OperationStatus, id: Option<i32>) -> Self {
+    OperationResult {
+      status,
+      id,
+      version: None,
+    }
+  }
+}
+
+/// The owner a change belongs to if the user may make it, or `None` if the
+/// operation is to be reported as unauthorized
+fn authorized(authorization: Result<i32, TRCError>) -> Result<Option<i32>, TRCError> {
+  match authorization {
+    Ok(owner) => Ok(Some(owner)),
+    Err(TRCError::Unauthorized) => Ok(None),
+    Err(err) => Err(err),
+  }
+}
+
+struct Push<'a> {
+  conn: &'a DBConnection,
+  media: &'a MediaConfig,
+  user_id: i32,
+  policy: ConflictPolicy,
+  time: i64,
+  created: HashMap<String, i32>,
+}
+
+impl<'a> Push<'a> {
+  fn resolve(&self, reference: &Reference) -> Option<i32> {
+    match reference {
+      Reference::Id(id) => Some(*id),
+      Reference::Client(client_id) => self.created.get(client_id).cloned(),
+    }
+  }
+
+  fn remember(&mut self, client_id: Option<String>, id: i32) {
+    if let Some(client_id) = client_id {
+      self.created.insert(client_id, id);
+    }
+  }
+
+  /// Checks the version an edit was based on. Returns the result to report
+  /// if the operation must not be applied.
+  fn check(&self, id: i32, current: i32, expected: i32) -> Option<OperationResult> {
+    if current != expected && self.policy == ConflictPolicy::Reject {
+      return Some(OperationResult::conflict(id, current));
+    }
+    None
+  }
+
+  fn audit(&self) -> Audit {
+    Audit::new(Some(self.user_id), self.user_id, self.time)
+  }
+
+  fn deck_version(&self, id: i32) -> QueryResult<Option<i32>> {
+    decks::table
+      .select(decks::version)
+      .filter(decks::id.eq(id))
+      .filter(decks::deleted_at.is_null())
+      .get_result::<i32>(self.conn)
+      .optional()
+  }
+
+  fn card_version(&self, id: i32) -> QueryResult<Option<i32>> {
+    cards::table
+      .inner_join(decks::table)
+      .select(cards::version)
+      .filter(cards::id.eq(id))
+      .filter(cards::deleted_at.is_null())
+      .filter(decks::deleted_at.is_null())
+      .get_result::<i32>(self.conn)
+      .optional()
+  }
+
+  fn set_owner(&self, id: i32) -> QueryResult<Option<(i32, i32, i32)>> {
+    sets::table
+      .select((sets::owner, sets::deck, sets::version))
+      .filter(sets::id.eq(id))
+      .filter(sets::deleted_at.is_null())
+      .get_result::<(i32, i32, i32)>(self.conn)
+      .optional()
+  }
+
+  /// Sets belong to whoever made them, editors of the deck may change them
+  /// too
+  fn may_edit_set(&self, owner: i32, deck: i32) -> Result<bool, TRCError> {
+    Ok(
+      owner == self.user_id
+        || authorized(authorize_deck(self.conn, self.user_id, deck, Access::Edit))?.is_some(),
+    )
+  }
+
+  fn apply(&mut self, operation: Operation) -> Result<OperationResult, TRCError> {
+    let conn = self.conn;
+    let (user_id, time) = (self.user_id, self.time);
+
+    match operation {
+      Operation::CreateDeck {
+        client_id,
+        name,
+        language,
+      } => {
+        if check_language(conn, language).is_err() {
+          return Ok(OperationResult::failed(OperationStatus::Invalid, None));
+        }
+        let inserted = diesel::insert_into(decks::table)
+          .values((
+            decks::name.eq(name),
+            decks::owner.eq(user_id),
+            decks::language.eq(language),
+            decks::updated_at.eq(time),
+          ))
+          .returning(decks::id)
+          .get_result::<i32>(conn)?;
+        change_log::record(
+          conn,
+          user_id,
+          Entity::Deck,
+          &[inserted],
+          ChangeKind::Upsert,
+          time,
+        )?;
+        self.audit().inserted::<DeckRow>(conn, &[inserted])?;
+        self.remember(client_id, inserted);
+        Ok(OperationResult::applied(inserted, Some(1)))
+      }
+      Operation::UpdateDeck { id, version, name } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let current = match self.deck_version(id)? {
+          Some(current) => current,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        let owner = match authorized(authorize_deck(conn, user_id, id, Access::Edit))? {
+          Some(owner) => owner,
+          None => {
+            return Ok(OperationResult::failed(
+              OperationStatus::Unauthorized,
+              Some(id),
+            ))
+          }
+        };
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = DeckRow::load(conn, &[id])?;
+        diesel::update(decks::table.filter(decks::id.eq(id)))
+          .set((
+            decks::name.eq(name),
+            decks::updated_at.eq(time),
+            decks::version.eq(current + 1),
+          ))
+          .execute(conn)?;
+        change_log::record(conn, owner, Entity::Deck, &[id], ChangeKind::Upsert, time)?;
+        Audit::new(Some(user_id), owner, time).updated(conn, before)?;
+        Ok(OperationResult::applied(id, Some(current + 1)))
+      }
+      Operation::DeleteDeck { id, version } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let current = match self.deck_version(id)? {
+          Some(current) => current,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        // Deleting a deck takes its sets and everyone's scores with it, which
+        // is left to the owner as in the GraphQL mutation
+        let owner = match authorized(authorize_deck(conn, user_id, id, Access::Own))? {
+          Some(owner) => owner,
+          None => {
+            return Ok(OperationResult::failed(
+              OperationStatus::Unauthorized,
+              Some(id),
+            ))
+          }
+        };
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = DeckRow::load(conn, &[id])?;
+        trash::trash_deck(conn, owner, id, time)?;
+        Audit::new(Some(user_id), owner, time).deleted(conn, before)?;
+        Ok(OperationResult::applied(id, None))
+      }
+      Operation::CreateCard {
+        client_id,
+        deck,
+        front,
+        back,
+        link,
+      } => {
+        let deck = match self.resolve(&deck) {
+          Some(deck) => deck,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let language = match decks::table
+          .select(decks::language)
+          .filter(decks::id.eq(deck))
+          .filter(decks::deleted_at.is_null())
+          .get_result::<i32>(conn)
+          .optional()?
+        {
+          Some(language) => language,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        // Editors may add cards to decks shared with them, as with CreateCard
+        let owner = match authorized(authorize_deck(conn, user_id, deck, Access::Edit))? {
+          Some(owner) => owner,
+          None => return Ok(OperationResult::failed(OperationStatus::Unauthorized, None)),
+        };
+
+        let inserted_back = insert_back(conn, self.media, language, back, time)?;
+        let inserted = diesel::insert_into(cards::table)
+          .values((
+            cards::front_language.eq(front_language(conn, deck)?),
+            cards::front.eq(front),
+            cards::deck.eq(deck),
+            cards::link.eq(link),
+            cards::created_at.eq(time),
+            cards::updated_at.eq(time),
+            cards::back.eq(inserted_back),
+          ))
+          .returning(cards::id)
+          .get_result::<i32>(conn)?;
+        change_log::record(
+          conn,
+          owner,
+          Entity::Back,
+          &[inserted_back],
+          ChangeKind::Upsert,
+          time,
+        )?;
+        change_log::record(
+          conn,
+          owner,
+          Entity::Card,
+          &[inserted],
+          ChangeKind::Upsert,
+          time,
+        )?;
+        Audit::new(Some(user_id), owner, time).inserted::<CardRow>(conn, &[inserted])?;
+        self.remember(client_id, inserted);
+        Ok(OperationResult::applied(inserted, Some(1)))
+      }
+      Operation::UpdateCard {
+        id,
+        version,
+        front,
+        back,
+        link,
+      } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let current = match self.card_version(id)? {
+          Some(current) => current,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        // Editors may change cards of decks shared with them
+        let owner = match authorized(authorize_card(conn, user_id, id, Access::Edit))? {
+          Some(owner) => owner,
+          None => {
+            return Ok(OperationResult::failed(
+              OperationStatus::Unauthorized,
+              Some(id),
+            ))
+          }
+        };
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = CardRow::load(conn, &[id])?;
+        let edit = CardEdit {
+          front: front.as_deref(),
+          back: back.as_deref(),
+          link: link.as_deref(),
+          ..CardEdit::default()
+        };
+        update_card(conn, self.media, owner, id, edit, time)?;
+        Audit::new(Some(user_id), owner, time).updated(conn, before)?;
+        Ok(OperationResult::applied(id, Some(current + 1)))
+      }
+      Operation::DeleteCard { id, version } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let current = match self.card_version(id)? {
+          Some(current) => current,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        // Editors may change cards of decks shared with them
+        let owner = match authorized(authorize_card(conn, user_id, id, Access::Edit))? {
+          Some(owner) => owner,
+          None => {
+            return Ok(OperationResult::failed(
+              OperationStatus::Unauthorized,
+              Some(id),
+            ))
+          }
+        };
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = CardRow::load(conn, &[id])?;
+        trash::trash_card(conn, owner, id, time)?;
+        Audit::new(Some(user_id), owner, time).deleted(conn, before)?;
+        Ok(OperationResult::applied(id, None))
+      }
+      Operation::CreateSet {
+        client_id,
+        deck,
+        name,
+        cards,
+      } => {
+        let deck = match self.resolve(&deck) {
+          Some(deck) => deck,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        if self.deck_version(deck)?.is_none() {
+          return Ok(OperationResult::failed(OperationStatus::NotFound, None));
+        }
+        // Anyone who may study a deck may make sets of its cards
+        if authorized(authorize_deck(conn, user_id, deck, Access::View))?.is_none() {
+          return Ok(OperationResult::failed(OperationStatus::Unauthorized, None));
+        }
+        let mut card_ids = vec![];
+        for card in &cards {
+          match self.resolve(card) {
+            Some(card) => card_ids.push(card),
+            None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+          }
+        }
+        if !cards_in_deck(conn, deck, &card_ids)? {
+          return Ok(OperationResult::failed(OperationStatus::Invalid, None));
+        }
+
+        let inserted = diesel::insert_into(sets::table)
+          .values((
+            sets::name.eq(name),
+            sets::deck.eq(deck),
+            sets::owner.eq(user_id),
+            sets::created_at.eq(time),
+            sets::updated_at.eq(time),
+          ))
+          .returning(sets::id)
+          .get_result::<i32>(conn)?;
+        let set_card_ids = diesel::insert_into(set_cards::table)
+          .values(
+            card_ids
+              .into_iter()
+              .map(|card_id| {
+                (
+                  set_cards::card_id.eq(card_id),
+                  set_cards::set_id.eq(inserted),
+                  set_cards::updated_at.eq(time),
+                )
+              })
+              .collect::<Vec<_>>(),
+          )
+          .returning(set_cards::id)
+          .get_results::<i32>(conn)?;
+        change_log::record(
+          conn,
+          user_id,
+          Entity::Set,
+          &[inserted],
+          ChangeKind::Upsert,
+          time,
+        )?;
+        self.audit().inserted::<SetRow>(conn, &[inserted])?;
+        change_log::record(
+          conn,
+          user_id,
+          Entity::SetCard,
+          &set_card_ids,
+          ChangeKind::Upsert,
+          time,
+        )?;
+        self.remember(client_id, inserted);
+        Ok(OperationResult::applied(inserted, Some(1)))
+      }
+      Operation::UpdateSet { id, version, name } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let (owner, deck, current) = match self.set_owner(id)? {
+          Some(found) => found,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        if !self.may_edit_set(owner, deck)? {
+          return Ok(OperationResult::failed(
+            OperationStatus::Unauthorized,
+            Some(id),
+          ));
+        }
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = SetRow::load(conn, &[id])?;
+        diesel::update(sets::table.filter(sets::id.eq(id)))
+          .set((
+            sets::name.eq(name),
+            sets::updated_at.eq(time),
+            sets::version.eq(current + 1),
+          ))
+          .execute(conn)?;
+        change_log::record(conn, owner, Entity::Set, &[id], ChangeKind::Upsert, time)?;
+        Audit::new(Some(user_id), owner, time).updated(conn, before)?;
+        Ok(OperationResult::applied(id, Some(current + 1)))
+      }
+      Operation::DeleteSet { id, version } => {
+        let id = match self.resolve(&id) {
+          Some(id) => id,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, None)),
+        };
+        let (owner, deck, current) = match self.set_owner(id)? {
+          Some(found) => found,
+          None => return Ok(OperationResult::failed(OperationStatus::NotFound, Some(id))),
+        };
+        if !self.may_edit_set(owner, deck)? {
+          return Ok(OperationResult::failed(
+            OperationStatus::Unauthorized,
+            Some(id),
+          ));
+        }
+        if let Some(result) = self.check(id, current, version) {
+          return Ok(result);
+        }
+
+        let before = SetRow::load(conn, &[id])?;
+        trash::trash_set(conn, id, time)?;
+        Audit::new(Some(user_id), owner, time).deleted(conn, before)?;
+        Ok(OperationResult::applied(id, None))
+      }
+    }
+  }
+}
+
+/// Applies a batch of offline edits in a single transaction, so a failure
+/// part way through leaves nothing behind. Conflicts and authorization
+/// failures are reported per operation and do not abort the batch.
+pub fn apply_batch(
+  conn: &DBConnection,
+  media: &MediaConfig,
+  user_id: i32,
+  batch: PushBatch,
+) -> Result<Vec<OperationResult>, TRCError> {
+  conn.transaction(|| {
+    let mut push = Push {
+      conn,
+      media,
+      user_id,
+      policy: batch.policy,
+      time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64,
+      created: HashMap::new(),
+    };
+
+    // Cards may wait on audio and images to download
+    change_log::deferred(conn, || {
+      let mut results = vec![];
+      for operation in batch.operations {
+        results.push(push.apply(operation)?);
+      }
+      Ok(results)
+    })
+  })
+}
@@ -0,0 +1,79 @@
+use multipart::server::Multipart;
+use std::io::{Cursor, Read};
+
+use crate::media::{self, NewMedia};
+
+/// The boundary of a `multipart/form-data` content type
+pub fn boundary(content_type: &str) -> Option<&str> {
+  let mut parts = content_type.split(';');
+  if !parts
+    .next()?
+    .trim()
+    .eq_ignore_ascii_case("multipart/form-data")
+  {
+    return None;
+  }
+  parts
+    .filter_map(|part| {
+      let mut pair = part.trim().splitn(2, '=');
+      match (pair.next()?, pair.next()?) {
+        (key, value) if key.eq_ignore_ascii_case("boundary") => Some(value.trim_matches('"')),
+        _ => None,
+      }
+    })
+    .next()
+}
+
+/// Reads an uploaded image or audio file from a multipart form with a `file`
+/// field, along with optional `source` and `licence` fields. The type is
+/// taken from the content, which has to agree with what the client claims.
+/// Errors are meant for the uploader.
+pub fn parse(boundary: &str, body: &[u8], max_size: u64) -> Result<NewMedia, String> {
+  let invalid = |err: std::io::Error| format!("Invalid form: {}", err);
+  let mut form = Multipart::with_body(Cursor::new(body), boundary);
+  let mut file = None;
+  let mut source = None;
+  let mut licence = None;
+  while let Some(mut field) = form.read_entry().map_err(invalid)? {
+    let mut value = vec![];
+    field
+      .data
+      .by_ref()
+      .take(max_size + 1)
+      .read_to_end(&mut value)
+      .map_err(invalid)?;
+    match &*field.headers.name {
+      "file" => {
+        let declared = field
+          .headers
+          .content_type
+          .map(|mime| format!("{}/{}", mime.type_(), mime.subtype()));
+        file = Some((value, declared));
+      }
+      "source" => source = Some(String::from_utf8_lossy(&value).into_owned()),
+      "licence" => licence = Some(String::from_utf8_lossy(&value).into_owned()),
+      _ => {}
+    }
+  }
+
+  let (bytes, declared) = file.ok_or_else(|| "No file was uploaded".to_owned())?;
+  if bytes.len() as u64 > max_size {
+    return Err(format!("Files may be at most {} bytes", max_size));
+  }
+  let mime = media::sniff(&bytes).ok_or_else(|| {
+    "Only JPEG, PNG, GIF and WebP images and MP3, Ogg and WAV audio can be uploaded".to_owned()
+  })?;
+  if let Some(declared) = declared.filter(|declared| declared != "application/octet-stream") {
+    let kind = |mime: &str| mime.split('/').next().unwrap_or_default().to_owned();
+    if kind(&declared) != kind(mime) {
+      return Err(format!("The file was sent as {} but is {}", declared, mime));
+    }
+  }
+
+  Ok(NewMedia {
+    bytes,
+    mime: mime.to_owned(),
+    source: source.filter(|source| !source.is_empty()),
+    licence: licence.filter(|licence| !licence.is_empty()),
+  })
+}
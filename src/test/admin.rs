@@ -0,0 +1,299 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::schema::users,
+    graphql::authorization::UserRole,
+    service::endpoints::{graphql, login},
+    test::init,
+  };
+  use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  use crate::test::LoginResponse;
+
+  #[actix_rt::test]
+  async fn test_admin() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    for username in &["test_user", "test_admin"] {
+      let req = TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({
+          "query": "mutation Register($username: String!, $password: String!) {
+            CreateUser(NewUser: { username: $username, password: $password }) {
+              username
+            }
+          }",
+          "variables": {
+            "username": username,
+            "password": "test",
+          },
+        }))
+        .to_request();
+      let resp = test::call_service(&mut app, req).await;
+
+      assert!(resp.status().is_success(), "Failed to create user");
+    }
+
+    {
+      let conn = data.pool.get().unwrap();
+      diesel::update(users::table.filter(users::username.eq("test_admin")))
+        .set(users::role.eq(UserRole::ADMIN))
+        .execute(&conn)
+        .unwrap();
+    }
+
+    let mut logins = vec![];
+    for username in &["test_user", "test_admin"] {
+      let req = TestRequest::post()
+        .uri("/login")
+        .set_json(&json!({
+          "username": username,
+          "password": "test",
+        }))
+        .to_request();
+      let resp = test::call_service(&mut app, req).await;
+
+      assert!(resp.status().is_success(), "Login failed");
+
+      let body = test::read_body(resp).await;
+      let body = from_utf8(&body).unwrap();
+      let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+      logins.push(login_response);
+    }
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          accounts {
+            username
+          }
+        }",
+      }))
+      .header("Authorization", logins[0].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(refused["errors"][0]["message"], "Unauthorized");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Disable($user: Int!) {
+          disableAccount(user: $user, disabled: true)
+          addLanguage(name: \"Klingon\", abbreviation: \"tlh\")
+        }",
+        "variables": {
+          "user": logins[0].user_id,
+        },
+      }))
+      .header("Authorization", logins[1].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["disableAccount"], true);
+    assert!(result["data"]["addLanguage"].is_number());
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          trash {
+            id
+          }
+        }",
+      }))
+      .header("Authorization", logins[0].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      refused["errors"][0]["message"], "Unauthorized",
+      "Tokens of disabled accounts should stop working"
+    );
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Reset($user: Int!) {
+          disableAccount(user: $user, disabled: false)
+          forcePasswordReset(user: $user, password: \"temporary\")
+        }",
+        "variables": {
+          "user": logins[0].user_id,
+        },
+      }))
+      .header("Authorization", logins[1].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"disableAccount\":true,\"forcePasswordReset\":true}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "temporary",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let login: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(login["password_reset"], true);
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          accounts(search: \"test\") {
+            username
+            role
+            disabled
+            passwordReset
+          }
+        }",
+      }))
+      .header("Authorization", logins[1].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["accounts"],
+      json!([
+        {
+          "username": "test_user",
+          "role": "USER",
+          "disabled": false,
+          "passwordReset": true,
+        },
+        {
+          "username": "test_admin",
+          "role": "ADMIN",
+          "disabled": false,
+          "passwordReset": false,
+        },
+      ])
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          literal: accounts(search: \"t%r\") {
+            username
+          }
+          negative: accounts(limit: -1, offset: -1) {
+            username
+          }
+        }",
+      }))
+      .header("Authorization", logins[1].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"literal\":[],\"negative\":[]}}",
+      "Wildcards are searched for literally and negative pages are empty"
+    );
+
+    let token = login["token"].as_str().unwrap().to_owned();
+
+    let update_user = |changes: &str| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({
+          "query": format!("mutation {{
+            UpdateUser(UpdateUser: {{ id: {}, {} }}) {{
+              username
+            }}
+          }}", logins[0].user_id, changes),
+        }))
+        .header("Authorization", token.clone())
+        .to_request()
+    };
+    let resp = test::call_service(&mut app, update_user("timezone: \"Europe/Amsterdam\"")).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      refused["data"]["UpdateUser"].is_null(),
+      "Nothing but a new password is accepted after a reset"
+    );
+    let resp = test::call_service(&mut app, update_user("password: \"renewed\"")).await;
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"UpdateUser\":{\"username\":\"test_user\"}}}"
+    );
+    let resp = test::call_service(&mut app, update_user("timezone: \"Europe/Amsterdam\"")).await;
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"UpdateUser\":{\"username\":\"test_user\"}}}"
+    );
+
+    {
+      let conn = data.pool.get().unwrap();
+      diesel::update(users::table.filter(users::username.eq("test_admin")))
+        .set(users::role.eq(UserRole::USER))
+        .execute(&conn)
+        .unwrap();
+    }
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          accounts {
+            username
+          }
+        }",
+      }))
+      .header("Authorization", logins[1].token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      refused["errors"][0]["message"], "Unauthorized",
+      "Demoted administrators lose their rights before their token expires"
+    );
+  }
+}
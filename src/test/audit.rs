@@ -0,0 +1,179 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    service::endpoints::{graphql, login},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  #[actix_rt::test]
+  async fn test_audit() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_deck_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation UpdateDeck($id: Int!, $name: String!) {
+          UpdateDeck(UpdateDeck: { name: $name, id: $id }) {
+            name
+          }
+        }",
+        "variables": {
+          "name": "changed_name",
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to update deck");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation DeleteDeck($id: Int!) {
+          DeleteDeck(DeleteDeck: { id: $id }) {
+            count
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to delete deck");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query AuditLog($id: Int!) {
+          auditLog(entity: DECK, entityId: $id) {
+            actor
+            operation
+            before
+            after
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let audit: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let entries = audit["data"]["auditLog"].as_array().unwrap();
+    let operations = entries
+      .iter()
+      .map(|entry| entry["operation"].as_str().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(operations, vec!["DELETE", "UPDATE", "INSERT"]);
+    assert_eq!(entries[1]["actor"], login_response.user_id);
+
+    let before: Value = serde_json::from_str(entries[1]["before"].as_str().unwrap()).unwrap();
+    let after: Value = serde_json::from_str(entries[1]["after"].as_str().unwrap()).unwrap();
+    assert_eq!(before["name"], "test_deck");
+    assert_eq!(after["name"], "changed_name");
+    assert_eq!(entries[0]["after"], Value::Null);
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          auditLog(entity: SET) {
+            id
+          }
+          negative: auditLog(limit: -1) {
+            id
+          }
+        }",
+      }))
+      .header("Authorization", login_response.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"auditLog\":[],\"negative\":[]}}"
+    );
+  }
+}
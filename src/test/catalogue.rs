@@ -0,0 +1,242 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    service::endpoints::{graphql, login},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  #[actix_rt::test]
+  async fn test_catalogue() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_reader",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create reader");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_reader",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Reader login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let reader_login: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_deck_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Clone($deck: Int!) {
+          cloneDeck(deck: $deck)
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", reader_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      refused["errors"].is_array(),
+      "Private decks should not be cloneable"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Publish($id: Int!) {
+          UpdateDeck(UpdateDeck: {
+            id: $id,
+            name: \"Everyday words\",
+            description: \"Common words for travelling\",
+            licence: \"CC-BY-4.0\",
+            published: true,
+          }) {
+            published
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"UpdateDeck\":{\"published\":true}}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Clone($deck: Int!) {
+          cloneDeck(deck: $deck, includeSets: true)
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", reader_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let cloned: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let clone_id = cloned["data"]["cloneDeck"].clone();
+    assert!(clone_id.is_number(), "Failed to clone deck");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Clone($id: Int!) {
+          Deck(primaryKey: { id: $id }) {
+            name
+            licence
+            published
+            source
+            owner {
+              username
+            }
+          }
+          publicDecks(language: \"AF\", search: \"travel\") {
+            name
+            owner
+            languageName
+            cardCount
+            clones
+          }
+          negative: publicDecks(limit: -1, offset: -1) {
+            name
+          }
+        }",
+        "variables": {
+          "id": clone_id,
+        },
+      }))
+      .header("Authorization", reader_login.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["Deck"],
+      json!({
+        "name": "Everyday words",
+        "licence": "CC-BY-4.0",
+        "published": false,
+        "source": deck_id,
+        "owner": { "username": "test_reader" },
+      })
+    );
+    assert_eq!(
+      result["data"]["publicDecks"],
+      json!([{
+        "name": "Everyday words",
+        "owner": "test_user",
+        "languageName": "Afrikaans",
+        "cardCount": 0,
+        "clones": 1,
+      }])
+    );
+    assert_eq!(result["data"]["negative"], json!([]));
+  }
+}
@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cli::{deck, user},
+    config::MediaConfig,
+    db::schema::{backs, cards},
+    graphql::authorization::UserRole,
+    test::init,
+  };
+  use diesel::prelude::*;
+  use std::{env, fs};
+
+  #[test]
+  fn test_deck_export_import() {
+    let data = init();
+    let conn = data.pool.get().unwrap();
+    let exporter = env::temp_dir().join(format!("total_recall_export_{}", std::process::id()));
+    fs::create_dir_all(exporter.join("audio")).unwrap();
+    fs::write(exporter.join("audio/hallo.wav"), b"RIFF\0\0\0\0WAVEhallo").unwrap();
+    let config = MediaConfig {
+      root: env::temp_dir().join(format!("total_recall_import_{}", std::process::id())),
+      ..MediaConfig::default()
+    };
+
+    user::create(&conn, "test_user", "test", UserRole::USER).unwrap();
+    let exported: deck::DeckExport = serde_json::from_str(
+      "{
+        \"name\": \"Everyday words\",
+        \"description\": null,
+        \"licence\": \"CC-BY-4.0\",
+        \"language\": \"af\",
+        \"cards\": [
+          { \"front\": \"hello\", \"back\": \"hallo\", \"link\": null, \"audio\": \"audio/hallo.wav\", \"image\": \"https://example.com/hallo.png\" },
+          { \"front\": \"thanks\", \"back\": \"dankie\", \"link\": null, \"audio\": null, \"image\": null }
+        ],
+        \"sets\": [{ \"name\": \"greetings\", \"cards\": [0] }]
+      }",
+    )
+    .unwrap();
+
+    let imported = deck::import(&conn, &config, &exporter, "test_user", exported).unwrap();
+    let (audio, audio_media, image, image_media) = cards::table
+      .inner_join(backs::table)
+      .select((
+        backs::audio,
+        backs::audio_media,
+        backs::image,
+        backs::image_media,
+      ))
+      .filter(cards::deck.eq(imported))
+      .filter(cards::front.eq("hello"))
+      .get_result::<(Option<String>, Option<i32>, Option<String>, Option<i32>)>(&conn)
+      .unwrap();
+    assert!(audio_media.is_some(), "Imported audio is stored as media");
+    assert!(config.path(&audio.unwrap()).unwrap().exists());
+    assert_eq!(image.as_deref(), Some("https://example.com/hallo.png"));
+    assert_eq!(image_media, None);
+
+    let exported = deck::export(&conn, &config, imported).unwrap();
+    assert!(exported.cards[0]
+      .audio
+      .as_ref()
+      .is_some_and(|path| path.starts_with("audio/") && config.root.join(path).exists()));
+    assert_eq!(exported.name, "Everyday words");
+    assert_eq!(exported.licence.as_deref(), Some("CC-BY-4.0"));
+    assert_eq!(
+      exported
+        .cards
+        .iter()
+        .map(|card| (card.front.as_str(), card.back.as_str()))
+        .collect::<Vec<_>>(),
+      vec![("hello", "hallo"), ("thanks", "dankie")]
+    );
+    assert_eq!(exported.sets[0].name, "greetings");
+    assert_eq!(exported.sets[0].cards, vec![0]);
+
+    assert!(deck::import(&conn, &config, &config.root, "nobody", exported).is_err());
+    user::delete(&conn, "test_user").unwrap();
+    fs::remove_dir_all(exporter).unwrap();
+    fs::remove_dir_all(&config.root).unwrap();
+  }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+  use crate::config::{Config, ImageSource, TtsProvider};
+  use std::path::Path;
+
+  #[test]
+  fn test_config() {
+    let config = Config::parse(
+      "
+      [database]
+      url = \"postgres://localhost/total_recall\"
+      pool_size = 4
+
+      [auth]
+      jwt_secret = \"not so secret\"
+
+      [web]
+      root = \"/srv/total_recall/web\"
+
+      [media]
+      root = \"/srv/total_recall/media\"
+      base_url = \"https://media.example.com/\"
+      images = \"none\"
+
+      [media.tts]
+      provider = \"local\"
+
+      [media.tts.voices]
+      af = \"af+f3\"
+
+      [cors]
+      allowed_origins = [\"https://example.com\"]
+      ",
+    )
+    .unwrap();
+    assert_eq!(config.database.pool_size, 4);
+    assert_eq!(config.server.socket, "127.0.0.1:8000");
+    assert_eq!(config.auth.token_days, 30);
+    assert_eq!(config.web.root, Path::new("/srv/total_recall/web"));
+    assert!(config.validate().is_ok());
+    assert_eq!(config.media.images, ImageSource::None);
+    assert_eq!(config.media.tts.voice("af"), "af+f3");
+    assert_eq!(config.media.tts.voice("eo"), "eo");
+
+    let media = &config.media;
+    assert_eq!(
+      media.url("audio/eo/hundo.mp3"),
+      "https://media.example.com/audio/eo/hundo.mp3"
+    );
+    assert_eq!(
+      media.path("https://media.example.com/audio/eo/hundo.mp3"),
+      Some(Path::new("/srv/total_recall/media/audio/eo/hundo.mp3").to_path_buf())
+    );
+    assert_eq!(
+      media.path("images/eo/hundo.jpg"),
+      Some(Path::new("/srv/total_recall/media/images/eo/hundo.jpg").to_path_buf())
+    );
+    assert_eq!(media.path("https://elsewhere.example.com/hundo.jpg"), None);
+    assert_eq!(media.path("images/../../etc/passwd"), None);
+
+    assert!(
+      Config::parse("[database]\nurl = \"x\"\npassword = \"y\"").is_err(),
+      "Unknown keys should be rejected"
+    );
+
+    let mut invalid = config.clone();
+    invalid.database.pool_size = 0;
+    assert!(invalid.validate().is_err());
+
+    let mut invalid = config.clone();
+    invalid.server.socket = "localhost".to_owned();
+    assert!(invalid.validate().is_err());
+
+    for secret in &["", "secret", "change me"] {
+      let mut invalid = config.clone();
+      invalid.auth.jwt_secret = (*secret).to_owned();
+      assert!(invalid.validate().is_err(), "{:?}", secret);
+    }
+
+    let mut invalid = config.clone();
+    invalid.media.base_url = "media.example.com".to_owned();
+    assert!(invalid.validate().is_err());
+
+    let mut invalid = config.clone();
+    invalid.media.tts.provider = TtsProvider::Local;
+    invalid.media.tts.command = vec!["piper".to_owned()];
+    assert!(
+      invalid.validate().is_err(),
+      "Local text to speech needs somewhere to write to"
+    );
+
+    let mut invalid = config;
+    invalid.cors.allowed_origins = vec!["example.com".to_owned()];
+    assert!(invalid.validate().is_err());
+  }
+}
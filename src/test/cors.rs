@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+  use crate::{config::CorsConfig, service::cors::Cors};
+  use actix_web::{
+    http::{header, Method, StatusCode},
+    test::{self, TestRequest},
+    web::post,
+    App, HttpResponse,
+  };
+
+  #[actix_rt::test]
+  async fn test_cors() {
+    let mut app = test::init_service(
+      App::new()
+        .wrap(Cors::new(CorsConfig {
+          allowed_origins: vec!["https://app.example".to_owned()],
+          max_age: 600,
+        }))
+        .route("/graphql", post().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let req = TestRequest::with_uri("/graphql")
+      .method(Method::OPTIONS)
+      .header(header::ORIGIN, "https://app.example")
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+      resp.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(),
+      "600"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .header(header::ORIGIN, "https://app.example")
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      resp
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .unwrap(),
+      "https://app.example"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .header(header::ORIGIN, "https://elsewhere.example")
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp
+      .headers()
+      .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+      .is_none());
+  }
+}
@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::schema::{backs, cards, decks, scores, users},
+    graphql::{authorization::UserRole, query::ScoreValue},
+    service::{endpoints::graphql, jwt::encode_jwt},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use chrono::{TimeZone, Utc};
+  use chrono_tz::Pacific::Kiritimati;
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::{
+    str::from_utf8,
+    time::{SystemTime, UNIX_EPOCH},
+  };
+
+  const DAY: i64 = 24 * 60 * 60 * 1000;
+
+  #[actix_rt::test]
+  async fn test_progress() {
+    let data = init();
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_millis() as i64;
+
+    let (user, new_card, token) = {
+      let conn = data.pool.get().unwrap();
+      let user = diesel::insert_into(users::table)
+        .values((
+          users::username.eq("test_user"),
+          users::password.eq(""),
+          users::created_at.eq(0),
+          users::updated_at.eq(0),
+        ))
+        .returning(users::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let deck = diesel::insert_into(decks::table)
+        .values((
+          decks::name.eq("Bestoj"),
+          decks::owner.eq(user),
+          decks::language.eq(1),
+        ))
+        .returning(decks::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let mut cards = vec![];
+      for (front, back) in &[("dog", "hundo"), ("cat", "kato")] {
+        let back = diesel::insert_into(backs::table)
+          .values((
+            backs::text.eq(back),
+            backs::language.eq(1),
+            backs::updated_at.eq(0),
+          ))
+          .returning(backs::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        cards.push(
+          diesel::insert_into(cards::table)
+            .values((
+              cards::front.eq(front),
+              cards::back.eq(back),
+              cards::deck.eq(deck),
+              cards::created_at.eq(0),
+            ))
+            .returning(cards::id)
+            .get_result::<i32>(&conn)
+            .unwrap(),
+        );
+      }
+
+      // A week in a row long ago, then the last three days
+      let mut reviews = (14..=20)
+        .map(|days| (cards[0], now - days * DAY))
+        .collect::<Vec<_>>();
+      reviews.extend(&[
+        (cards[0], now - 2 * DAY),
+        (cards[0], now - DAY),
+        (cards[0], now),
+      ]);
+      for (card, time) in reviews {
+        diesel::insert_into(scores::table)
+          .values((
+            scores::card.eq(card),
+            scores::value.eq(ScoreValue::FOUR),
+            scores::owner.eq(user),
+            scores::created_at.eq(time),
+            scores::updated_at.eq(time),
+          ))
+          .execute(&conn)
+          .unwrap();
+      }
+      let token = encode_jwt(&data.config.auth.jwt_secret, user, UserRole::USER, 1).unwrap();
+      (user, cards[1], token)
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let query = |query: &str, variables: Value| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({ "query": query, "variables": variables }))
+        .header("Authorization", token.clone())
+        .to_request()
+    };
+
+    let update_user = "mutation Goals($id: Int!, $timezone: String, $reviews: Int, $new: Int) {
+      UpdateUser(UpdateUser: {
+        id: $id,
+        timezone: $timezone,
+        dailyReviewGoal: $reviews,
+        dailyNewCardGoal: $new
+      }) {
+        id
+      }
+    }";
+    let req = query(
+      update_user,
+      json!({ "id": user, "timezone": "Mars/Olympus_Mons" }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Unknown time zones are refused"
+    );
+
+    let req = query(
+      update_user,
+      json!({ "id": user, "timezone": "Pacific/Kiritimati", "reviews": 2, "new": 1 }),
+    );
+    test::call_service(&mut app, req).await;
+    let req = query(
+      "query {
+        progress {
+          timezone
+          dailyReviewGoal
+        }
+      }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["progress"],
+      json!({ "timezone": "Pacific/Kiritimati", "dailyReviewGoal": 2 })
+    );
+
+    // Goals are only visible to their owner, through `progress`
+    let req = query(
+      "query {
+        Users {
+          timezone
+        }
+      }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(result["errors"].is_array());
+
+    // Looking at progress unlocks nothing, scoring does
+    let req = query("query { progress { achievements { kind } } }", json!({}));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["progress"]["achievements"], json!([]));
+
+    let req = query(
+      "mutation CreateScore($card: Int!, $value: ScoreValue!) {
+        CreateScore(NewScore: { card: $card, value: $value }) {
+          id
+        }
+      }",
+      json!({ "card": new_card, "value": "FOUR" }),
+    );
+    test::call_service(&mut app, req).await;
+
+    let progress = "query {
+      progress {
+        today
+        reviewsToday
+        newCardsToday
+        goalMet
+        currentStreak
+        longestStreak
+        achievements {
+          kind
+          unlockedAt
+        }
+      }
+    }";
+    let req = query(progress, json!({}));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let progress = &result["data"]["progress"];
+    let today = Utc.timestamp_millis(now).with_timezone(&Kiritimati);
+    assert_eq!(progress["today"], today.format("%Y-%m-%d").to_string());
+    assert_eq!(progress["reviewsToday"], 2);
+    assert_eq!(progress["newCardsToday"], 1);
+    assert_eq!(progress["goalMet"], true);
+    assert_eq!(progress["currentStreak"], 3);
+    assert_eq!(progress["longestStreak"], 7);
+    let kinds = progress["achievements"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|achievement| achievement["kind"].as_str().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(kinds, ["FIRST_REVIEW", "WEEK_STREAK", "DAILY_GOAL"]);
+    assert!(
+      progress["achievements"][1]["unlockedAt"].as_f64().unwrap() as i64 >= now,
+      "Achievements are unlocked by the review that reached them"
+    );
+
+    let req = query(update_user, json!({ "id": user, "reviews": 3 }));
+    test::call_service(&mut app, req).await;
+    let req = query(
+      "query { progress { goalMet achievements { kind } } }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["progress"]["goalMet"], false);
+    assert_eq!(
+      result["data"]["progress"]["achievements"]
+        .as_array()
+        .unwrap()
+        .len(),
+      3,
+      "Achievements stay unlocked"
+    );
+  }
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::schema::{backs, cards, decks, users},
+    graphql::authorization::UserRole,
+    service::{endpoints::graphql, jwt::encode_jwt},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  #[actix_rt::test]
+  async fn test_check_answer() {
+    let data = init();
+
+    let (card, tokens) = {
+      let conn = data.pool.get().unwrap();
+      let mut users = vec![];
+      for username in &["owner", "stranger"] {
+        users.push(
+          diesel::insert_into(users::table)
+            .values((
+              users::username.eq(username),
+              users::password.eq(""),
+              users::created_at.eq(0),
+              users::updated_at.eq(0),
+            ))
+            .returning(users::id)
+            .get_result::<i32>(&conn)
+            .unwrap(),
+        );
+      }
+      let deck = diesel::insert_into(decks::table)
+        .values((
+          decks::name.eq("Wörter"),
+          decks::owner.eq(users[0]),
+          decks::language.eq(1),
+        ))
+        .returning(decks::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let back = diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq("der Schäferhund"),
+          backs::language.eq(1),
+          backs::updated_at.eq(0),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let card = diesel::insert_into(cards::table)
+        .values((
+          cards::front.eq("the German shepherd"),
+          cards::back.eq(back),
+          cards::deck.eq(deck),
+          cards::created_at.eq(0),
+        ))
+        .returning(cards::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let secret = &data.config.auth.jwt_secret;
+      let tokens = users
+        .iter()
+        .map(|user| encode_jwt(secret, *user, UserRole::USER, 1).unwrap())
+        .collect::<Vec<_>>();
+      (card, tokens)
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let check = |token: &str, typed: &str| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({
+          "query": "mutation Check($card: Int!, $typed: String!) {
+            checkAnswer(card: $card, typed: $typed) {
+              correct
+              distance
+              score
+              diff {
+                kind
+                text
+              }
+            }
+          }",
+          "variables": { "card": card, "typed": typed },
+        }))
+        .header("Authorization", token.to_owned())
+        .to_request()
+    };
+
+    let req = check(&tokens[1], "der Schäferhund");
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(refused["errors"][0]["message"], "Unauthorized");
+
+    for (typed, correct, score) in &[
+      // Decomposed, with extra whitespace
+      ("  der  Scha\u{308}ferhund ", true, "FIVE"),
+      ("Der Schaferhund!", true, "FOUR"),
+      ("der Schäfrehund", true, "THREE"),
+      ("der Schäferhunde", true, "THREE"),
+      ("der Hund", false, "TWO"),
+      ("die Katze", false, "ONE"),
+      ("", false, "ZERO"),
+    ] {
+      let req = check(&tokens[0], typed);
+      let resp = test::call_service(&mut app, req).await;
+      let body = test::read_body(resp).await;
+      let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+      let checked = &result["data"]["checkAnswer"];
+      assert_eq!(checked["correct"], *correct, "{}", typed);
+      assert_eq!(checked["score"], *score, "{}", typed);
+    }
+
+    let req = check(&tokens[0], "der Schaferhunt");
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["checkAnswer"]["distance"], 1);
+    assert_eq!(
+      result["data"]["checkAnswer"]["diff"],
+      json!([
+        { "kind": "SAME", "text": "der Sch" },
+        { "kind": "EXTRA", "text": "a" },
+        { "kind": "MISSING", "text": "ä" },
+        { "kind": "SAME", "text": "ferhun" },
+        { "kind": "EXTRA", "text": "t" },
+        { "kind": "MISSING", "text": "d" },
+      ])
+    );
+  }
+}
@@ -0,0 +1,443 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::schema::{backs, cards, decks, scores, set_cards, sets, users},
+    graphql::{authorization::UserRole, query::ScoreValue},
+    service::{endpoints::graphql, jwt::encode_jwt},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::{
+    str::from_utf8,
+    time::{SystemTime, UNIX_EPOCH},
+  };
+
+  const DAY: i64 = 24 * 60 * 60 * 1000;
+
+  #[actix_rt::test]
+  async fn test_groups() {
+    let data = init();
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_millis() as i64;
+
+    let (users, deck, cards, set, tokens) = {
+      let conn = data.pool.get().unwrap();
+      let mut users = vec![];
+      let mut tokens = vec![];
+      for username in &["test_teacher", "test_ana", "test_bob", "test_eve"] {
+        let user = diesel::insert_into(users::table)
+          .values((
+            users::username.eq(username),
+            users::password.eq(""),
+            users::created_at.eq(0),
+            users::updated_at.eq(0),
+          ))
+          .returning(users::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        users.push(user);
+        tokens.push(encode_jwt(&data.config.auth.jwt_secret, user, UserRole::USER, 1).unwrap());
+      }
+      let deck = diesel::insert_into(decks::table)
+        .values((
+          decks::name.eq("Bestoj"),
+          decks::owner.eq(users[0]),
+          decks::language.eq(1),
+        ))
+        .returning(decks::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let mut cards = vec![];
+      for (front, back) in &[
+        ("dog", "hundo"),
+        ("cat", "kato"),
+        ("bird", "birdo"),
+        ("cow", "bovino"),
+      ] {
+        let back = diesel::insert_into(backs::table)
+          .values((
+            backs::text.eq(back),
+            backs::language.eq(1),
+            backs::updated_at.eq(0),
+          ))
+          .returning(backs::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        cards.push(
+          diesel::insert_into(cards::table)
+            .values((
+              cards::front.eq(front),
+              cards::back.eq(back),
+              cards::deck.eq(deck),
+              cards::created_at.eq(0),
+            ))
+            .returning(cards::id)
+            .get_result::<i32>(&conn)
+            .unwrap(),
+        );
+      }
+      let set = diesel::insert_into(sets::table)
+        .values((
+          sets::name.eq("Pets"),
+          sets::deck.eq(deck),
+          sets::owner.eq(users[0]),
+          sets::created_at.eq(0),
+        ))
+        .returning(sets::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      for card in &cards[..2] {
+        diesel::insert_into(set_cards::table)
+          .values((set_cards::card_id.eq(card), set_cards::set_id.eq(set)))
+          .execute(&conn)
+          .unwrap();
+      }
+      (users, deck, cards, set, tokens)
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let query = |token: &str, query: &str, variables: Value| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({ "query": query, "variables": variables }))
+        .header("Authorization", token.to_owned())
+        .to_request()
+    };
+
+    let req = query(
+      &tokens[0],
+      "mutation {
+        createGroup(name: \"Esperanto 101\") {
+          id
+          role
+        }
+      }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["createGroup"]["role"], "TEACHER");
+    let group = result["data"]["createGroup"]["id"].as_i64().unwrap();
+
+    let invite = "mutation Invite($group: Int!, $username: String!) {
+      inviteToGroup(group: $group, username: $username, role: STUDENT) {
+        username
+        accepted
+      }
+    }";
+    let req = query(
+      &tokens[1],
+      invite,
+      json!({ "group": group, "username": "test_eve" }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(refused["errors"][0]["message"], "Unauthorized");
+
+    for username in &["test_ana", "test_bob"] {
+      let req = query(
+        &tokens[0],
+        invite,
+        json!({ "group": group, "username": username }),
+      );
+      let resp = test::call_service(&mut app, req).await;
+      let body = test::read_body(resp).await;
+      let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+      assert_eq!(
+        result["data"]["inviteToGroup"],
+        json!({ "username": username, "accepted": false })
+      );
+    }
+
+    let req = query(
+      &tokens[1],
+      "query {
+        groupInvitations {
+          groupName
+        }
+      }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["groupInvitations"],
+      json!([{ "groupName": "Esperanto 101" }])
+    );
+
+    let req = query(
+      &tokens[1],
+      "mutation Accept($group: Int!) {
+        acceptGroupInvitation(group: $group)
+      }",
+      json!({ "group": group }),
+    );
+    test::call_service(&mut app, req).await;
+
+    let check = "mutation Check($card: Int!) {
+      checkAnswer(card: $card, typed: \"hundo\") {
+        correct
+      }
+    }";
+    let req = query(&tokens[1], check, json!({ "card": cards[0] }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Nothing is assigned to the group yet"
+    );
+
+    let assign = "mutation Assign($group: Int!, $deck: Int, $set: Int) {
+      assignToGroup(group: $group, deck: $deck, set: $set) {
+        id
+        deck
+        deckName
+        setId
+      }
+    }";
+    let req = query(&tokens[0], assign, json!({ "group": group, "set": set }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let assignment = &result["data"]["assignToGroup"];
+    assert_eq!(assignment["deck"], deck);
+    assert_eq!(assignment["deckName"], "Bestoj");
+    assert_eq!(assignment["setId"], set);
+    let set_assignment = assignment["id"].as_i64().unwrap();
+
+    let req = query(&tokens[0], assign, json!({ "group": group, "deck": deck }));
+    test::call_service(&mut app, req).await;
+
+    let req = query(&tokens[1], check, json!({ "card": cards[0] }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["checkAnswer"]["correct"], true);
+
+    for token in &[&tokens[2], &tokens[3]] {
+      let req = query(token, check, json!({ "card": cards[0] }));
+      let resp = test::call_service(&mut app, req).await;
+      let body = test::read_body(resp).await;
+      let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+      assert!(
+        result["errors"].is_array(),
+        "Only members who accepted see assigned decks"
+      );
+    }
+
+    let req = query(
+      &tokens[1],
+      "mutation Edit($card: Int!) {
+        UpdateCard(UpdateCard: { id: $card, front: \"wolf\" }) {
+          front
+        }
+      }",
+      json!({ "card": cards[0] }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Students can't edit assigned decks"
+    );
+
+    {
+      let conn = data.pool.get().unwrap();
+      for (student, card, value, time) in &[
+        (users[1], cards[0], ScoreValue::THREE, now - 40 * DAY),
+        (users[1], cards[0], ScoreValue::FIVE, now - DAY / 2),
+        (users[1], cards[1], ScoreValue::ONE, now),
+        (users[1], cards[2], ScoreValue::FOUR, now - 10 * DAY),
+        (users[3], cards[3], ScoreValue::FIVE, now),
+      ] {
+        diesel::insert_into(scores::table)
+          .values((
+            scores::card.eq(card),
+            scores::value.eq(value),
+            scores::created_at.eq(time),
+            scores::updated_at.eq(time),
+            scores::owner.eq(student),
+          ))
+          .execute(&conn)
+          .unwrap();
+      }
+    }
+
+    let progress = "query Progress($group: Int!) {
+      groupProgress(group: $group) {
+        username
+        assignedCards
+        studiedCards
+        learnedCards
+        retention
+        overdueCards
+        lastReview
+      }
+    }";
+    let req = query(&tokens[1], progress, json!({ "group": group }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(refused["errors"][0]["message"], "Unauthorized");
+
+    let req = query(&tokens[0], progress, json!({ "group": group }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["groupProgress"],
+      json!([{
+        "username": "test_ana",
+        "assignedCards": 4,
+        "studiedCards": 3,
+        "learnedCards": 2,
+        "retention": 2.0 / 3.0,
+        "overdueCards": 1,
+        "lastReview": now,
+      }])
+    );
+
+    let req = query(
+      &tokens[0],
+      "mutation Unassign($assignment: Int!, $group: Int!, $user: Int!) {
+        unassignFromGroup(assignment: $assignment)
+        removeGroupMember(group: $group, user: $user)
+      }",
+      json!({ "assignment": set_assignment, "group": group, "user": users[1] }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"],
+      json!({ "unassignFromGroup": true, "removeGroupMember": true })
+    );
+
+    let req = query(&tokens[1], check, json!({ "card": cards[0] }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Removed members lose access to assigned decks"
+    );
+
+    let req = query(
+      &tokens[0],
+      "query Members($group: Int!) {
+        groupMembers(group: $group) {
+          username
+          role
+        }
+        groupAssignments(group: $group) {
+          setId
+        }
+      }",
+      json!({ "group": group }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"],
+      json!({
+        "groupMembers": [
+          { "username": "test_teacher", "role": "TEACHER" },
+          { "username": "test_bob", "role": "STUDENT" },
+        ],
+        "groupAssignments": [{ "setId": null }],
+      })
+    );
+
+    for (token, username) in &[(&tokens[1], "test_ana"), (&tokens[3], "test_eve")] {
+      let req = query(
+        &tokens[0],
+        "mutation Invite($group: Int!, $username: String!) {
+          inviteToGroup(group: $group, username: $username, role: TEACHER) {
+            accepted
+          }
+        }",
+        json!({ "group": group, "username": username }),
+      );
+      test::call_service(&mut app, req).await;
+      let req = query(
+        token,
+        "mutation Accept($group: Int!) {
+          acceptGroupInvitation(group: $group)
+        }",
+        json!({ "group": group }),
+      );
+      test::call_service(&mut app, req).await;
+    }
+    let remove = "mutation Remove($group: Int!, $user: Int!) {
+      removeGroupMember(group: $group, user: $user)
+    }";
+    let req = query(
+      &tokens[3],
+      remove,
+      json!({ "group": group, "user": users[1] }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      refused["errors"][0]["message"], "Unauthorized",
+      "Only the owner removes teachers"
+    );
+    let req = query(
+      &tokens[0],
+      remove,
+      json!({ "group": group, "user": users[1] }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["removeGroupMember"], true);
+
+    let req = query(
+      &tokens[0],
+      "query {
+        groups: auditLog(entity: GROUP) {
+          operation
+        }
+        assignments: auditLog(entity: GROUP_ASSIGNMENT) {
+          operation
+        }
+      }",
+      json!({}),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"],
+      json!({
+        "groups": [{ "operation": "INSERT" }],
+        "assignments": [
+          { "operation": "DELETE" },
+          { "operation": "INSERT" },
+          { "operation": "INSERT" },
+        ],
+      })
+    );
+  }
+}
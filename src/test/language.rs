@@ -0,0 +1,384 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    config::{Config, TtsProvider},
+    db::schema::{backs, cards, languages, users},
+    graphql::authorization::UserRole,
+    service::{
+      endpoints::{graphql, login},
+      jwt::encode_jwt,
+      AppState,
+    },
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::{env, fs, str::from_utf8, sync::Arc};
+
+  use crate::test::LoginResponse;
+
+  #[actix_rt::test]
+  async fn test_front_language() {
+    let root = env::temp_dir().join(format!("total_recall_language_{}", std::process::id()));
+    let mut config = Config::default();
+    config.media.root = root.clone();
+    config.media.tts.provider = TtsProvider::Local;
+    config.media.tts.command = vec![
+      "sh".to_owned(),
+      "-c".to_owned(),
+      "printf 'RIFF\\0\\0\\0\\0WAVE' > \"$1\"; cat >> \"$1\"".to_owned(),
+      "sh".to_owned(),
+      "{output}".to_owned(),
+    ];
+    let data = AppState {
+      config: Arc::new(config),
+      ..init()
+    };
+
+    let (english, afrikaans) = {
+      let conn = data.pool.get().unwrap();
+      let id = |abbreviation: &str| {
+        languages::table
+          .select(languages::id)
+          .filter(languages::abbreviation.eq(abbreviation))
+          .get_result::<i32>(&conn)
+          .unwrap()
+      };
+      (id("en"), id("af"))
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation {
+          CreateUser(NewUser: { username: \"test_user\", password: \"test\" }) {
+            id
+          }
+        }",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let created: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let user_id = created["data"]["CreateUser"]["id"].as_i64().unwrap();
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let login_response: LoginResponse = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let token = login_response.token;
+
+    let query = |query: &str, variables: Value| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({ "query": query, "variables": variables }))
+        .header("Authorization", token.clone())
+        .to_request()
+    };
+
+    let req = query(
+      "mutation Deck($language: Int!) {
+        CreateDeck(NewDeck: { name: \"Woorde\", language: $language }) {
+          id
+        }
+      }",
+      json!({ "language": afrikaans }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let deck: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let deck_id = deck["data"]["CreateDeck"]["id"].as_i64().unwrap() as i32;
+
+    // Creating cards through the API fetches an image, so this goes in directly
+    let card_id = {
+      let conn = data.pool.get().unwrap();
+      let back = diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq("hond"),
+          backs::language.eq(afrikaans),
+          backs::updated_at.eq(0),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      diesel::insert_into(cards::table)
+        .values((
+          cards::front.eq("dog"),
+          cards::back.eq(back),
+          cards::deck.eq(deck_id),
+          cards::created_at.eq(0),
+        ))
+        .returning(cards::id)
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+
+    let update_card = "mutation Update($id: Int!, $front: String, $audio: Boolean) {
+      UpdateCard(UpdateCard: { id: $id, front: $front, frontAudio: $audio }) {
+        front_language {
+          abbreviation
+        }
+        front_audio
+      }
+    }";
+    let req = query(update_card, json!({ "id": card_id, "audio": true }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Front audio needs the language of the front"
+    );
+
+    let req = query(
+      "mutation Native($id: Int!, $language: Int!) {
+        UpdateUser(UpdateUser: { id: $id, nativeLanguage: $language }) {
+          native_language {
+            abbreviation
+          }
+        }
+      }",
+      json!({ "id": user_id, "language": english }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["UpdateUser"]["native_language"]["abbreviation"],
+      "en"
+    );
+
+    let req = query(update_card, json!({ "id": card_id, "audio": true }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let card = &result["data"]["UpdateCard"];
+    assert_eq!(card["front_language"]["abbreviation"], "en");
+    let audio = card["front_audio"].as_str().unwrap().to_owned();
+    let file = data.config.media.path(&audio).unwrap();
+    assert!(fs::read(file).unwrap().ends_with(b"dog"));
+
+    let req = query(update_card, json!({ "id": card_id, "front": "hound" }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let audio = result["data"]["UpdateCard"]["front_audio"]
+      .as_str()
+      .unwrap();
+    let file = data.config.media.path(audio).unwrap();
+    assert!(
+      fs::read(file).unwrap().ends_with(b"hound"),
+      "Audio follows the front"
+    );
+
+    let req = query(update_card, json!({ "id": card_id, "audio": false }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["UpdateCard"]["front_audio"], Value::Null);
+
+    let req = query(
+      "mutation Pair($id: Int!, $language: Int!) {
+        UpdateDeck(UpdateDeck: { id: $id, name: \"Woorde\", frontLanguage: $language }) {
+          front_language {
+            abbreviation
+          }
+        }
+      }",
+      json!({ "id": deck_id, "language": afrikaans }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["UpdateDeck"]["front_language"]["abbreviation"],
+      "af"
+    );
+
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[actix_rt::test]
+  async fn test_manage_languages() {
+    let data = init();
+
+    let tokens = {
+      let conn = data.pool.get().unwrap();
+      let mut tokens = vec![];
+      for (username, role) in &[
+        ("test_user", UserRole::USER),
+        ("test_admin", UserRole::ADMIN),
+      ] {
+        let user = diesel::insert_into(users::table)
+          .values((
+            users::username.eq(username),
+            users::password.eq(""),
+            users::role.eq(role),
+            users::created_at.eq(0),
+            users::updated_at.eq(0),
+          ))
+          .returning(users::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        tokens.push(encode_jwt(&data.config.auth.jwt_secret, user, *role, 1).unwrap());
+      }
+      tokens
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let query = |token: &str, query: &str, variables: Value| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({ "query": query, "variables": variables }))
+        .header("Authorization", token.to_owned())
+        .to_request()
+    };
+
+    let add_language = "mutation Add($tag: String, $script: String) {
+      addLanguage(name: \"Klingon\", abbreviation: \"tlh\", tag: $tag, script: $script, direction: LTR)
+    }";
+    let req = query(&tokens[0], add_language, json!({}));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(refused["errors"][0]["message"], "Unauthorized");
+
+    for (tag, script) in &[("klingon language", "Piqd"), ("tlh", "pIqaD")] {
+      let req = query(
+        &tokens[1],
+        add_language,
+        json!({ "tag": tag, "script": script }),
+      );
+      let resp = test::call_service(&mut app, req).await;
+      let body = test::read_body(resp).await;
+      let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+      assert!(result["errors"].is_array(), "{} {}", tag, script);
+    }
+
+    let req = query(&tokens[1], add_language, json!({ "script": "piqd" }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let klingon = result["data"]["addLanguage"].as_i64().unwrap();
+
+    let req = query(
+      &tokens[1],
+      "mutation Details($id: Int!) {
+        setLanguageDetails(id: $id, tag: \"tlh-Piqd\", ttsVoice: \"klingon\")
+        unchanged: setLanguageDetails(id: $id)
+        disableLanguage(id: $id, disabled: true)
+      }",
+      json!({ "id": klingon }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"],
+      json!({ "setLanguageDetails": true, "unchanged": false, "disableLanguage": true })
+    );
+
+    let req = query(
+      &tokens[0],
+      "query Language($id: Int!) {
+        Language(primaryKey: { id: $id }) {
+          tag
+          script
+          direction
+          tts_voice
+          disabled
+        }
+      }",
+      json!({ "id": klingon }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["Language"],
+      json!({
+        "tag": "tlh-Piqd",
+        "script": "Piqd",
+        "direction": "LTR",
+        "tts_voice": "klingon",
+        "disabled": true,
+      })
+    );
+
+    let create_deck = "mutation Deck($language: Int!) {
+      CreateDeck(NewDeck: { name: \"Mu'mey\", language: $language }) {
+        id
+      }
+    }";
+    let req = query(&tokens[0], create_deck, json!({ "language": klingon }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      result["errors"].is_array(),
+      "Disabled languages can't be picked for decks"
+    );
+
+    let req = query(
+      &tokens[1],
+      "mutation Enable($id: Int!) {
+        disableLanguage(id: $id, disabled: false)
+      }",
+      json!({ "id": klingon }),
+    );
+    test::call_service(&mut app, req).await;
+
+    let req = query(&tokens[0], create_deck, json!({ "language": klingon }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(result["data"]["CreateDeck"]["id"].is_number());
+
+    let req = query(
+      &tokens[1],
+      "query Audit($id: Int!) {
+        auditLog(entity: LANGUAGE, entityId: $id) {
+          operation
+        }
+      }",
+      json!({ "id": klingon }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["auditLog"],
+      json!([
+        { "operation": "UPDATE" },
+        { "operation": "UPDATE" },
+        { "operation": "UPDATE" },
+        { "operation": "INSERT" },
+      ])
+    );
+  }
+}
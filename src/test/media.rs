@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    cli::media::rehash,
+    config::MediaConfig,
+    db::schema::{backs, cards, decks, users},
+    media::{self, MediaRow, NewMedia},
+    test::init,
+  };
+  use diesel::prelude::*;
+  use image::{DynamicImage, ImageFormat};
+  use std::{
+    env,
+    fs::{self, File},
+    io::Cursor,
+    time::{Duration, SystemTime},
+  };
+
+  fn new_media(bytes: &[u8], mime: &str) -> NewMedia {
+    NewMedia {
+      bytes: bytes.to_vec(),
+      mime: mime.to_owned(),
+      source: Some("https://example.com/hundo".to_owned()),
+      licence: Some("CC0".to_owned()),
+    }
+  }
+
+  #[test]
+  fn test_media() {
+    let data = init();
+    let conn = data.pool.get().unwrap();
+    let root = env::temp_dir().join(format!("total_recall_media_{}", std::process::id()));
+    let config = MediaConfig {
+      root: root.clone(),
+      base_url: "/media".to_owned(),
+      ..MediaConfig::default()
+    };
+
+    let stored = media::store(&conn, &config, new_media(b"woof", "audio/mpeg"), 0).unwrap();
+    assert_eq!(stored.hash, media::hash(b"woof"));
+    assert_eq!(stored.size, 4);
+    assert_eq!(
+      stored.path(),
+      format!("audio/{}/{}.mp3", &stored.hash[..2], stored.hash)
+    );
+    assert_eq!(fs::read(root.join(stored.path())).unwrap(), b"woof");
+
+    let again = media::store(&conn, &config, new_media(b"woof", "audio/mpeg"), 1).unwrap();
+    assert_eq!(
+      again.id, stored.id,
+      "Identical content should be stored once"
+    );
+    assert_eq!(MediaRow::find(&conn, stored.id).unwrap().created_at, 0);
+
+    let other = media::store(&conn, &config, new_media(b"meow", "image/png"), 0).unwrap();
+    assert_ne!(other.id, stored.id);
+    assert!(other.path().starts_with("images/"));
+
+    // A file stored by name before media was content addressed
+    fs::create_dir_all(root.join("audio/eo")).unwrap();
+    fs::write(root.join("audio/eo/hundo.mp3"), b"woof").unwrap();
+    let back = diesel::insert_into(backs::table)
+      .values((
+        backs::text.eq("hundo"),
+        backs::language.eq(1),
+        backs::audio.eq("audio/eo/hundo.mp3"),
+        backs::updated_at.eq(0),
+      ))
+      .returning(backs::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    assert_eq!(rehash(&conn, &config).unwrap(), 1);
+    let (audio, audio_media) = backs::table
+      .select((backs::audio, backs::audio_media))
+      .filter(backs::id.eq(back))
+      .get_result::<(Option<String>, Option<i32>)>(&conn)
+      .unwrap();
+    assert_eq!(audio_media, Some(stored.id));
+    assert_eq!(audio, Some(config.url(&stored.path())));
+    assert_eq!(rehash(&conn, &config).unwrap(), 0);
+
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn test_media_gc() {
+    let data = init();
+    let conn = data.pool.get().unwrap();
+    let root = env::temp_dir().join(format!("total_recall_gc_{}", std::process::id()));
+    let config = MediaConfig {
+      root: root.clone(),
+      ..MediaConfig::default()
+    };
+
+    let user = diesel::insert_into(users::table)
+      .values((
+        users::username.eq("gc_owner"),
+        users::password.eq(""),
+        users::created_at.eq(0),
+        users::updated_at.eq(0),
+      ))
+      .returning(users::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let deck = diesel::insert_into(decks::table)
+      .values((
+        decks::name.eq("Bestoj"),
+        decks::owner.eq(user),
+        decks::language.eq(1),
+      ))
+      .returning(decks::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+
+    let used = media::store(&conn, &config, new_media(b"woof", "audio/mpeg"), 0).unwrap();
+    let orphaned = media::store(&conn, &config, new_media(b"meow", "audio/mpeg"), 0).unwrap();
+    let insert_back = |media: i32| {
+      diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq("besto"),
+          backs::language.eq(1),
+          backs::audio_media.eq(media),
+          backs::updated_at.eq(0),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+    let back = insert_back(used.id);
+    insert_back(orphaned.id);
+
+    let mut png = Cursor::new(vec![]);
+    DynamicImage::new_rgb8(800, 600)
+      .write_to(&mut png, ImageFormat::Png)
+      .unwrap();
+    let picture =
+      media::store_image(&conn, &config, new_media(&png.into_inner(), "image/png"), 0).unwrap();
+    assert_eq!(picture.image.mime, "image/webp");
+    assert_eq!(picture.variants.len(), 2);
+    diesel::update(backs::table.filter(backs::id.eq(back)))
+      .set(backs::image_media.eq(picture.image.id))
+      .execute(&conn)
+      .unwrap();
+    diesel::insert_into(cards::table)
+      .values((
+        cards::front.eq("animal"),
+        cards::back.eq(back),
+        cards::deck.eq(deck),
+        cards::created_at.eq(0),
+      ))
+      .execute(&conn)
+      .unwrap();
+
+    // Stray files are only collected once they are old enough, and only if
+    // they are named after their hash like the files stored here
+    let stray = root.join(media::file_path(&"ab".repeat(32), "image/jpeg"));
+    let recent = root.join(media::file_path(&"cd".repeat(32), "image/jpeg"));
+    let foreign = root.join("images/eo/besto.jpg");
+    fs::create_dir_all(root.join("images/eo")).unwrap();
+    for (path, bytes) in &[(&stray, b"old"), (&recent, b"new"), (&foreign, b"web")] {
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, bytes).unwrap();
+    }
+    for path in &[&stray, &foreign] {
+      File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(24 * 60 * 60))
+        .unwrap();
+    }
+
+    let report = media::gc(&conn, &config, true).unwrap();
+    assert_eq!(report.rows, 1);
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(report.bytes, 7);
+    assert!(
+      root.join(orphaned.path()).exists(),
+      "Dry runs remove nothing"
+    );
+
+    let report = media::gc(&conn, &config, false).unwrap();
+    assert_eq!(report.rows, 1);
+    assert_eq!(report.bytes, 7);
+    assert!(!root.join(orphaned.path()).exists());
+    assert!(!stray.exists());
+    assert!(recent.exists());
+    assert!(foreign.exists(), "Files not stored by hash are left alone");
+    assert!(root.join(used.path()).exists());
+    assert!(MediaRow::find(&conn, orphaned.id).is_err());
+    assert!(MediaRow::find(&conn, used.id).is_ok());
+    for (_, variant) in &picture.variants {
+      assert!(
+        root.join(variant.path()).exists(),
+        "Variants of used images are kept"
+      );
+    }
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}
@@ -0,0 +1,296 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    service::endpoints::{graphql, login},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  #[actix_rt::test]
+  async fn test_members() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_student",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create student");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_student",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Student login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let student_login: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_deck_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Invite($deck: Int!) {
+          inviteMember(deck: $deck, username: \"test_student\", role: VIEWER) {
+            username
+            role
+            accepted
+          }
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", student_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      refused["errors"].is_array(),
+      "Only the owner should be able to invite"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Invite($deck: Int!) {
+          inviteMember(deck: $deck, username: \"test_student\", role: VIEWER) {
+            username
+            role
+            accepted
+          }
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"inviteMember\":{\"username\":\"test_student\",\"role\":\"VIEWER\",\"accepted\":false}}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateSet($deck: Int!) {
+          CreateSet(NewSet: { name: \"study\", deck: $deck, cards: [] }) {
+            id
+          }
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", student_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      refused["data"]["CreateSet"],
+      Value::Null,
+      "Pending invitations should not grant access"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Accept($deck: Int!) {
+          acceptInvitation(deck: $deck)
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", student_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"acceptInvitation\":true}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateSet($deck: Int!) {
+          CreateSet(NewSet: { name: \"study\", deck: $deck, cards: [] }) {
+            name
+          }
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", student_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"CreateSet\":{\"name\":\"study\"}}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation UpdateDeck($id: Int!) {
+          UpdateDeck(UpdateDeck: { name: \"taken\", id: $id }) {
+            name
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", student_login.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let refused: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      refused["data"]["UpdateDeck"],
+      Value::Null,
+      "Viewers should not be able to change the deck"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Members($deck: Int!) {
+          deckMembers(deck: $deck) {
+            username
+            accepted
+          }
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"deckMembers\":[{\"username\":\"test_student\",\"accepted\":true}]}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          auditLog(entity: DECK_MEMBER) {
+            operation
+          }
+        }",
+      }))
+      .header("Authorization", login_response.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"auditLog\":[{\"operation\":\"UPDATE\"},{\"operation\":\"INSERT\"}]}}",
+      "Invitations and their answers are audited for the owner"
+    );
+  }
+}
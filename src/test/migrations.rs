@@ -0,0 +1,19 @@
+#[cfg(test)]
+mod tests {
+  use crate::db::migrations;
+  use std::fs;
+
+  #[test]
+  fn test_embedded_migrations() {
+    let mut directories = fs::read_dir("migrations")
+      .unwrap()
+      .map(|entry| entry.unwrap())
+      .filter(|entry| entry.path().is_dir())
+      .map(|entry| entry.file_name().into_string().unwrap())
+      .map(|name| name.split('_').next().unwrap().replace('-', ""))
+      .collect::<Vec<_>>();
+    directories.sort();
+
+    assert_eq!(migrations::embedded(), directories);
+  }
+}
@@ -1,21 +1,39 @@
-use diesel::{
-  prelude::*,
-  r2d2::{ConnectionManager, Pool},
-};
+use diesel::prelude::*;
 use dotenv::dotenv;
 use serde::Deserialize;
 use std::{env, sync::Arc};
 
 use crate::{
-  db::DBConnection,
-  graphql::{mutations::Mutation, query::Query, GQLContext, Schema},
+  config::{Config, ImageSource, TtsProvider},
+  db::connect,
+  graphql::create_schema,
   service::AppState,
 };
 
+mod admin;
+mod audit;
 mod card;
+mod catalogue;
+mod cli;
+mod config;
+mod cors;
 mod deck;
+mod goals;
+mod grading;
+mod groups;
+mod language;
+mod media;
+mod members;
+mod migrations;
+mod quiz;
 mod score;
+mod serve;
 mod set;
+mod sync;
+mod trash;
+mod tts;
+mod updates;
+mod upload;
 mod user;
 
 #[derive(Deserialize)]
@@ -92,21 +110,32 @@ struct CreateScoreResponse {
 pub fn init() -> AppState {
   dotenv().ok();
   let db_url = env::var("DATABASE_URL").expect("Database url not set");
-  let manager = ConnectionManager::<DBConnection>::new(db_url);
-  let pool = Pool::builder()
-    .max_size(1)
-    .build(manager)
-    .expect("Failed to initialize connection pool");
+  let pool = connect(&db_url, Some(1)).expect("Failed to initialize connection pool");
   let conn = pool.get().expect("Failed to get db connection");
   conn
     .begin_test_transaction()
     .expect("Failed to start transaction");
 
-  let query = Query::<GQLContext<DBConnection>>::default();
-  let mutation = Mutation::<GQLContext<DBConnection>>::default();
-  let schema = Schema::new(query, mutation);
+  let schema = create_schema();
 
   let schema = Arc::new(schema);
   let pool = Arc::new(pool);
-  AppState { schema, pool }
+  // Cards get silent audio and no picture, so tests don't need the network
+  let mut config = Config::default();
+  config.media.root = env::temp_dir().join("total_recall_test_media");
+  config.media.images = ImageSource::None;
+  config.media.tts.provider = TtsProvider::Local;
+  config.media.tts.command = vec![
+    "sh".to_owned(),
+    "-c".to_owned(),
+    "printf 'RIFF\\0\\0\\0\\0WAVE' > \"$1\"; cat >> \"$1\"".to_owned(),
+    "sh".to_owned(),
+    "{output}".to_owned(),
+  ];
+  let config = Arc::new(config);
+  AppState {
+    schema,
+    pool,
+    config,
+  }
 }
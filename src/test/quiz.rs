@@ -0,0 +1,228 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::schema::{backs, cards, decks, scores, set_cards, sets, users},
+    graphql::authorization::UserRole,
+    service::{endpoints::graphql, jwt::encode_jwt},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  const WORDS: [(&str, &str); 6] = [
+    ("dog", "hundo"),
+    ("cat", "kato"),
+    ("bird", "birdo"),
+    ("horse", "ĉevalo"),
+    ("cow", "bovino"),
+    ("elephant", "elefanto"),
+  ];
+
+  #[actix_rt::test]
+  async fn test_quiz() {
+    let data = init();
+
+    let (cards, set, token) = {
+      let conn = data.pool.get().unwrap();
+      let user = diesel::insert_into(users::table)
+        .values((
+          users::username.eq("test_user"),
+          users::password.eq(""),
+          users::created_at.eq(0),
+          users::updated_at.eq(0),
+        ))
+        .returning(users::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let deck = diesel::insert_into(decks::table)
+        .values((
+          decks::name.eq("Bestoj"),
+          decks::owner.eq(user),
+          decks::language.eq(1),
+        ))
+        .returning(decks::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let mut cards = vec![];
+      for (front, back) in WORDS.iter() {
+        let back = diesel::insert_into(backs::table)
+          .values((
+            backs::text.eq(back),
+            backs::language.eq(1),
+            backs::updated_at.eq(0),
+          ))
+          .returning(backs::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        cards.push(
+          diesel::insert_into(cards::table)
+            .values((
+              cards::front.eq(front),
+              cards::back.eq(back),
+              cards::deck.eq(deck),
+              cards::created_at.eq(0),
+            ))
+            .returning(cards::id)
+            .get_result::<i32>(&conn)
+            .unwrap(),
+        );
+      }
+      let set = diesel::insert_into(sets::table)
+        .values((
+          sets::name.eq("Pets"),
+          sets::deck.eq(deck),
+          sets::owner.eq(user),
+          sets::created_at.eq(0),
+        ))
+        .returning(sets::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      for card in &cards[..3] {
+        diesel::insert_into(set_cards::table)
+          .values((set_cards::card_id.eq(card), set_cards::set_id.eq(set)))
+          .execute(&conn)
+          .unwrap();
+      }
+      let token = encode_jwt(&data.config.auth.jwt_secret, user, UserRole::USER, 1).unwrap();
+      (cards, set, token)
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let query = |query: &str, variables: Value| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({ "query": query, "variables": variables }))
+        .header("Authorization", token.clone())
+        .to_request()
+    };
+
+    let quiz = "query Quiz($set: Int, $size: Int, $choices: Int) {
+      quiz(set: $set, size: $size, choices: $choices) {
+        card
+        front
+        choices
+      }
+    }";
+    let req = query(quiz, json!({ "set": set, "choices": 3 }));
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let questions = result["data"]["quiz"].as_array().unwrap();
+    assert_eq!(questions.len(), 3, "Only cards of the set are asked");
+    for question in questions {
+      let (_, back) = WORDS
+        .iter()
+        .find(|(front, _)| question["front"] == *front)
+        .unwrap();
+      let mut choices = question["choices"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|choice| choice.as_str().unwrap())
+        .collect::<Vec<_>>();
+      assert!(choices.contains(back));
+      choices.sort_unstable();
+      let mut pets = vec!["birdo", "hundo", "kato"];
+      pets.sort_unstable();
+      assert_eq!(choices, pets, "Other cards of the set come first");
+    }
+
+    let req = query(
+      "mutation Submit($answers: [QuizAnswer!]!) {
+        submitQuiz(answers: $answers) {
+          card
+          correct
+          expected
+          score
+        }
+      }",
+      json!({
+        "answers": [
+          { "card": cards[0], "answer": "hundo" },
+          { "card": cards[1], "answer": "hundo" },
+        ],
+      }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["submitQuiz"],
+      json!([
+        { "card": cards[0], "correct": true, "expected": "hundo", "score": "FOUR" },
+        { "card": cards[1], "correct": false, "expected": "kato", "score": "ONE" },
+      ])
+    );
+
+    {
+      let conn = data.pool.get().unwrap();
+      let recorded = scores::table
+        .select(scores::card)
+        .order(scores::card.asc())
+        .load::<i32>(&conn)
+        .unwrap();
+      assert_eq!(recorded, &cards[..2]);
+    }
+
+    let req = query(
+      "query Quiz($deck: Int) {
+        quiz(deck: $deck, size: 6) {
+          card
+          choices
+        }
+      }",
+      json!({ "deck": null }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(result["errors"].is_array(), "Quizzes need a deck or a set");
+
+    let deck = {
+      let conn = data.pool.get().unwrap();
+      cards::table
+        .select(cards::deck)
+        .filter(cards::id.eq(cards[0]))
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+    let req = query(
+      "query Quiz($deck: Int) {
+        quiz(deck: $deck, size: 6) {
+          card
+          choices
+        }
+      }",
+      json!({ "deck": deck }),
+    );
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let order = result["data"]["quiz"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|question| {
+        assert_eq!(question["choices"].as_array().unwrap().len(), 4);
+        question["card"].as_i64().unwrap() as i32
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      &order[4..],
+      &[cards[1], cards[0]],
+      "Unscored cards come first, then the worst scored"
+    );
+  }
+}
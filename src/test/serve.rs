@@ -0,0 +1,184 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    config::Config,
+    db::schema::{backs, cards, decks, users},
+    graphql::authorization::UserRole,
+    media::{self, NewMedia},
+    service::{endpoints::serve_media, jwt::encode_jwt, AppState},
+    test::init,
+  };
+  use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::get,
+    App,
+  };
+  use diesel::prelude::*;
+  use std::{env, fs, sync::Arc};
+
+  #[actix_rt::test]
+  async fn test_serve_media() {
+    let root = env::temp_dir().join(format!("total_recall_serve_{}", std::process::id()));
+    let mut config = Config::default();
+    config.media.root = root.clone();
+    config.media.private = true;
+    let data = AppState {
+      config: Arc::new(config),
+      ..init()
+    };
+    let media_config = &data.config.media;
+    let secret = &data.config.auth.jwt_secret;
+
+    let (path, deck, tokens) = {
+      let conn = data.pool.get().unwrap();
+      let stored = media::store(
+        &conn,
+        media_config,
+        NewMedia {
+          bytes: b"ID3 woof".to_vec(),
+          mime: "audio/mpeg".to_owned(),
+          source: None,
+          licence: None,
+        },
+        0,
+      )
+      .unwrap();
+      let path = stored.path();
+
+      let mut tokens = vec![];
+      for username in &["owner", "stranger"] {
+        let user = diesel::insert_into(users::table)
+          .values((
+            users::username.eq(username),
+            users::password.eq(""),
+            users::created_at.eq(0),
+            users::updated_at.eq(0),
+          ))
+          .returning(users::id)
+          .get_result::<i32>(&conn)
+          .unwrap();
+        tokens.push(encode_jwt(secret, user, UserRole::USER, 1).unwrap());
+      }
+      let owner = users::table
+        .select(users::id)
+        .filter(users::username.eq("owner"))
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let deck = diesel::insert_into(decks::table)
+        .values((
+          decks::name.eq("Bestoj"),
+          decks::owner.eq(owner),
+          decks::language.eq(1),
+        ))
+        .returning(decks::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let back = diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq("hundo"),
+          backs::language.eq(1),
+          backs::audio.eq(media_config.url(&path)),
+          backs::audio_media.eq(stored.id),
+          backs::updated_at.eq(0),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      diesel::insert_into(cards::table)
+        .values((
+          cards::front.eq("dog"),
+          cards::back.eq(back),
+          cards::deck.eq(deck),
+          cards::created_at.eq(0),
+        ))
+        .execute(&conn)
+        .unwrap();
+      (path, deck, tokens)
+    };
+    fs::write(root.join("audio/legacy.mp3"), b"ID3 meow").unwrap();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/media/{path:.*}", get().to(serve_media)),
+    )
+    .await;
+    let uri = format!("/media/{}", path);
+
+    let req = TestRequest::get().uri(&uri).to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::NOT_FOUND,
+      "Media of private decks is hidden"
+    );
+
+    let req = TestRequest::get()
+      .uri(&format!("{}?token={}", uri, tokens[1]))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let req = TestRequest::get()
+      .uri(&format!("{}?token=nonsense", uri))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = TestRequest::get()
+      .uri(&format!("{}?token={}", uri, tokens[0]))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let hash = media::hash_of(&path).unwrap();
+    assert_eq!(
+      resp.headers().get("ETag").unwrap(),
+      &format!("\"{}\"", hash)
+    );
+    assert_eq!(
+      resp.headers().get("Cache-Control").unwrap(),
+      "private, max-age=31536000, immutable"
+    );
+    assert_eq!(test::read_body(resp).await, &b"ID3 woof"[..]);
+
+    {
+      let conn = data.pool.get().unwrap();
+      diesel::update(decks::table.filter(decks::id.eq(deck)))
+        .set(decks::published.eq(true))
+        .execute(&conn)
+        .unwrap();
+    }
+
+    let req = TestRequest::get()
+      .uri(&uri)
+      .header("Range", "bytes=4-6")
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::PARTIAL_CONTENT,
+      "Media of published decks is public"
+    );
+    assert_eq!(test::read_body(resp).await, &b"woo"[..]);
+
+    let req = TestRequest::get()
+      .uri(&uri)
+      .header("If-None-Match", format!("\"{}\"", hash))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+    for uri in &[
+      "/media/audio/legacy.mp3",
+      "/media/audio/../../Cargo.toml",
+      "/media/Cargo.toml",
+    ] {
+      let req = TestRequest::get().uri(uri).to_request();
+      let resp = test::call_service(&mut app, req).await;
+      assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}
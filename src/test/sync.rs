@@ -0,0 +1,595 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::{
+      changes::{self, ChangeKind, Entity},
+      schema::{
+        backs, cards, changes as changes_table, deck_members, decks, languages, scores, set_cards,
+        sets, users,
+      },
+      trash,
+    },
+    graphql::{
+      authorization::{record_access, DeckRole},
+      query::ScoreValue,
+    },
+    service::{
+      endpoints::{graphql, login, pull_changes, push_changes},
+      sync::{apply_batch, changes_since, OperationStatus},
+    },
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::{get, post},
+    App,
+  };
+  use diesel::{prelude::*, sql_types::SmallInt};
+  use serde_json::{self, json, Value};
+  use std::str::from_utf8;
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  #[actix_rt::test]
+  async fn test_sync() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql))
+        .route("/sync", get().to(pull_changes)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::get().uri("/sync").to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(resp.status(), 401, "Sync without a token should fail");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_response.data.CreateDeck.id;
+
+    let req = TestRequest::get()
+      .uri("/sync")
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to pull snapshot");
+
+    let body = test::read_body(resp).await;
+    let snapshot: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(snapshot["decks"][0]["id"], deck_id);
+    assert_eq!(snapshot["decks"][0]["name"], "test_deck");
+    let cursor = snapshot["cursor"].as_i64().unwrap();
+
+    let req = TestRequest::get()
+      .uri(&format!("/sync?cursor={}", cursor))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let unchanged: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(unchanged["cursor"], cursor);
+    assert_eq!(unchanged["decks"], json!([]));
+    assert_eq!(unchanged["deleted"], json!([]));
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation UpdateDeck($id: Int!, $name: String!) {
+          UpdateDeck(UpdateDeck: { name: $name, id: $id }) {
+            name
+          }
+        }",
+        "variables": {
+          "name": "changed_name",
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to update deck");
+
+    let req = TestRequest::get()
+      .uri(&format!("/sync?cursor={}", cursor))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let updated: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(updated["decks"][0]["name"], "changed_name");
+    let cursor = updated["cursor"].as_i64().unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation DeleteDeck($id: Int!) {
+          DeleteDeck(DeleteDeck: { id: $id }) {
+            count
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to delete deck");
+
+    let req = TestRequest::get()
+      .uri(&format!("/sync?cursor={}", cursor))
+      .header("Authorization", login_response.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let deleted: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(deleted["decks"], json!([]));
+    assert_eq!(
+      deleted["deleted"],
+      json!([{ "entity": "deck", "id": deck_id }])
+    );
+  }
+
+  #[actix_rt::test]
+  async fn test_push() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql))
+        .route("/sync", post().to(push_changes)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/sync")
+      .set_json(&json!({
+        "operations": [
+          { "op": "create_deck", "client_id": "deck", "name": "offline", "language": 1 },
+          { "op": "create_set", "client_id": "set", "deck": "deck", "name": "set", "cards": [] },
+          { "op": "update_set", "id": "set", "version": 1, "name": "first" },
+          { "op": "update_set", "id": "set", "version": 1, "name": "second" },
+        ],
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to push changes");
+
+    let body = test::read_body(resp).await;
+    let pushed: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let results = pushed["results"].as_array().unwrap();
+    let set_id = results[1]["id"].clone();
+    assert_eq!(results[0]["status"], "applied");
+    assert_eq!(results[1]["status"], "applied");
+    assert_eq!(
+      results[2],
+      json!({ "status": "applied", "id": set_id, "version": 2 })
+    );
+    assert_eq!(
+      results[3],
+      json!({ "status": "conflict", "id": set_id, "version": 2 })
+    );
+
+    let req = TestRequest::post()
+      .uri("/sync")
+      .set_json(&json!({
+        "policy": "last_writer_wins",
+        "operations": [
+          { "op": "update_set", "id": set_id, "version": 1, "name": "second" },
+        ],
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let pushed: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      pushed["results"],
+      json!([{ "status": "applied", "id": set_id, "version": 3 }])
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query GetSet($id: Int!) {
+          Set(primaryKey: { id: $id }) {
+            name
+          }
+        }",
+        "variables": {
+          "id": set_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"Set\":{\"name\":\"second\"}}}"
+    );
+
+    let disabled = {
+      let conn = data.pool.get().unwrap();
+      diesel::insert_into(languages::table)
+        .values((
+          languages::name.eq("Klingon"),
+          languages::abbreviation.eq("tlh"),
+          languages::tag.eq("tlh"),
+          languages::disabled.eq(true),
+        ))
+        .returning(languages::id)
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+    let req = TestRequest::post()
+      .uri("/sync")
+      .set_json(&json!({
+        "operations": [
+          { "op": "create_deck", "name": "offline", "language": disabled },
+          { "op": "create_deck", "client_id": "trashed", "name": "trashed", "language": 1 },
+          { "op": "delete_deck", "id": "trashed", "version": 1 },
+          { "op": "create_card", "deck": "trashed", "front": "dog", "back": "hundo" },
+        ],
+      }))
+      .header("Authorization", login_response.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let pushed: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let results = pushed["results"].as_array().unwrap();
+    assert_eq!(
+      results[0],
+      json!({ "status": "invalid", "id": null, "version": null })
+    );
+    assert_eq!(results[2]["status"], "applied");
+    assert_eq!(
+      results[3]["status"], "not_found",
+      "Cards can't be pushed into a deck in the trash"
+    );
+  }
+
+  #[actix_rt::test]
+  async fn test_sync_shared_deck() {
+    let data = init();
+    let conn = data.pool.get().unwrap();
+
+    let mut users = vec![];
+    for username in &["test_owner", "test_member"] {
+      users.push(
+        diesel::insert_into(users::table)
+          .values((
+            users::username.eq(username),
+            users::password.eq(""),
+            users::created_at.eq(0),
+            users::updated_at.eq(0),
+          ))
+          .returning(users::id)
+          .get_result::<i32>(&conn)
+          .unwrap(),
+      );
+    }
+    let (owner, member) = (users[0], users[1]);
+    let deck = diesel::insert_into(decks::table)
+      .values((
+        decks::name.eq("Bestoj"),
+        decks::owner.eq(owner),
+        decks::language.eq(1),
+      ))
+      .returning(decks::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    diesel::insert_into(deck_members::table)
+      .values((
+        deck_members::deck.eq(deck),
+        deck_members::user_id.eq(member),
+        deck_members::role.eq(DeckRole::EDITOR),
+        deck_members::accepted.eq(true),
+        deck_members::created_at.eq(0),
+      ))
+      .execute(&conn)
+      .unwrap();
+    let back = diesel::insert_into(backs::table)
+      .values((
+        backs::text.eq("hundo"),
+        backs::language.eq(1),
+        backs::updated_at.eq(0),
+      ))
+      .returning(backs::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let card = diesel::insert_into(cards::table)
+      .values((
+        cards::front.eq("dog"),
+        cards::back.eq(back),
+        cards::deck.eq(deck),
+        cards::created_at.eq(0),
+      ))
+      .returning(cards::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let other_deck = diesel::insert_into(decks::table)
+      .values((
+        decks::name.eq("Aliaj"),
+        decks::owner.eq(owner),
+        decks::language.eq(1),
+      ))
+      .returning(decks::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let other_card = diesel::insert_into(cards::table)
+      .values((
+        cards::front.eq("cat"),
+        cards::back.eq(back),
+        cards::deck.eq(other_deck),
+        cards::created_at.eq(0),
+      ))
+      .returning(cards::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let set = diesel::insert_into(sets::table)
+      .values((
+        sets::name.eq("Pets"),
+        sets::deck.eq(deck),
+        sets::owner.eq(member),
+        sets::created_at.eq(0),
+      ))
+      .returning(sets::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let set_card = diesel::insert_into(set_cards::table)
+      .values((set_cards::card_id.eq(card), set_cards::set_id.eq(set)))
+      .returning(set_cards::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    let score = diesel::insert_into(scores::table)
+      .values((
+        scores::card.eq(card),
+        scores::value.eq(ScoreValue::FIVE),
+        scores::created_at.eq(0),
+        scores::updated_at.eq(0),
+        scores::owner.eq(member),
+      ))
+      .returning(scores::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+
+    let snapshot = changes_since(&conn, member, 0).unwrap();
+    assert_eq!(snapshot.decks.len(), 1, "Decks that aren't shared stay out");
+    assert_eq!(snapshot.cards.len(), 1);
+    assert_eq!(snapshot.backs.len(), 1);
+    assert_eq!(snapshot.sets.len(), 1);
+    assert_eq!(snapshot.set_cards.len(), 1);
+    assert_eq!(snapshot.scores.len(), 1);
+
+    // Makes sure there is a cursor to pull from
+    changes::record(&conn, owner, Entity::User, &[owner], ChangeKind::Upsert, 0).unwrap();
+    let cursor = changes_since(&conn, owner, 0).unwrap().cursor;
+    trash::trash_card(&conn, owner, card, 1).unwrap();
+
+    let owners = changes_since(&conn, owner, cursor).unwrap();
+    let entities = owners
+      .deleted
+      .iter()
+      .map(|tombstone| tombstone.entity)
+      .collect::<Vec<_>>();
+    assert!(entities.contains(&Entity::Card));
+    assert!(
+      !entities.contains(&Entity::Score) && !entities.contains(&Entity::SetCard),
+      "Rows of members stay out of the owner's log"
+    );
+    let members = changes_since(&conn, member, cursor).unwrap();
+    let tombstones = members
+      .deleted
+      .iter()
+      .map(|tombstone| (tombstone.entity, tombstone.id))
+      .collect::<Vec<_>>();
+    assert!(tombstones.contains(&(Entity::Card, card)));
+    assert!(tombstones.contains(&(Entity::Score, score)));
+    assert!(tombstones.contains(&(Entity::SetCard, set_card)));
+
+    trash::restore_card(&conn, owner, card, 2).unwrap();
+    let owners = changes_since(&conn, owner, cursor).unwrap();
+    assert_eq!(owners.cards.len(), 1);
+    assert!(owners.scores.is_empty() && owners.set_cards.is_empty());
+    let members = changes_since(&conn, member, cursor).unwrap();
+    assert_eq!(members.scores.len(), 1);
+    assert_eq!(members.set_cards.len(), 1);
+    assert_eq!(
+      members.cards.len(),
+      1,
+      "Cards of decks shared with the member are part of their sync"
+    );
+    assert_eq!(members.backs.len(), 1);
+
+    let batch = serde_json::from_value(json!({
+      "operations": [
+        {
+          "op": "update_card",
+          "id": card,
+          "version": 1,
+          "front": "doggy",
+          "link": "https://example.org",
+        },
+        { "op": "update_set", "id": set, "version": 1, "name": "Animals" },
+        { "op": "delete_deck", "id": deck, "version": 1 },
+        { "op": "create_set", "deck": deck, "name": "Mixed", "cards": [card, other_card] },
+      ],
+    }))
+    .unwrap();
+    let results = apply_batch(&conn, &data.config.media, member, batch).unwrap();
+    let statuses = results
+      .iter()
+      .map(|result| result.status)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      statuses,
+      vec![
+        OperationStatus::Applied,
+        OperationStatus::Applied,
+        OperationStatus::Unauthorized,
+        OperationStatus::Invalid,
+      ],
+      "Editors may push edits to cards of shared decks, but not delete the deck"
+    );
+    let owners = changes_since(&conn, owner, cursor).unwrap();
+    assert_eq!(owners.cards[0].front, "doggy");
+    assert_eq!(owners.cards[0].link.as_deref(), Some("https://example.org"));
+
+    let cursor = members.cursor;
+    diesel::delete(deck_members::table.filter(deck_members::user_id.eq(member)))
+      .execute(&conn)
+      .unwrap();
+    record_access(&conn, member, &[deck], 3).unwrap();
+    let members = changes_since(&conn, member, cursor).unwrap();
+    let tombstones = members
+      .deleted
+      .iter()
+      .map(|tombstone| (tombstone.entity, tombstone.id))
+      .collect::<Vec<_>>();
+    assert!(
+      tombstones.contains(&(Entity::Deck, deck)) && tombstones.contains(&(Entity::Card, card)),
+      "Members who lose access to a deck are told to drop it"
+    );
+    assert!(changes_since(&conn, member, 0).unwrap().decks.is_empty());
+
+    let latest = || {
+      changes_table::table
+        .select(diesel::dsl::max(changes_table::id))
+        .get_result::<Option<i64>>(&conn)
+        .unwrap()
+    };
+    let before = latest();
+    let held = changes::deferred(&conn, || {
+      changes::record(&conn, owner, Entity::Deck, &[deck], ChangeKind::Upsert, 4)?;
+      Ok::<_, diesel::result::Error>(latest())
+    })
+    .unwrap();
+    assert_eq!(held, before, "Deferred changes are held back");
+    assert!(latest() > before, "Deferred changes are written at the end");
+    let before = latest();
+    let failed = changes::deferred(&conn, || {
+      changes::record(&conn, owner, Entity::Deck, &[deck], ChangeKind::Upsert, 5)?;
+      Err::<(), _>(diesel::result::Error::RollbackTransaction)
+    });
+    assert!(failed.is_err());
+    changes::record(&conn, owner, Entity::Card, &[card], ChangeKind::Upsert, 6).unwrap();
+    let last = changes_table::table
+      .select((changes_table::id, changes_table::entity))
+      .order(changes_table::id.desc())
+      .first::<(i64, Entity)>(&conn)
+      .unwrap();
+    assert_eq!(
+      last.1,
+      Entity::Card,
+      "Changes of a failed deferred transaction are dropped"
+    );
+    assert!(Some(last.0) > before);
+
+    let unknown = diesel::select(99i16.into_sql::<SmallInt>()).get_result::<Entity>(&conn);
+    assert!(unknown.is_err(), "Unknown entities are errors, not panics");
+  }
+}
@@ -0,0 +1,270 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    db::trash,
+    service::endpoints::{graphql, login},
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use serde_json::{self, json, Value};
+  use std::{str::from_utf8, time::Duration};
+
+  use crate::test::{CreateDeckResponse, CreateSetResponse, LoginResponse};
+
+  #[actix_rt::test]
+  async fn test_trash() {
+    let data = init();
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Register($username: String!, $password: String!) {
+          CreateUser(NewUser: { username: $username, password: $password }) {
+            username
+          }
+        }",
+        "variables": {
+          "username": "test_user",
+          "password": "test",
+        },
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Login failed");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_deck_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateSet($name: String!, $deck: Int!, $cards: [Int!]!) {
+          CreateSet(NewSet: { name: $name, deck: $deck, cards: $cards }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_set",
+          "deck": deck_id,
+          "cards": [],
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create set");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_set_response: CreateSetResponse = serde_json::from_str(&body).unwrap();
+    let set_id = create_set_response.data.CreateSet.id;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation DeleteSet($id: Int!) {
+          DeleteSet(DeleteSet: { id: $id }) {
+            count
+          }
+        }",
+        "variables": {
+          "id": set_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"DeleteSet\":{\"count\":1}}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          Sets {
+            id
+          }
+          trash {
+            kind
+            id
+            name
+          }
+        }",
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let trashed: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(trashed["data"]["Sets"], json!([]));
+    assert_eq!(
+      trashed["data"]["trash"],
+      json!([{ "kind": "SET", "id": set_id, "name": "test_set" }])
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Restore($id: Int!) {
+          restore(kind: SET, id: $id)
+        }",
+        "variables": {
+          "id": set_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"restore\":true}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation DeleteDeck($id: Int!, $set: Int!) {
+          DeleteSet(DeleteSet: { id: $set }) {
+            count
+          }
+          DeleteDeck(DeleteDeck: { id: $id }) {
+            count
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+          "set": set_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to delete deck");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          Decks {
+            id
+          }
+          Sets {
+            id
+          }
+          trash {
+            kind
+            id
+          }
+        }",
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      format!(
+        "{{\"data\":{{\"Decks\":[],\"Sets\":[],\"trash\":[{{\"kind\":\"DECK\",\"id\":{}}}]}}}}",
+        deck_id
+      )
+    );
+
+    // The set can't come back into the trashed deck
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Restore($id: Int!) {
+          restore(kind: SET, id: $id)
+        }",
+        "variables": {
+          "id": set_id,
+        },
+      }))
+      .header("Authorization", login_response.token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let restored: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(restored["errors"].is_array());
+
+    let conn = data.pool.get().unwrap();
+    assert_eq!(trash::restore_set(&conn, set_id, 0).unwrap(), 0);
+    assert_eq!(trash::purge(&conn, Duration::from_secs(0)).unwrap(), 2);
+    drop(conn);
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query {
+          trash {
+            id
+          }
+        }",
+      }))
+      .header("Authorization", login_response.token)
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"trash\":[]}}"
+    );
+  }
+}
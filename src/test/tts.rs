@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    config::{TtsConfig, TtsProvider},
+    graphql::mutations::utilities::get_audio,
+    TRCError,
+  };
+
+  fn local(script: &str) -> TtsConfig {
+    let mut config = TtsConfig {
+      provider: TtsProvider::Local,
+      command: vec![
+        "sh".to_owned(),
+        "-c".to_owned(),
+        script.to_owned(),
+        "sh".to_owned(),
+        "{voice}".to_owned(),
+        "{output}".to_owned(),
+      ],
+      timeout_secs: 1,
+      ..TtsConfig::default()
+    };
+    config.voices.insert("af".to_owned(), "af-voice".to_owned());
+    config
+  }
+
+  #[test]
+  fn test_tts() {
+    // Writes a WAV header followed by the voice and the text it was given
+    let config = local("printf 'RIFF\\0\\0\\0\\0WAVE%s:' \"$1\" > \"$2\"; cat >> \"$2\"");
+    let audio = get_audio(&config, "af", None, "hallo").unwrap();
+    assert_eq!(audio.mime, "audio/wav");
+    assert_eq!(audio.bytes, b"RIFF\0\0\0\0WAVEaf-voice:hallo");
+
+    let audio = get_audio(&config, "eo", None, "saluton").unwrap();
+    assert!(
+      audio.bytes.ends_with(b"WAVEeo:saluton"),
+      "Languages without a voice use their abbreviation"
+    );
+
+    let audio = get_audio(&config, "af", Some("af-custom"), "hallo").unwrap();
+    assert!(
+      audio.bytes.ends_with(b"WAVEaf-custom:hallo"),
+      "The voice set for a language comes first"
+    );
+
+    let failing = local("exit 3");
+    assert!(matches!(
+      get_audio(&failing, "af", None, "hallo"),
+      Err(TRCError::Media(_))
+    ));
+
+    let silent = local("echo 'not audio' > \"$2\"");
+    assert!(matches!(
+      get_audio(&silent, "af", None, "hallo"),
+      Err(TRCError::Media(_))
+    ));
+
+    let slow = local("sleep 5");
+    assert!(matches!(
+      get_audio(&slow, "af", None, "hallo"),
+      Err(TRCError::Media(_))
+    ));
+  }
+}
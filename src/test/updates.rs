@@ -0,0 +1,491 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    config::{Config, TtsProvider},
+    db::{
+      audit::Operation,
+      changes::Entity,
+      schema::{audit_log, backs, cards},
+    },
+    service::{
+      endpoints::{graphql, login},
+      AppState,
+    },
+    test::init,
+  };
+  use actix_web::{
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use serde_json::{self, json, Value};
+  use std::{env, fs, str::from_utf8, sync::Arc};
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  // Creating cards through the API fetches audio, so these go in directly
+  fn insert_card(data: &AppState, deck: i32, front: &str, back: &str) -> i32 {
+    let conn = data.pool.get().unwrap();
+    let back = diesel::insert_into(backs::table)
+      .values((
+        backs::text.eq(back),
+        backs::language.eq(1),
+        backs::updated_at.eq(0),
+      ))
+      .returning(backs::id)
+      .get_result::<i32>(&conn)
+      .unwrap();
+    diesel::insert_into(cards::table)
+      .values((
+        cards::front.eq(front),
+        cards::back.eq(back),
+        cards::deck.eq(deck),
+        cards::created_at.eq(0),
+        cards::updated_at.eq(0),
+      ))
+      .returning(cards::id)
+      .get_result::<i32>(&conn)
+      .unwrap()
+  }
+
+  #[actix_rt::test]
+  async fn test_deck_updates() {
+    // Editing a back records it again, so speech comes from a local command
+    let root = env::temp_dir().join(format!("total_recall_updates_{}", std::process::id()));
+    let mut config = Config::default();
+    config.media.root = root.clone();
+    config.media.tts.provider = TtsProvider::Local;
+    config.media.tts.command = vec![
+      "sh".to_owned(),
+      "-c".to_owned(),
+      "printf 'RIFF\\0\\0\\0\\0WAVE' > \"$1\"; cat >> \"$1\"".to_owned(),
+      "sh".to_owned(),
+      "{output}".to_owned(),
+    ];
+    let data = AppState {
+      config: Arc::new(config),
+      ..init()
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql)),
+    )
+    .await;
+
+    let mut tokens = vec![];
+    for username in &["test_user", "test_reader"] {
+      let req = TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({
+          "query": "mutation Register($username: String!, $password: String!) {
+            CreateUser(NewUser: { username: $username, password: $password }) {
+              username
+            }
+          }",
+          "variables": {
+            "username": username,
+            "password": "test",
+          },
+        }))
+        .to_request();
+      let resp = test::call_service(&mut app, req).await;
+
+      assert!(resp.status().is_success(), "Failed to create user");
+
+      let req = TestRequest::post()
+        .uri("/login")
+        .set_json(&json!({
+          "username": username,
+          "password": "test",
+        }))
+        .to_request();
+      let resp = test::call_service(&mut app, req).await;
+
+      assert!(resp.status().is_success(), "Login failed");
+
+      let body = test::read_body(resp).await;
+      let body = from_utf8(&body).unwrap();
+      let login_response: LoginResponse = serde_json::from_str(&body).unwrap();
+      tokens.push(login_response.token);
+    }
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation CreateDeck($name: String!, $language: Int!) {
+          CreateDeck(NewDeck: { name: $name, language: $language }) {
+            id
+          }
+        }",
+        "variables": {
+          "name": "test_deck",
+          "language": 1,
+        },
+      }))
+      .header("Authorization", tokens[0].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create deck");
+
+    let body = test::read_body(resp).await;
+    let body = from_utf8(&body).unwrap();
+    let create_deck_response: CreateDeckResponse = serde_json::from_str(&body).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    let hello = insert_card(&data, deck_id, "hello", "hallo");
+    let goodbye = insert_card(&data, deck_id, "goodbye", "totsiens");
+    let thanks = insert_card(&data, deck_id, "thanks", "dankie");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Publish($id: Int!) {
+          UpdateDeck(UpdateDeck: { id: $id, name: \"test_deck\", published: true }) {
+            published
+          }
+        }",
+        "variables": {
+          "id": deck_id,
+        },
+      }))
+      .header("Authorization", tokens[0].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to publish deck");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Clone($deck: Int!) {
+          cloneDeck(deck: $deck)
+        }",
+        "variables": {
+          "deck": deck_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let cloned: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let clone_id = cloned["data"]["cloneDeck"].as_i64().unwrap() as i32;
+
+    let local_thanks = {
+      let conn = data.pool.get().unwrap();
+      cards::table
+        .select(cards::id)
+        .filter(cards::origin.eq(thanks))
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+
+    // The author fixes one card, adds another and drops a third. The learner
+    // has meanwhile reworded the card the author also changed.
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Edit($hello: Int!, $thanks: Int!, $goodbye: Int!) {
+          a: UpdateCard(UpdateCard: { id: $hello, back: \"hallo!\" }) { id }
+          b: UpdateCard(UpdateCard: { id: $thanks, back: \"baie dankie\" }) { id }
+          DeleteCard(DeleteCard: { id: $goodbye }) { count }
+        }",
+        "variables": {
+          "hello": hello,
+          "thanks": thanks,
+          "goodbye": goodbye,
+        },
+      }))
+      .header("Authorization", tokens[0].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to edit source deck");
+    let please = insert_card(&data, deck_id, "please", "asseblief");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Edit($id: Int!) {
+          UpdateCard(UpdateCard: { id: $id, front: \"thank you\" }) { id }
+        }",
+        "variables": {
+          "id": local_thanks,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to edit clone");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Updates($deck: Int!) {
+          deckUpdates(deck: $deck) {
+            kind
+            front
+            back
+            localFront
+            localBack
+            locallyEdited: locallyEdited
+          }
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["deckUpdates"],
+      json!([
+        {
+          "kind": "CHANGED",
+          "front": "hello",
+          "back": "hallo!",
+          "localFront": "hello",
+          "localBack": "hallo",
+          "locallyEdited": false,
+        },
+        {
+          "kind": "REMOVED",
+          "front": null,
+          "back": null,
+          "localFront": "goodbye",
+          "localBack": "totsiens",
+          "locallyEdited": false,
+        },
+        {
+          "kind": "CHANGED",
+          "front": "thanks",
+          "back": "baie dankie",
+          "localFront": "thank you",
+          "localBack": "dankie",
+          "locallyEdited": true,
+        },
+        {
+          "kind": "ADDED",
+          "front": "please",
+          "back": "asseblief",
+          "localFront": null,
+          "localBack": null,
+          "locallyEdited": false,
+        },
+      ])
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Apply($deck: Int!) {
+          applyDeckUpdates(deck: $deck)
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"applyDeckUpdates\":3}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Clone($deck: Int!) {
+          Cards(filter: { deck: { id: { eq: $deck } } }, order: [{ column: id }]) {
+            front
+            back {
+              text
+            }
+          }
+          deckUpdates(deck: $deck) {
+            kind
+            locallyEdited
+          }
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["Cards"],
+      json!([
+        { "front": "hello", "back": { "text": "hallo!" } },
+        { "front": "thank you", "back": { "text": "dankie" } },
+        { "front": "please", "back": { "text": "asseblief" } },
+      ])
+    );
+    assert_eq!(
+      result["data"]["deckUpdates"],
+      json!([{ "kind": "CHANGED", "locallyEdited": true }])
+    );
+
+    // The author brings back the dropped card and fixes the added one, which
+    // the learner has trashed in the meantime. Only the copy the update
+    // trashed comes back.
+    let local_please = {
+      let conn = data.pool.get().unwrap();
+      cards::table
+        .select(cards::id)
+        .filter(cards::origin.eq(please))
+        .get_result::<i32>(&conn)
+        .unwrap()
+    };
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Trash($id: Int!) {
+          DeleteCard(DeleteCard: { id: $id }) { count }
+        }",
+        "variables": {
+          "id": local_please,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to trash the copy");
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Edit($goodbye: Int!, $please: Int!) {
+          restore(kind: CARD, id: $goodbye)
+          a: UpdateCard(UpdateCard: { id: $goodbye, back: \"tot siens\" }) { id }
+          b: UpdateCard(UpdateCard: { id: $please, back: \"asseblief!\" }) { id }
+        }",
+        "variables": {
+          "goodbye": goodbye,
+          "please": please,
+        },
+      }))
+      .header("Authorization", tokens[0].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(result["data"]["restore"], true, "{}", result);
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Updates($deck: Int!) {
+          deckUpdates(deck: $deck) {
+            kind
+            back
+            localBack
+          }
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["deckUpdates"],
+      json!([
+        { "kind": "RESTORED", "back": "tot siens", "localBack": "totsiens" },
+        { "kind": "CHANGED", "back": "baie dankie", "localBack": "dankie" },
+      ])
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation Apply($deck: Int!) {
+          applyDeckUpdates(deck: $deck)
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert_eq!(
+      from_utf8(&test::read_body(resp).await).unwrap(),
+      "{\"data\":{\"applyDeckUpdates\":1}}"
+    );
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "query Clone($deck: Int!) {
+          Cards(filter: { deck: { id: { eq: $deck } } }, order: [{ column: id }]) {
+            front
+          }
+        }",
+        "variables": {
+          "deck": clone_id,
+        },
+      }))
+      .header("Authorization", tokens[1].clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    let body = test::read_body(resp).await;
+    let result: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(
+      result["data"]["Cards"],
+      json!([{ "front": "hello" }, { "front": "goodbye" }, { "front": "thank you" }])
+    );
+
+    // The fixed back was recorded anew and the clone shares the recording
+    let conn = data.pool.get().unwrap();
+    let audio = backs::table
+      .select((backs::audio, backs::audio_media))
+      .filter(backs::text.eq("hallo!"))
+      .load::<(Option<String>, Option<i32>)>(&conn)
+      .unwrap();
+    assert_eq!(audio.len(), 2);
+    assert!(audio[0].1.is_some());
+    assert_eq!(audio[0], audio[1]);
+    let file = data
+      .config
+      .media
+      .path(audio[0].0.as_ref().unwrap())
+      .unwrap();
+    assert!(file.exists());
+    let edit = audit_log::table
+      .select((audit_log::before, audit_log::after))
+      .filter(audit_log::entity.eq(Entity::Card))
+      .filter(audit_log::entity_id.eq(hello))
+      .filter(audit_log::operation.eq(Operation::Update))
+      .get_result::<(Option<String>, Option<String>)>(&conn)
+      .unwrap();
+    assert!(edit.0.unwrap().contains("\"back_text\":\"hallo\""));
+    assert!(edit.1.unwrap().contains("\"back_text\":\"hallo!\""));
+    fs::remove_dir_all(root).unwrap();
+  }
+}
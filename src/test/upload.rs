@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod tests {
+  use crate::{
+    config::Config,
+    db::schema::{backs, cards},
+    service::{
+      endpoints::{graphql, login, upload_media},
+      AppState,
+    },
+    test::init,
+  };
+  use actix_web::{
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::post,
+    App,
+  };
+  use diesel::prelude::*;
+  use image::{DynamicImage, GenericImageView, ImageFormat};
+  use serde_json::{self, json, Value};
+  use std::{env, fs, io::Cursor, str::from_utf8, sync::Arc};
+
+  use crate::test::{CreateDeckResponse, LoginResponse};
+
+  const BOUNDARY: &str = "total-recall-boundary";
+
+  fn form(file: &[u8], content_type: &str) -> Vec<u8> {
+    let mut body = format!(
+      "--{b}\r\nContent-Disposition: form-data; name=\"licence\"\r\n\r\nCC-BY 4.0\r\n\
+       --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"dog\"\r\n\
+       Content-Type: {}\r\n\r\n",
+      content_type,
+      b = BOUNDARY
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    body
+  }
+
+  fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut encoded = Cursor::new(vec![]);
+    DynamicImage::new_rgb8(width, height)
+      .write_to(&mut encoded, ImageFormat::Png)
+      .unwrap();
+    encoded.into_inner()
+  }
+
+  #[actix_rt::test]
+  async fn test_upload() {
+    let root = env::temp_dir().join(format!("total_recall_upload_{}", std::process::id()));
+    let mut config = Config::default();
+    config.media.root = root.clone();
+    config.media.max_upload_bytes = 16 * 1024;
+    let data = AppState {
+      config: Arc::new(config),
+      ..init()
+    };
+
+    let mut app = test::init_service(
+      App::new()
+        .data(data.clone())
+        .route("/login", post().to(login))
+        .route("/graphql", post().to(graphql))
+        .route("/upload", post().to(upload_media)),
+    )
+    .await;
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation {
+          CreateUser(NewUser: { username: \"test_user\", password: \"test\" }) {
+            username
+          }
+        }",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success(), "Failed to create user");
+
+    let req = TestRequest::post()
+      .uri("/login")
+      .set_json(&json!({
+        "username": "test_user",
+        "password": "test",
+      }))
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let login_response: LoginResponse = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let token = login_response.token;
+
+    let upload = |file: &[u8], content_type: &str, token: Option<&str>| {
+      let req = TestRequest::post()
+        .uri("/upload")
+        .header(
+          "Content-Type",
+          format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .set_payload(form(file, content_type));
+      match token {
+        Some(token) => req.header("Authorization", token.to_owned()),
+        None => req,
+      }
+      .to_request()
+    };
+    let png = png(1000, 500);
+
+    let resp = test::call_service(&mut app, upload(&png, "image/png", None)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = test::call_service(&mut app, upload(b"plain text", "image/png", Some(&token))).await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::BAD_REQUEST,
+      "Unknown types are rejected"
+    );
+
+    let resp = test::call_service(&mut app, upload(&png, "audio/mpeg", Some(&token))).await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::BAD_REQUEST,
+      "Mislabelled files are rejected"
+    );
+
+    let resp = test::call_service(
+      &mut app,
+      upload(&[0xFF; 16 * 1024 + 1], "audio/mpeg", Some(&token)),
+    )
+    .await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::BAD_REQUEST,
+      "Large files are rejected"
+    );
+
+    let resp = test::call_service(
+      &mut app,
+      upload(b"\x89PNG\r\n\x1A\n a small dog", "image/png", Some(&token)),
+    )
+    .await;
+    assert_eq!(
+      resp.status(),
+      StatusCode::BAD_REQUEST,
+      "Broken images are rejected"
+    );
+
+    let resp = test::call_service(&mut app, upload(&png, "image/png", Some(&token))).await;
+    assert!(resp.status().is_success(), "Failed to upload");
+    let body = test::read_body(resp).await;
+    let uploaded: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let hash = uploaded["hash"].as_str().unwrap().to_owned();
+    let url = uploaded["url"].as_str().unwrap().to_owned();
+    let thumbnail = uploaded["thumbnail"].as_str().unwrap().to_owned();
+    assert_eq!(uploaded["mime"], "image/webp");
+    assert!(url.ends_with(".webp"));
+    let file = |url: &str| data.config.media.path(url).unwrap();
+    let converted = image::open(file(&url)).unwrap();
+    assert_eq!(converted.dimensions(), (1000, 500));
+    let small = image::open(file(&thumbnail)).unwrap();
+    assert_eq!(small.dimensions(), (160, 80));
+    let card_size = image::open(file(uploaded["card"].as_str().unwrap())).unwrap();
+    assert_eq!(card_size.dimensions(), (480, 240));
+
+    let req = TestRequest::post()
+      .uri("/graphql")
+      .set_json(&json!({
+        "query": "mutation {
+          CreateDeck(NewDeck: { name: \"test_deck\", language: 1 }) {
+            id
+          }
+        }",
+      }))
+      .header("Authorization", token.clone())
+      .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    let body = test::read_body(resp).await;
+    let create_deck_response: CreateDeckResponse =
+      serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    let deck_id = create_deck_response.data.CreateDeck.id;
+
+    // Creating cards through the API fetches media, so this goes in directly
+    let (back, card) = {
+      let conn = data.pool.get().unwrap();
+      let back = diesel::insert_into(backs::table)
+        .values((
+          backs::text.eq("hundo"),
+          backs::language.eq(1),
+          backs::updated_at.eq(0),
+        ))
+        .returning(backs::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      let card = diesel::insert_into(cards::table)
+        .values((
+          cards::front.eq("dog"),
+          cards::back.eq(back),
+          cards::deck.eq(deck_id),
+          cards::created_at.eq(0),
+        ))
+        .returning(cards::id)
+        .get_result::<i32>(&conn)
+        .unwrap();
+      (back, card)
+    };
+
+    let set_back_media = |kind: &str, hash: &str| {
+      TestRequest::post()
+        .uri("/graphql")
+        .set_json(&json!({
+          "query": "mutation SetBackMedia($card: Int!, $kind: MediaKind!, $hash: String) {
+            setBackMedia(card: $card, kind: $kind, hash: $hash)
+          }",
+          "variables": {
+            "card": card,
+            "kind": kind,
+            "hash": hash,
+          },
+        }))
+        .header("Authorization", token.clone())
+        .to_request()
+    };
+
+    let resp = test::call_service(&mut app, set_back_media("AUDIO", &hash)).await;
+    let body = test::read_body(resp).await;
+    let response: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert!(
+      response["errors"].is_array(),
+      "Images can't be used as audio"
+    );
+
+    let resp = test::call_service(&mut app, set_back_media("IMAGE", &hash)).await;
+    let body = test::read_body(resp).await;
+    let response: Value = serde_json::from_str(from_utf8(&body).unwrap()).unwrap();
+    assert_eq!(response["data"]["setBackMedia"], url.as_str());
+
+    let conn = data.pool.get().unwrap();
+    let (image, image_media, image_thumbnail) = backs::table
+      .select((backs::image, backs::image_media, backs::image_thumbnail))
+      .filter(backs::id.eq(back))
+      .get_result::<(Option<String>, Option<i32>, Option<String>)>(&conn)
+      .unwrap();
+    assert_eq!(image, Some(url));
+    assert!(image_media.is_some());
+    assert_eq!(image_thumbnail, Some(thumbnail));
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}